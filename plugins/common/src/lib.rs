@@ -1,7 +1,7 @@
 /// ABI version implemented by this SDK version.
 /// Must match `PLUGIN_ABI_VERSION` in the treetags host (`src/plugin/mod.rs`).
 /// Bump this (and the host constant) whenever the WIT interface changes.
-pub const ABI_VERSION: u32 = 3;
+pub const ABI_VERSION: u32 = 4;
 
 pub mod tag_config;
 pub use tag_config::TagKindConfig;
@@ -18,3 +18,8 @@ pub use cursor::{child_ident, has_child, line_of, node_text};
 pub mod tree_walker;
 #[cfg(feature = "tree-walker")]
 pub use tree_walker::{walk_tree, WalkContext};
+
+#[cfg(feature = "tags-query")]
+pub mod tags_query;
+#[cfg(feature = "tags-query")]
+pub use tags_query::{Definition, QueryTag, TagsQuery};
@@ -0,0 +1,387 @@
+//! Tag generation from a tree-sitter tags query, for plugins that do not need
+//! a hand-written tree walker.
+//!
+//! The query follows the `tags.scm` conventions: each pattern captures the
+//! whole definition as `@definition.<type>` and its identifier as `@name`.
+//! Every `<type>` is mapped to a kind letter through a [`Definition`] table.
+//! Scopes come from nesting: a tag inside the range of a definition that opens
+//! a scope gets a `<scope>:<Outer.Inner>` field, just like with [`ScopeStack`].
+//!
+//! Extra ctags fields can be attached per pattern, either from a capture
+//! (`@field.signature` adds `signature:<captured text>`) or as a constant with
+//! `(#set! field.file "")`.
+//!
+//! [`ScopeStack`]: crate::ScopeStack
+
+use tree_sitter::{Language, Parser, Query, QueryCursor, StreamingIterator, Tree};
+
+use crate::{ScopeKey, ScopeStack, TagKindConfig};
+
+const DEFINITION_PREFIX: &str = "definition.";
+const FIELD_PREFIX: &str = "field.";
+
+/// How the captures of one `@definition.<type>` are tagged.
+pub struct Definition {
+    /// The `<type>` part of the capture name, e.g. `"class"`. Also accepted as
+    /// the long name of `kind` in the kinds string.
+    pub capture: &'static str,
+    /// Kind letter written to the tags file, e.g. `"c"`.
+    pub kind: &'static str,
+    /// Scope field key for tags nested inside this definition, e.g. `"class"`.
+    /// `None` when the definition does not open a scope.
+    pub scope: Option<&'static str>,
+    /// Whether the kind is generated when the kinds string does not mention it.
+    pub default: bool,
+}
+
+/// A tag produced by [`TagsQuery`], with the same fields as the WIT `tag`
+/// record so a plugin can convert it field by field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryTag {
+    pub name: String,
+    /// 1-based line of the `@name` capture.
+    pub line: u32,
+    pub kind: String,
+    /// 1-based last line of the `@definition.*` capture.
+    pub end_line: Option<u32>,
+    /// Sorted ctags extension fields.
+    pub extension_fields: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy)]
+struct FieldKey(&'static str);
+
+impl ScopeKey for FieldKey {
+    fn key(self) -> &'static str {
+        self.0
+    }
+}
+
+/// What a capture of the query stands for.
+#[derive(Clone, Copy)]
+enum Capture {
+    Definition(usize),
+    Name,
+    Field(usize),
+    Other,
+}
+
+/// One definition found by the query, before scopes are assigned.
+struct Match {
+    start_byte: usize,
+    end_byte: usize,
+    definition: usize,
+    name: String,
+    line: u32,
+    end_line: u32,
+    fields: Vec<(String, String)>,
+}
+
+/// A compiled tags query plus its kind table.
+pub struct TagsQuery {
+    language: Language,
+    query: Query,
+    definitions: &'static [Definition],
+    captures: Vec<Capture>,
+    /// Keys of the `@field.*` captures, indexed by `Capture::Field`.
+    field_keys: Vec<String>,
+}
+
+impl TagsQuery {
+    /// Compiles `source` for `language`. Fails when the query does not compile
+    /// or captures a `@definition.<type>` missing from `definitions`.
+    pub fn new(
+        language: Language,
+        source: &str,
+        definitions: &'static [Definition],
+    ) -> Result<Self, String> {
+        let query = Query::new(&language, source).map_err(|e| format!("tags query: {e}"))?;
+        let mut field_keys = Vec::new();
+        let mut captures = Vec::with_capacity(query.capture_names().len());
+        for name in query.capture_names() {
+            let capture = if *name == "name" {
+                Capture::Name
+            } else if let Some(ty) = name.strip_prefix(DEFINITION_PREFIX) {
+                let index = definitions
+                    .iter()
+                    .position(|d| d.capture == ty)
+                    .ok_or_else(|| format!("tags query: no kind for @{name}"))?;
+                Capture::Definition(index)
+            } else if let Some(key) = name.strip_prefix(FIELD_PREFIX) {
+                field_keys.push(key.to_string());
+                Capture::Field(field_keys.len() - 1)
+            } else {
+                Capture::Other
+            };
+            captures.push(capture);
+        }
+        Ok(Self {
+            language,
+            query,
+            definitions,
+            captures,
+            field_keys,
+        })
+    }
+
+    /// Parses the `kinds` string of a request against the definition table.
+    /// Both the kind letters and the capture types are accepted.
+    pub fn kind_config(&self, kinds: &str) -> TagKindConfig {
+        let mut letters: Vec<&Definition> = Vec::new();
+        for def in self.definitions {
+            if !letters.iter().any(|d| d.kind == def.kind) {
+                letters.push(def);
+            }
+        }
+        let aliases: Vec<Vec<&str>> = letters
+            .iter()
+            .map(|letter| {
+                let mut names = vec![letter.kind];
+                names.extend(
+                    self.definitions
+                        .iter()
+                        .filter(|d| d.kind == letter.kind)
+                        .map(|d| d.capture),
+                );
+                names
+            })
+            .collect();
+        let mapping = |default: bool| -> Vec<(&[&str], &str)> {
+            letters
+                .iter()
+                .zip(&aliases)
+                .filter(|(d, _)| d.default == default)
+                .map(|(d, a)| (&a[..], d.kind))
+                .collect()
+        };
+        TagKindConfig::parse(kinds, &mapping(true), &mapping(false))
+    }
+
+    /// Parses `source` and returns the tags of every enabled kind.
+    pub fn generate(&self, source: &[u8], kinds: &TagKindConfig) -> Result<Vec<QueryTag>, String> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&self.language)
+            .map_err(|e| format!("set_language: {e}"))?;
+        let tree = parser
+            .parse(source, None)
+            .ok_or_else(|| "parse failed".to_string())?;
+        Ok(self.tags(&tree, source, kinds))
+    }
+
+    /// The tags of an already parsed `tree`, in source order.
+    pub fn tags(&self, tree: &Tree, source: &[u8], kinds: &TagKindConfig) -> Vec<QueryTag> {
+        let mut found = self.matches(tree, source);
+        // Outer definitions first, so scopes are open before their members.
+        found.sort_by_key(|m| (m.start_byte, std::cmp::Reverse(m.end_byte), m.definition));
+        found.dedup_by_key(|m| (m.start_byte, m.end_byte, m.definition));
+
+        let mut scopes = ScopeStack::new();
+        let mut open_until: Vec<usize> = Vec::new();
+        let mut tags = Vec::new();
+        for m in found {
+            while open_until.last().is_some_and(|&end| m.start_byte >= end) {
+                open_until.pop();
+                scopes.pop();
+            }
+            let def = &self.definitions[m.definition];
+            if kinds.is_enabled(def.kind) {
+                let mut extension_fields = m.fields;
+                if let Some((key, value)) = scopes.current_field() {
+                    extension_fields.push((key.to_string(), value.to_string()));
+                }
+                extension_fields.sort();
+                tags.push(QueryTag {
+                    name: m.name.clone(),
+                    line: m.line,
+                    kind: def.kind.to_string(),
+                    end_line: Some(m.end_line),
+                    extension_fields,
+                });
+            }
+            if let Some(key) = def.scope {
+                scopes.push(FieldKey(key), &m.name);
+                open_until.push(m.end_byte);
+            }
+        }
+        tags
+    }
+
+    fn matches(&self, tree: &Tree, source: &[u8]) -> Vec<Match> {
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(&self.query, tree.root_node(), source);
+        let mut found = Vec::new();
+        while let Some(m) = matches.next() {
+            let mut definition = None;
+            let mut name = None;
+            let mut fields = Vec::new();
+            for capture in m.captures {
+                let node = capture.node;
+                match self.captures[capture.index as usize] {
+                    Capture::Definition(index) => definition = Some((index, node)),
+                    Capture::Name => name = Some(node),
+                    Capture::Field(index) => fields.push((
+                        self.field_keys[index].clone(),
+                        node.utf8_text(source).unwrap_or("").to_string(),
+                    )),
+                    Capture::Other => {}
+                }
+            }
+            let (Some((definition, node)), Some(name)) = (definition, name) else {
+                continue;
+            };
+            for setting in self.query.property_settings(m.pattern_index) {
+                if let Some(key) = setting.key.strip_prefix(FIELD_PREFIX) {
+                    let value = setting.value.as_deref().unwrap_or("");
+                    fields.push((key.to_string(), value.to_string()));
+                }
+            }
+            found.push(Match {
+                start_byte: node.start_byte(),
+                end_byte: node.end_byte(),
+                definition,
+                name: name.utf8_text(source).unwrap_or("").to_string(),
+                line: name.start_position().row as u32 + 1,
+                end_line: node.end_position().row as u32 + 1,
+                fields,
+            });
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFINITIONS: &[Definition] = &[
+        Definition {
+            capture: "class",
+            kind: "c",
+            scope: Some("class"),
+            default: true,
+        },
+        Definition {
+            capture: "method",
+            kind: "m",
+            scope: None,
+            default: true,
+        },
+        Definition {
+            capture: "constructor",
+            kind: "m",
+            scope: None,
+            default: true,
+        },
+        Definition {
+            capture: "field",
+            kind: "f",
+            scope: None,
+            default: false,
+        },
+    ];
+
+    const QUERY: &str = r#"
+(class_declaration name: (identifier) @name) @definition.class
+(method_declaration
+  (modifiers "private")
+  name: (identifier) @name
+  parameters: (formal_parameters) @field.signature
+  (#set! field.file "")) @definition.method
+(method_declaration
+  (modifiers "public")
+  name: (identifier) @name) @definition.method
+(constructor_declaration name: (identifier) @name) @definition.constructor
+(field_declaration declarator: (variable_declarator name: (identifier) @name)) @definition.field
+"#;
+
+    const SOURCE: &str = "class Outer {
+    int count;
+    Outer() {}
+    public void run() {}
+    class Inner {
+        private int add(int a) {
+            return a;
+        }
+    }
+}
+";
+
+    fn query() -> TagsQuery {
+        TagsQuery::new(tree_sitter_java::LANGUAGE.into(), QUERY, DEFINITIONS).unwrap()
+    }
+
+    fn tag(name: &str, line: u32, kind: &str, end: u32, fields: &[(&str, &str)]) -> QueryTag {
+        QueryTag {
+            name: name.to_string(),
+            line,
+            kind: kind.to_string(),
+            end_line: Some(end),
+            extension_fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_tags_with_scopes_and_fields() {
+        let query = query();
+        let tags = query
+            .generate(SOURCE.as_bytes(), &query.kind_config(""))
+            .unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                tag("Outer", 1, "c", 10, &[]),
+                tag("Outer", 3, "m", 3, &[("class", "Outer")]),
+                tag("run", 4, "m", 4, &[("class", "Outer")]),
+                tag("Inner", 5, "c", 9, &[("class", "Outer")]),
+                tag(
+                    "add",
+                    6,
+                    "m",
+                    8,
+                    &[
+                        ("class", "Outer.Inner"),
+                        ("file", ""),
+                        ("signature", "(int a)")
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kind_config_keeps_disabled_scopes() {
+        let query = query();
+        let tags = query
+            .generate(SOURCE.as_bytes(), &query.kind_config("-c,+field"))
+            .unwrap();
+        let summary: Vec<_> = tags
+            .iter()
+            .map(|t| (t.name.as_str(), t.kind.as_str(), t.extension_fields.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("count", "f", 1),
+                ("Outer", "m", 1),
+                ("run", "m", 1),
+                ("add", "m", 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_definition_capture() {
+        let err = TagsQuery::new(
+            tree_sitter_java::LANGUAGE.into(),
+            "(interface_declaration name: (identifier) @name) @definition.interface",
+            DEFINITIONS,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err, "tags query: no kind for @definition.interface");
+    }
+}
@@ -4,10 +4,17 @@
 //! cannot:
 //!   * the `--plugin-cache` capability — it writes to `req.cache_file`, and is
 //!     the only end-to-end test of the WASI preopened cache-file path;
+//!   * the `--plugin-project-read` capability — it reads its own source back
+//!     through the read-only `/project` preopen and emits `echo_project`;
 //!   * the no-C-toolchain build path — it is pure Rust with no tree-sitter, so
 //!     it builds with just the `wasm32-wasip2` target and needs no WASI SDK
 //!     (the Java plugin's C code does), giving contributors without the SDK a
 //!     working plugin to test against;
+//!   * the optional `batch` export — it implements `generate-batch` on top of
+//!     `generate` and adds an `echo_batch` tag to every file tagged that way;
+//!   * the optional `detect` export — it scores files starting with `hello` as
+//!     certain and everything else as 0, so content-based disambiguation can be
+//!     tested against a language sharing `.echo` via `--map-<lang>=+.echo`;
 //!   * the raw `Guest` trait surface — it does not use `common`'s `tree-walker`
 //!     feature, unlike Java.
 //!
@@ -17,10 +24,12 @@
 //! excluding it from the published distribution index.
 
 wit_bindgen::generate!({
-    world: "plugin-world",
+    world: "full-plugin-world",
     path: "../../wit",
 });
 
+use exports::treetags::plugin::batch::{BatchFile, Guest as BatchGuest};
+use exports::treetags::plugin::detect::Guest as DetectGuest;
 use exports::treetags::plugin::plugin::{Guest, Request, Tag};
 
 struct EchoPlugin;
@@ -31,13 +40,54 @@ impl Guest for EchoPlugin {
             std::fs::write(&cache_name, "echo_cache_written\n")
                 .map_err(|e| format!("cache write error: {e}"))?;
         }
-        Ok(vec![Tag {
+        let mut tags = vec![Tag {
             name: "echo_tag".into(),
             line: 1,
             kind: "f".into(),
             end_line: None,
             extension_fields: vec![],
-        }])
+        }];
+        if let Some(project_path) = req.project_path {
+            std::fs::read(format!("/project/{project_path}"))
+                .map_err(|e| format!("project read error: {e}"))?;
+            tags.push(Tag {
+                name: "echo_project".into(),
+                line: 1,
+                kind: "f".into(),
+                end_line: None,
+                extension_fields: vec![],
+            });
+        }
+        Ok(tags)
+    }
+}
+
+impl BatchGuest for EchoPlugin {
+    fn generate_batch(files: Vec<BatchFile>) -> Vec<Result<Vec<Tag>, String>> {
+        files
+            .into_iter()
+            .map(|file| {
+                let mut tags = Self::generate(file.req, file.source)?;
+                tags.push(Tag {
+                    name: "echo_batch".into(),
+                    line: 1,
+                    kind: "f".into(),
+                    end_line: None,
+                    extension_fields: vec![],
+                });
+                Ok(tags)
+            })
+            .collect()
+    }
+}
+
+impl DetectGuest for EchoPlugin {
+    fn score(prefix: Vec<u8>) -> u32 {
+        if prefix.starts_with(b"hello") {
+            100
+        } else {
+            0
+        }
     }
 }
 
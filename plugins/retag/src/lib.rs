@@ -0,0 +1,57 @@
+//! Retag plugin — an internal dev/test fixture, not an end-user plugin.
+//!
+//! It is the post-processor counterpart of the echo plugin: it exports the
+//! `post-process` interface so the pipeline configured by `[[post_processors]]`
+//! has something to run end to end. Its args are space-separated directives:
+//!   * `prefix=<p>` — prepends `<p>` to every tag name;
+//!   * `drop=<name>` — removes tags named `<name>` (before prefixing);
+//!   * `size` — adds a `size` field holding the length of the file contents,
+//!     which the plugin only receives when configured with `source = true`.
+//!
+//! Like echo, its `plugin.toml` sets `internal = true`.
+
+wit_bindgen::generate!({
+    world: "post-processor-world",
+    path: "../../wit",
+});
+
+use exports::treetags::plugin::post_process::{FileTags, Guest, TagEntry};
+
+struct Retag;
+
+impl Guest for Retag {
+    fn process(input: FileTags) -> Result<Vec<TagEntry>, String> {
+        let mut prefix = "";
+        let mut drop = Vec::new();
+        let mut size = false;
+        for directive in input.args.split_whitespace() {
+            match directive.split_once('=') {
+                Some(("prefix", value)) => prefix = value,
+                Some(("drop", value)) => drop.push(value),
+                None if directive == "size" => size = true,
+                _ => return Err(format!("unknown directive `{directive}`")),
+            }
+        }
+        let size = match (size, &input.source) {
+            (true, Some(source)) => Some(source.len().to_string()),
+            (true, None) => return Err("`size` needs `source = true`".to_string()),
+            (false, _) => None,
+        };
+
+        Ok(input
+            .tags
+            .into_iter()
+            .filter(|tag| !drop.contains(&tag.name.as_str()))
+            .map(|mut tag| {
+                tag.name = format!("{prefix}{}", tag.name);
+                if let Some(size) = &size {
+                    tag.extension_fields
+                        .push(("size".to_string(), size.clone()));
+                }
+                tag
+            })
+            .collect())
+    }
+}
+
+export!(Retag);
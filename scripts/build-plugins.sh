@@ -22,6 +22,7 @@
 #   WASI_SDK_PATH      existing WASI SDK       (preferred if set)
 #   WASI_SDK_VERSION   version to fetch        (default: 30)
 #   WASI_SDK_SHA256    pinned tarball hash     (optional; else upstream SHA256SUMS)
+#   SIGNING_KEY        ed25519 key file        (optional; signs the index + blobs)
 set -euo pipefail
 
 ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
@@ -35,7 +36,7 @@ for arg in "$@"; do
   case "$arg" in
     --out-dir) : ;;                       # handled below
     --out-dir=*) OUT_DIR="${arg#*=}" ;;
-    -h|--help) sed -n '2,24p' "$0"; exit 0 ;;
+    -h|--help) sed -n '2,25p' "$0"; exit 0 ;;
     *) echo "unknown argument: $arg" >&2; exit 2 ;;
   esac
 done
@@ -107,11 +108,18 @@ done
 
 # 4. Generate index.json + Markdown into the site.
 echo "==> generating site content into $OUT_DIR"
+sign_args=()
+if [ -n "${SIGNING_KEY:-}" ]; then
+  sign_args=(--signing-key "$SIGNING_KEY")
+else
+  echo "==> SIGNING_KEY not set; the index will be unsigned and clients will refuse it without --allow-unsigned-plugins" >&2
+fi
 "$BS" \
   --abi "$ABI" \
   --asset-base-url "$ASSET_BASE" \
   --out-dir "$OUT_DIR" \
   --generated-at "$(date -u +%Y-%m-%dT%H:%M:%SZ)" \
+  ${sign_args[@]+"${sign_args[@]}"} \
   "$DIST"/*/
 
 echo
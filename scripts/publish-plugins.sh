@@ -50,9 +50,16 @@ rm -rf "$UPLOAD"; mkdir -p "$UPLOAD"
 staged=()
 for d in "${dirs[@]}"; do
   name=$(basename "$d")
-  cp "$d/plugin.wasm" "$UPLOAD/$name.wasm"
-  cp "$d/plugin.toml" "$UPLOAD/$name.toml"
-  staged+=("$name")
+  version=$(sed -n 's/^version[[:space:]]*=[[:space:]]*"\(.*\)".*/\1/p' "$d/plugin.toml" | head -1)
+  [ -n "$version" ] || { echo "no version in $d/plugin.toml" >&2; exit 1; }
+  # Versioned names keep every published version downloadable.
+  cp "$d/plugin.wasm" "$UPLOAD/$name-$version.wasm"
+  cp "$d/plugin.toml" "$UPLOAD/$name-$version.toml"
+  # Query files of declarative plugins, hosted as <name>-<version>.<file>.
+  for f in "$d"/*.scm; do
+    [ -e "$f" ] && cp "$f" "$UPLOAD/$name-$version.$(basename "$f")"
+  done
+  staged+=("$name@$version")
 done
 
 TAG="plugin-store-v${ABI}"
@@ -69,8 +76,8 @@ fi
 if ! gh release view "$TAG" --repo "$REPO" >/dev/null 2>&1; then
   gh release create "$TAG" --repo "$REPO" \
     --title "Plugin store (ABI ${ABI})" \
-    --notes "Durable blob store for treetags plugins built against ABI ${ABI}. Only the latest compatible build of each plugin is kept."
+    --notes "Durable blob store for treetags plugins built against ABI ${ABI}. Every published version of each plugin is kept."
 fi
 echo "==> uploading assets to $TAG"
-gh release upload "$TAG" --repo "$REPO" --clobber "$UPLOAD"/*.wasm "$UPLOAD"/*.toml
+gh release upload "$TAG" --repo "$REPO" --clobber "$UPLOAD"/*
 echo "==> done. Move plugins_index/ into the jekyll site repo under treetags/ and commit there to publish the index + pages."
@@ -1,25 +1,45 @@
 /// treetags-build-plugin — compile a WASM plugin project for distribution in one step.
 ///
+/// Declarative plugins (a `plugin.toml` with a `grammar` field, a grammar
+/// `.wasm` and query files) are packaged without building anything.
+///
+/// With `--bundle tar|zip` the output directory is also archived as
+/// `<name>-<version>.<ext>` next to it, with a `SHA256SUMS` inside, ready for
+/// `treetags plugin install ./<bundle>` on machines without index access.
+///
+/// `new <NAME> --grammar <CRATE>` scaffolds a plugin project instead; see
+/// [`scaffold`].
+///
 /// Usage:
 ///   treetags-build-plugin [OPTIONS] [PLUGIN_DIR]
+///   treetags-build-plugin new <NAME> --grammar <CRATE>[@<VERSION>]
 ///
 /// Build:
 ///   cargo build --release --bin treetags-build-plugin
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use treetags::plugin::bundle::{self, BundleFormat};
+
+#[path = "build_plugin/scaffold.rs"]
+mod scaffold;
 
 /// ABI version written into the distributed plugin.toml.
 /// Keep in sync with PLUGIN_ABI_VERSION in src/plugin/mod.rs.
-const PLUGIN_ABI_VERSION: u32 = 3;
+const PLUGIN_ABI_VERSION: u32 = 4;
 
 #[derive(Parser)]
 #[command(
     name = "treetags-build-plugin",
-    about = "Build a treetags WASM plugin project for distribution"
+    about = "Build a treetags WASM plugin project for distribution",
+    args_conflicts_with_subcommands = true
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Path to the plugin project directory (default: current directory)
     plugin_dir: Option<PathBuf>,
 
@@ -30,6 +50,28 @@ struct Args {
     /// Skip `cargo build`; use the existing .wasm artifact in target/
     #[arg(long)]
     no_build: bool,
+
+    /// Also archive the built plugin as <OUTPUT_DIR>/<name>-<version>.<tar|zip>
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    bundle: Option<BundleFormat>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Create a new plugin project built on treetags-plugin-common
+    New {
+        /// Plugin name; also the default language name and file extension
+        name: String,
+        /// tree-sitter grammar crate to parse with, optionally as CRATE@VERSION
+        #[arg(long, value_name = "CRATE")]
+        grammar: String,
+        /// File extension handled by the plugin (repeatable; default: NAME)
+        #[arg(long = "extension", value_name = "EXT")]
+        extensions: Vec<String>,
+        /// Directory to create the project in (default: ./NAME)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
 }
 
 #[derive(Deserialize, Default)]
@@ -40,6 +82,21 @@ struct FileConfig {
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    if let Some(Commands::New {
+        name,
+        grammar,
+        extensions,
+        path,
+    }) = args.command
+    {
+        return new_plugin(scaffold::NewPlugin {
+            name,
+            grammar,
+            extensions,
+            path,
+        });
+    }
+
     let plugin_dir = match args.plugin_dir {
         Some(p) => std::fs::canonicalize(&p).unwrap_or(p),
         None => std::env::current_dir().expect("cannot get current directory"),
@@ -56,6 +113,15 @@ fn main() -> anyhow::Result<()> {
         plugin_dir.join("dist")
     };
 
+    // Declarative plugins (grammar .wasm + queries) need no cargo build.
+    let plugin_toml_path = plugin_dir.join("plugin.toml");
+    if let Ok(plugin_toml_str) = std::fs::read_to_string(&plugin_toml_path) {
+        if parse_toml_string_field(&plugin_toml_str, "grammar").is_some() {
+            let out_dir = package_declarative(&plugin_dir, &plugin_toml_str, &output_dir_root)?;
+            return write_bundle(args.bundle, &out_dir, &output_dir_root);
+        }
+    }
+
     // Parse Cargo.toml.
     let cargo_toml_path = plugin_dir.join("Cargo.toml");
     let cargo_toml_str = std::fs::read_to_string(&cargo_toml_path)
@@ -66,7 +132,6 @@ fn main() -> anyhow::Result<()> {
     check_cdylib(&cargo_toml_str, &cargo_toml_path)?;
 
     // Read or scaffold plugin.toml.
-    let plugin_toml_path = plugin_dir.join("plugin.toml");
     if !plugin_toml_path.exists() {
         write_plugin_toml_template(&plugin_toml_path)?;
         eprintln!(
@@ -93,11 +158,15 @@ fn main() -> anyhow::Result<()> {
             "--target",
             "wasm32-wasip2",
             "--release",
-            "--locked",
             "-p",
             &pkg_name,
         ])
         .current_dir(&plugin_dir);
+        // A freshly scaffolded project has no Cargo.lock until its first build.
+        let workspace_root = cargo_metadata_path(&plugin_dir, "workspace_root");
+        if workspace_root.is_some_and(|root| root.join("Cargo.lock").exists()) {
+            cmd.arg("--locked");
+        }
 
         // Configure WASI SDK cross-compiler for C code (e.g. tree-sitter parsers).
         if let Some(sdk) = find_wasi_sdk() {
@@ -126,7 +195,8 @@ fn main() -> anyhow::Result<()> {
 
     // Locate the .wasm artifact.
     let wasm_filename = format!("{}.wasm", pkg_name.replace('-', "_"));
-    let target_dir = cargo_target_dir(&plugin_dir).unwrap_or_else(|| plugin_dir.join("target"));
+    let target_dir = cargo_metadata_path(&plugin_dir, "target_directory")
+        .unwrap_or_else(|| plugin_dir.join("target"));
     let wasm_path = target_dir
         .join("wasm32-wasip2/release")
         .join(&wasm_filename);
@@ -158,10 +228,131 @@ fn main() -> anyhow::Result<()> {
     println!();
     println!("To test: treetags --plugin-dir {} FILE", out_dir.display());
 
+    write_bundle(args.bundle, &out_dir, &output_dir_root)
+}
+
+/// Creates a plugin project and prints how to build and test it.
+fn new_plugin(opts: scaffold::NewPlugin) -> anyhow::Result<()> {
+    let dir = scaffold::create(&opts)?;
+    println!(
+        "Created plugin project `{}` in {}",
+        opts.name,
+        dir.display()
+    );
+    if !opts.grammar.contains('@') {
+        println!("Pin the `*` version of {} in Cargo.toml.", opts.grammar);
+    }
+    println!();
+    println!("Next steps:");
+    println!("  1. Adjust the node kinds matched in src/lib.rs to the grammar");
+    println!(
+        "  2. treetags-build-plugin {}   (set WASI_SDK_PATH to the WASI SDK for C grammars)",
+        dir.display()
+    );
+    println!(
+        "  3. Add samples under {0}/test_cases and run `treetags plugin test {0}`",
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Archives the files of the built plugin in `out_dir` when `--bundle` is set.
+fn write_bundle(
+    format: Option<BundleFormat>,
+    out_dir: &Path,
+    output_dir_root: &Path,
+) -> anyhow::Result<()> {
+    let Some(format) = format else {
+        return Ok(());
+    };
+    let manifest_path = out_dir.join("plugin.toml");
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", manifest_path.display()))?;
+    let name = parse_toml_string_field(&manifest, "name")
+        .ok_or_else(|| anyhow::anyhow!("no `name` field in {}", manifest_path.display()))?;
+    let version = parse_toml_string_field(&manifest, "version")
+        .ok_or_else(|| anyhow::anyhow!("no `version` field in {}", manifest_path.display()))?;
+
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(out_dir)
+        .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", out_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file() {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            files.insert(file_name.into_owned(), std::fs::read(&path)?);
+        }
+    }
+
+    let bundle_path = output_dir_root.join(format!("{name}-{version}.{}", format.extension()));
+    bundle::write_bundle(&bundle_path, format, &name, &files)?;
+    println!("Wrote bundle {}", bundle_path.display());
+    println!(
+        "To install: treetags plugin install {}",
+        bundle_path.display()
+    );
     Ok(())
 }
 
-fn cargo_target_dir(plugin_dir: &Path) -> Option<PathBuf> {
+/// Packages a declarative plugin: copies its grammar `.wasm` to `plugin.wasm`
+/// and its query files next to it, and writes the distribution manifest.
+/// Returns the output directory.
+fn package_declarative(
+    plugin_dir: &Path,
+    plugin_toml_str: &str,
+    output_dir_root: &Path,
+) -> anyhow::Result<PathBuf> {
+    let mut table: toml::Table = toml::from_str(plugin_toml_str)
+        .map_err(|e| anyhow::anyhow!("cannot parse plugin.toml: {e}"))?;
+    let plugin_name = parse_toml_string_field(plugin_toml_str, "name")
+        .ok_or_else(|| anyhow::anyhow!("no `name` field in plugin.toml"))?;
+    let field = |key: &str, default: Option<&str>| {
+        table
+            .get(key)
+            .and_then(|v| v.as_str())
+            .or(default)
+            .map(str::to_string)
+    };
+    let wasm_src = plugin_dir.join(field("wasm_file", Some("plugin.wasm")).unwrap_or_default());
+    let mut queries = vec![("tags_query", field("tags_query", Some("tags.scm")))];
+    queries.push(("locals_query", field("locals_query", None)));
+
+    let out_dir = output_dir_root.join(&plugin_name);
+    std::fs::create_dir_all(&out_dir).map_err(|e| {
+        anyhow::anyhow!("cannot create output directory {}: {e}", out_dir.display())
+    })?;
+
+    let wasm_out = out_dir.join("plugin.wasm");
+    std::fs::copy(&wasm_src, &wasm_out)
+        .map_err(|e| anyhow::anyhow!("cannot copy {}: {e}", wasm_src.display()))?;
+    println!("Copied {} -> {}", wasm_src.display(), wasm_out.display());
+
+    for (key, query) in queries {
+        let Some(query) = query else { continue };
+        let src = plugin_dir.join(&query);
+        let file_name = Path::new(&query)
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("invalid `{key}` path `{query}`"))?
+            .to_string_lossy()
+            .into_owned();
+        let dest = out_dir.join(&file_name);
+        std::fs::copy(&src, &dest)
+            .map_err(|e| anyhow::anyhow!("cannot copy {}: {e}", src.display()))?;
+        println!("Copied {} -> {}", src.display(), dest.display());
+        table.insert(key.to_string(), toml::Value::String(file_name));
+    }
+
+    let out_toml_path = out_dir.join("plugin.toml");
+    write_dist_manifest(&toml::to_string(&table)?, &out_toml_path)?;
+    println!("Writing {}", out_toml_path.display());
+    println!();
+    println!("To test: treetags --plugin-dir {} FILE", out_dir.display());
+    Ok(out_dir)
+}
+
+/// A path-valued field of `cargo metadata` for the plugin's workspace, such as
+/// `target_directory` or `workspace_root`.
+fn cargo_metadata_path(plugin_dir: &Path, field: &str) -> Option<PathBuf> {
     let output = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
         .args(["metadata", "--no-deps", "--format-version", "1"])
         .current_dir(plugin_dir)
@@ -173,7 +364,7 @@ fn cargo_target_dir(plugin_dir: &Path) -> Option<PathBuf> {
     }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let re = regex::Regex::new(r#""target_directory"\s*:\s*"([^"]+)""#).ok()?;
+    let re = regex::Regex::new(&format!(r#""{field}"\s*:\s*"([^"]+)""#)).ok()?;
     re.captures(&stdout)
         .and_then(|c| c.get(1))
         .map(|m| PathBuf::from(m.as_str()))
@@ -0,0 +1,432 @@
+//! `treetags-build-plugin new`: generates a ready-to-build plugin project.
+//!
+//! The project gets its own copy of the WIT interface, a tree walker built on
+//! `treetags-plugin-common`'s `walk_tree`/`ScopeStack`, a `plugin.toml` whose
+//! `[[kinds]]` come from the same table as the Rust `*_DEFAULT_KINDS` and
+//! `*_OPTIONAL_KINDS` consts, and a golden test under `test_cases/`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::PLUGIN_ABI_VERSION;
+
+/// WIT interface the generated plugin is built against.
+const WIT: &str = include_str!("../../../wit/treetags-plugin.wit");
+
+/// Where `treetags-plugin-common` is fetched from outside this repository.
+const SDK_GIT_URL: &str = "https://github.com/jha-naman/treetags";
+
+/// Kinds the generated plugin starts with: letter, name and whether it is
+/// generated by default. Both `plugin.toml` and the Rust kind tables are
+/// rendered from this list so they start out in agreement.
+const KINDS: &[(&str, &str, bool)] = &[
+    ("c", "class", true),
+    ("f", "function", true),
+    ("m", "method", true),
+    ("l", "local", false),
+];
+
+/// Options of `treetags-build-plugin new`.
+pub struct NewPlugin {
+    /// Plugin name, also the default language name and extension.
+    pub name: String,
+    /// Grammar crate, e.g. `tree-sitter-lua`, optionally as `CRATE@VERSION`.
+    pub grammar: String,
+    /// File extensions; defaults to the plugin name.
+    pub extensions: Vec<String>,
+    /// Project directory; defaults to `./<name>`.
+    pub path: Option<PathBuf>,
+}
+
+/// Writes the project and returns its directory.
+pub fn create(opts: &NewPlugin) -> anyhow::Result<PathBuf> {
+    if !is_valid_name(&opts.name) {
+        anyhow::bail!(
+            "invalid plugin name `{}`: use lowercase letters, digits, `-` and `_`, \
+             starting with a letter",
+            opts.name
+        );
+    }
+    let dir = opts
+        .path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(&opts.name));
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        anyhow::bail!("{} already exists and is not empty", dir.display());
+    }
+
+    let (grammar_crate, grammar_version) = match opts.grammar.split_once('@') {
+        Some((krate, version)) => (krate, version),
+        None => (opts.grammar.as_str(), "*"),
+    };
+    let extensions = if opts.extensions.is_empty() {
+        vec![opts.name.clone()]
+    } else {
+        opts.extensions.clone()
+    };
+    let sdk_dep = match sibling_sdk(&dir) {
+        Some(path) => format!("path = \"{path}\""),
+        None => format!("git = \"{SDK_GIT_URL}\""),
+    };
+
+    let files = [
+        (
+            "Cargo.toml",
+            cargo_toml(&opts.name, grammar_crate, grammar_version, &sdk_dep),
+        ),
+        ("plugin.toml", plugin_toml(&opts.name, &extensions)),
+        ("src/lib.rs", lib_rs(&opts.name, grammar_crate)),
+        ("wit/treetags-plugin.wit", WIT.to_string()),
+        (".gitignore", "/target\n/dist\n".to_string()),
+        (
+            "test_cases/basic/input/args.txt",
+            format!("--sort yes -f -\nsource.{}\n", extensions[0]),
+        ),
+        (
+            &format!("test_cases/basic/input/source.{}", extensions[0]),
+            String::new(),
+        ),
+        ("test_cases/basic/expected/exit_code.txt", "0".to_string()),
+        ("test_cases/basic/expected/stderr.txt", String::new()),
+        (
+            "test_cases/basic/expected/stdout.txt",
+            "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/\n".to_string(),
+        ),
+    ];
+    for (rel, contents) in files {
+        let path = dir.join(rel);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("cannot create {}: {e}", parent.display()))?;
+        }
+        std::fs::write(&path, contents)
+            .map_err(|e| anyhow::anyhow!("cannot write {}: {e}", path.display()))?;
+    }
+    // The template is not laid out for every name length; tidy it up when
+    // rustfmt is around.
+    let _ = Command::new("rustfmt")
+        .args(["--edition", "2021"])
+        .arg(dir.join("src/lib.rs"))
+        .output();
+    Ok(dir)
+}
+
+/// Relative path to `plugins/common` when the project is created next to it
+/// inside a treetags checkout.
+fn sibling_sdk(dir: &Path) -> Option<&'static str> {
+    let parent = dir.parent().filter(|p| !p.as_os_str().is_empty());
+    let parent = parent.unwrap_or(Path::new("."));
+    let manifest = std::fs::read_to_string(parent.join("common/Cargo.toml")).ok()?;
+    manifest
+        .contains("name = \"treetags-plugin-common\"")
+        .then_some("../common")
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn cargo_toml(name: &str, grammar_crate: &str, grammar_version: &str, sdk_dep: &str) -> String {
+    format!(
+        r#"[package]
+name = "treetags-plugin-{name}"
+version = "0.1.0"
+edition = "2021"
+description = "{name} plugin for treetags"
+
+[lib]
+crate-type = ["cdylib"]
+
+[dependencies]
+treetags-plugin-common = {{ {sdk_dep}, features = ["tree-walker"] }}
+tree-sitter = "0.26.5"
+{grammar_crate} = "{grammar_version}"
+wit-bindgen = "0.58"
+
+# Built on its own rather than as part of an enclosing workspace.
+[workspace]
+"#
+    )
+}
+
+fn plugin_toml(name: &str, extensions: &[String]) -> String {
+    let extensions = extensions
+        .iter()
+        .map(|e| format!("\"{e}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut out = format!(
+        "name = \"{name}\"\nversion = \"0.1.0\"\nabi_version = {PLUGIN_ABI_VERSION}\n\
+         extensions = [{extensions}]\nlanguage = \"{name}\"\n"
+    );
+    for (letter, kind, default) in KINDS {
+        out.push_str(&format!(
+            "\n[[kinds]]\nletter = \"{letter}\"\nname = \"{kind}\"\ndefault = {default}\n"
+        ));
+    }
+    out
+}
+
+fn lib_rs(name: &str, grammar_crate: &str) -> String {
+    let camel: String = name
+        .split(['-', '_'])
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    let upper = name.to_ascii_uppercase().replace('-', "_");
+    let kind_rows = |default: bool| -> String {
+        KINDS
+            .iter()
+            .filter(|(_, _, d)| *d == default)
+            .map(|(letter, kind, _)| format!("    (&[\"{letter}\", \"{kind}\"], \"{letter}\"),\n"))
+            .collect()
+    };
+    LIB_RS
+        .replace("{{NAME}}", name)
+        .replace("{{CAMEL}}", &camel)
+        .replace("{{UPPER}}", &upper)
+        .replace("{{GRAMMAR}}", &grammar_crate.replace('-', "_"))
+        .replace("{{DEFAULT_KINDS}}", &kind_rows(true))
+        .replace("{{OPTIONAL_KINDS}}", &kind_rows(false))
+}
+
+const LIB_RS: &str = r#"wit_bindgen::generate!({
+    world: "plugin-world",
+    path: "wit",
+});
+
+use exports::treetags::plugin::plugin::{Guest, Request, Tag};
+use tree_sitter::{Node, Parser as TsParser, TreeCursor};
+use treetags_plugin_common::{
+    node_text, walk_tree, ScopeKey, ScopeStack, TagKindConfig, WalkContext,
+};
+
+struct {{CAMEL}}Plugin;
+
+impl Guest for {{CAMEL}}Plugin {
+    fn generate(req: Request, source: Vec<u8>) -> Result<Vec<Tag>, String> {
+        let mut parser = TsParser::new();
+        // Some grammar crates name this differently, e.g. `LANGUAGE_TYPESCRIPT`.
+        let language: tree_sitter::Language = {{GRAMMAR}}::LANGUAGE.into();
+        parser
+            .set_language(&language)
+            .map_err(|e| format!("set_language: {e}"))?;
+        generate_tags(&mut parser, &req, &source)
+    }
+}
+
+export!({{CAMEL}}Plugin);
+
+// Names accepted by `--kinds-{{NAME}}` and the letter emitted for each kind.
+// Keep in sync with the `[[kinds]]` in plugin.toml.
+const {{UPPER}}_DEFAULT_KINDS: &[(&[&str], &str)] = &[
+{{DEFAULT_KINDS}}];
+
+const {{UPPER}}_OPTIONAL_KINDS: &[(&[&str], &str)] = &[
+{{OPTIONAL_KINDS}}];
+
+#[derive(Clone, Copy)]
+enum ScopeKind {
+    Class,
+    Function,
+}
+
+impl ScopeKey for ScopeKind {
+    fn key(self) -> &'static str {
+        match self {
+            ScopeKind::Class => "class",
+            ScopeKind::Function => "function",
+        }
+    }
+}
+
+struct {{CAMEL}}Walker<'src> {
+    source: &'src [u8],
+    scopes: ScopeStack<ScopeKind>,
+    kinds: TagKindConfig,
+    tags: Vec<Tag>,
+}
+
+impl WalkContext for {{CAMEL}}Walker<'_> {
+    fn process_node(&mut self, cursor: &mut TreeCursor) -> bool {
+        let node = cursor.node();
+        // Node kinds differ between grammars: run `tree-sitter parse` on a
+        // sample file (or read the grammar's node-types.json) and adjust these
+        // arms to match.
+        match node.kind() {
+            "class_definition" | "class_declaration" => {
+                self.definition(node, "c", Some(ScopeKind::Class))
+            }
+            "function_definition" | "function_declaration" | "function_item" => {
+                let kind = match self.scopes.last_key() {
+                    Some(ScopeKind::Class) => "m",
+                    _ => "f",
+                };
+                self.definition(node, kind, Some(ScopeKind::Function))
+            }
+            "variable_declarator" => match self.scopes.last_key() {
+                Some(ScopeKind::Function) => self.definition(node, "l", None),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+}
+
+impl {{CAMEL}}Walker<'_> {
+    /// Tags `node` by its `name` field as `kind`, if that kind is enabled, and
+    /// enters `scope` when given. Returns whether a scope was pushed.
+    fn definition(&mut self, node: Node, kind: &str, scope: Option<ScopeKind>) -> bool {
+        let Some(name_node) = node.child_by_field_name("name") else {
+            return false;
+        };
+        let name = node_text(name_node, self.source).to_string();
+        if self.kinds.is_enabled(kind) {
+            let extension_fields = self
+                .scopes
+                .current_field()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .into_iter()
+                .collect();
+            self.tags.push(Tag {
+                name: name.clone(),
+                line: name_node.start_position().row as u32 + 1,
+                kind: kind.to_string(),
+                end_line: None,
+                extension_fields,
+            });
+        }
+        match scope {
+            Some(scope) => {
+                self.scopes.push(scope, &name);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn generate_tags(parser: &mut TsParser, req: &Request, source: &[u8]) -> Result<Vec<Tag>, String> {
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| "parse failed".to_string())?;
+
+    let mut walker = {{CAMEL}}Walker {
+        source,
+        scopes: ScopeStack::new(),
+        kinds: TagKindConfig::parse(&req.kinds, {{UPPER}}_DEFAULT_KINDS, {{UPPER}}_OPTIONAL_KINDS),
+        tags: Vec::new(),
+    };
+
+    let mut cursor = tree.walk();
+    if cursor.goto_first_child() {
+        walk_tree(&mut cursor, &mut walker);
+    }
+
+    Ok(walker.tags)
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use treetags::plugin::manifest::PluginManifest;
+
+    fn new_plugin(dir: &Path) -> NewPlugin {
+        NewPlugin {
+            name: "lua".to_string(),
+            grammar: "tree-sitter-lua@0.2".to_string(),
+            extensions: Vec::new(),
+            path: Some(dir.to_path_buf()),
+        }
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        for name in ["lua", "tree-sitter-x", "c_sharp", "f90"] {
+            assert!(is_valid_name(name), "{name}");
+        }
+        for name in ["", "Lua", "9lives", "-lua", "my plugin", "lua.x"] {
+            assert!(!is_valid_name(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_plugin_toml_is_a_valid_manifest() {
+        let text = plugin_toml("lua", &["lua".to_string(), "luau".to_string()]);
+        let manifest: PluginManifest = toml::from_str(&text).unwrap();
+        assert_eq!(manifest.name, "lua");
+        assert_eq!(manifest.abi_version, PLUGIN_ABI_VERSION);
+        assert_eq!(manifest.extensions, ["lua", "luau"]);
+        assert_eq!(manifest.language.as_deref(), Some("lua"));
+        let kinds: Vec<_> = manifest
+            .kinds
+            .unwrap()
+            .iter()
+            .map(|k| (k.letter.clone(), k.name.clone(), k.default))
+            .collect();
+        let expected: Vec<_> = KINDS
+            .iter()
+            .map(|(letter, name, default)| (letter.to_string(), name.to_string(), *default))
+            .collect();
+        assert_eq!(kinds, expected);
+    }
+
+    #[test]
+    fn test_cargo_toml_parses() {
+        let text = cargo_toml("lua", "tree-sitter-lua", "0.2", "path = \"../common\"");
+        let doc: toml::Table = toml::from_str(&text).unwrap();
+        assert_eq!(doc["package"]["name"].as_str(), Some("treetags-plugin-lua"));
+        assert_eq!(doc["lib"]["crate-type"][0].as_str(), Some("cdylib"));
+        let deps = doc["dependencies"].as_table().unwrap();
+        assert_eq!(deps["tree-sitter-lua"].as_str(), Some("0.2"));
+        assert_eq!(
+            deps["treetags-plugin-common"]["path"].as_str(),
+            Some("../common")
+        );
+        assert!(doc.contains_key("workspace"));
+    }
+
+    #[test]
+    fn test_create_refuses_existing_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("lua");
+        let opts = new_plugin(&dir);
+
+        assert_eq!(create(&opts).unwrap(), dir);
+        let manifest = std::fs::read_to_string(dir.join("plugin.toml")).unwrap();
+        assert!(toml::from_str::<PluginManifest>(&manifest).is_ok());
+        for file in [
+            "Cargo.toml",
+            "src/lib.rs",
+            "test_cases/basic/input/source.lua",
+        ] {
+            assert!(dir.join(file).is_file(), "{file}");
+        }
+
+        let err = create(&opts).unwrap_err().to_string();
+        assert!(err.contains("already exists"), "{err}");
+
+        let bad = NewPlugin {
+            name: "Lua".to_string(),
+            ..new_plugin(&tmp.path().join("other"))
+        };
+        assert!(create(&bad).is_err());
+        assert!(!tmp.path().join("other").exists());
+    }
+}
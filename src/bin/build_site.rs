@@ -1,7 +1,8 @@
 //! treetags-build-site — generate the plugin distribution content for the site.
 //!
 //! Reads built plugin directories (each the `<name>/` output of
-//! `treetags-build-plugin`, containing `plugin.wasm` + `plugin.toml`) and writes,
+//! `treetags-build-plugin`, containing `plugin.wasm` + `plugin.toml`, plus the
+//! query files of a declarative plugin) and writes,
 //! under `--out-dir`, everything the Jekyll site needs for one ABI bucket:
 //!
 //!   <out-dir>/<abi>/index.json   machine index the treetags client fetches
@@ -12,26 +13,38 @@
 //! Point `--out-dir` at a staging folder (e.g. `plugins_index`) that is later
 //! moved into the site under `treetags/`, served at `/treetags/…` (e.g. the
 //! client fetches `/treetags/<abi>/index.json`). Asset URLs point at the GitHub
-//! Release that hosts the `.wasm`/`.toml` blobs.
+//! Release that hosts the `<name>-<version>.wasm`/`.toml` blobs.
 //!
 //! Only the current ABI's files are (re)written; other ABI buckets already in
 //! `out-dir` are left untouched, and the landing page is regenerated from the
-//! merged `abis.json`. Internal (dev/test) plugins are excluded.
+//! merged `abis.json`. Versions listed in the bucket's existing `index.json`
+//! are kept as each plugin's `previous_versions`, so the index gains history
+//! across runs. Internal (dev/test) plugins are excluded.
+//!
+//! With `--signing-key` every `.wasm`, manifest and extra file is signed into
+//! its index entry and `index.json` gets a detached `index.json.sig`, which the
+//! client verifies before installing. Create a key once with
+//! `--generate-key <path>`, which prints the public key clients should trust.
 //!
 //! Usage:
 //!   treetags-build-site \
 //!     --asset-base-url https://github.com/jha-naman/treetags/releases/download/plugin-store-v3 \
 //!     --out-dir plugins_index \
 //!     [--abi 3] [--generated-at 2026-07-21T00:00:00Z] \
+//!     [--signing-key plugin-signing.key] \
 //!     dist/java
 //!
+//!   treetags-build-site --generate-key plugin-signing.key
+//!
 //! Build:
 //!   cargo build --release --locked --bin treetags-build-site
 use clap::Parser;
+use ed25519_dalek::SigningKey;
 use std::path::{Path, PathBuf};
 
 use treetags::plugin::index::{sha256_hex, AbisFile, IndexEntry, PluginIndex};
 use treetags::plugin::manifest::PluginManifest;
+use treetags::plugin::signing;
 
 #[derive(Parser)]
 #[command(
@@ -47,29 +60,55 @@ struct Args {
     #[arg(long)]
     abi: Option<u32>,
 
-    /// Base URL under which `<name>.wasm` / `<name>.toml` assets are hosted,
+    /// Base URL under which `<name>-<version>.wasm` / `.toml` assets are hosted,
     /// e.g. a GitHub Release download URL.
-    #[arg(long)]
-    asset_base_url: String,
+    #[arg(long, required_unless_present = "generate_key")]
+    asset_base_url: Option<String>,
 
     /// Output root (e.g. `plugins_index`, later moved into the site under `treetags/`).
-    #[arg(long)]
-    out_dir: PathBuf,
+    #[arg(long, required_unless_present = "generate_key")]
+    out_dir: Option<PathBuf>,
 
     /// Optional RFC3339 timestamp stamped into the generated files. Pass
     /// `$(date -u +%Y-%m-%dT%H:%M:%SZ)`; omitted keeps the field out.
     #[arg(long)]
     generated_at: Option<String>,
+
+    /// File holding the hex ed25519 secret key used to sign the index and
+    /// every plugin artifact.
+    #[arg(long, value_name = "PATH")]
+    signing_key: Option<PathBuf>,
+
+    /// Write a new signing key to PATH, print its public key and exit.
+    #[arg(long, value_name = "PATH", conflicts_with = "signing_key")]
+    generate_key: Option<PathBuf>,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    if let Some(path) = &args.generate_key {
+        let key = signing::generate_signing_key()?;
+        signing::write_signing_key(path, &key)?;
+        println!("Wrote signing key to {}", path.display());
+        println!("Public key: {}", signing::public_key_hex(&key));
+        return Ok(());
+    }
+    let (Some(asset_base_url), Some(out)) = (&args.asset_base_url, &args.out_dir) else {
+        anyhow::bail!("--asset-base-url and --out-dir are required");
+    };
+    let signing_key = args
+        .signing_key
+        .as_deref()
+        .map(signing::read_signing_key)
+        .transpose()?;
+
     let mut entries: Vec<IndexEntry> = Vec::with_capacity(args.plugin_dirs.len());
     let mut derived_abi: Option<u32> = None;
 
     for dir in &args.plugin_dirs {
-        let Some((entry, abi)) = process_plugin_dir(dir, &args.asset_base_url)? else {
+        let Some((entry, abi)) = process_plugin_dir(dir, asset_base_url, signing_key.as_ref())?
+        else {
             println!("Skipping internal plugin at {}", dir.display());
             continue;
         };
@@ -105,8 +144,19 @@ fn main() -> anyhow::Result<()> {
     // Deterministic output regardless of argument order.
     entries.sort_by(|a, b| a.name.cmp(&b.name));
 
+    let index_path = out.join(abi.to_string()).join("index.json");
+    let published = read_json_if_exists::<PluginIndex>(&index_path)?;
+    let entries: Vec<IndexEntry> = entries
+        .into_iter()
+        .map(|entry| {
+            let existing = published
+                .as_ref()
+                .and_then(|index| index.plugins.iter().find(|p| p.name == entry.name));
+            entry.with_history(existing)
+        })
+        .collect();
+
     let generated_at = args.generated_at.as_deref();
-    let out = &args.out_dir;
 
     // Per-ABI machine index + human page.
     let index = PluginIndex {
@@ -114,8 +164,19 @@ fn main() -> anyhow::Result<()> {
         generated_at: args.generated_at.clone(),
         plugins: entries.clone(),
     };
-    let index_path = out.join(abi.to_string()).join("index.json");
-    write_json(&index_path, &index)?;
+    let index_json = to_json(&index)?;
+    write_str(&index_path, &index_json)?;
+    let sig_path = out.join(abi.to_string()).join("index.json.sig");
+    match &signing_key {
+        Some(key) => write_str(
+            &sig_path,
+            &format!("{}\n", signing::sign_hex(key, index_json.as_bytes())),
+        )?,
+        // A stale signature would never verify against the new index.
+        None => {
+            let _ = std::fs::remove_file(&sig_path);
+        }
+    }
     let page_path = out.join(abi.to_string()).join("index.md");
     write_str(&page_path, &render_abi_page(abi, &entries, generated_at))?;
 
@@ -130,10 +191,15 @@ fn main() -> anyhow::Result<()> {
     )?;
 
     println!(
-        "Wrote {} ({} plugin{}, ABI {abi}); abis: {:?}, latest {}",
+        "Wrote {} ({} plugin{}, ABI {abi}, {}); abis: {:?}, latest {}",
         index_path.display(),
         index.plugins.len(),
         if index.plugins.len() == 1 { "" } else { "s" },
+        if signing_key.is_some() {
+            "signed"
+        } else {
+            "unsigned"
+        },
         merged.abis,
         merged.latest
     );
@@ -143,10 +209,11 @@ fn main() -> anyhow::Result<()> {
 /// Parse a built plugin directory into an [`IndexEntry`], returning the ABI it
 /// targets so the caller can enforce a single ABI across the bucket. Returns
 /// `None` for internal (dev/test-only) plugins, which are excluded so end users
-/// never see or install them.
+/// never see or install them. With a `signing_key` every artifact is signed.
 fn process_plugin_dir(
     dir: &Path,
     asset_base_url: &str,
+    signing_key: Option<&SigningKey>,
 ) -> anyhow::Result<Option<(IndexEntry, u32)>> {
     let manifest_path = dir.join("plugin.toml");
     let manifest_str = std::fs::read_to_string(&manifest_path)
@@ -164,8 +231,26 @@ fn process_plugin_dir(
     let sha256 = sha256_hex(&wasm_bytes);
     let size = wasm_bytes.len() as u64;
 
+    let mut files = Vec::new();
+    let mut file_bytes = Vec::new();
+    for file in manifest.query_files() {
+        let path = dir.join(file);
+        let bytes = std::fs::read(&path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        files.push((file.to_string(), sha256_hex(&bytes)));
+        file_bytes.push(bytes);
+    }
+
     let abi = manifest.abi_version;
-    let entry = IndexEntry::from_manifest(&manifest, asset_base_url, sha256, size);
+    let mut entry = IndexEntry::from_manifest(&manifest, asset_base_url, sha256, size)
+        .with_files(asset_base_url, files);
+    if let Some(key) = signing_key {
+        entry.wasm_signature = Some(signing::sign_hex(key, &wasm_bytes));
+        entry.manifest_signature = Some(signing::sign_hex(key, manifest_str.as_bytes()));
+        for (file, bytes) in entry.files.iter_mut().zip(&file_bytes) {
+            file.signature = Some(signing::sign_hex(key, bytes));
+        }
+    }
     Ok(Some((entry, abi)))
 }
 
@@ -260,8 +345,12 @@ fn json_escape(s: &str) -> String {
 // IO helpers
 // ---------------------------------------------------------------------------
 
+fn to_json<T: serde::Serialize>(value: &T) -> anyhow::Result<String> {
+    Ok(format!("{}\n", serde_json::to_string_pretty(value)?))
+}
+
 fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
-    write_str(path, &format!("{}\n", serde_json::to_string_pretty(value)?))
+    write_str(path, &to_json(value)?)
 }
 
 fn write_str(path: &Path, contents: &str) -> anyhow::Result<()> {
@@ -3,13 +3,17 @@
 //! This module is responsible for parsing command line arguments
 //! and providing configuration options to the rest of the application.
 
-use clap::{FromArgMatches, Parser, Subcommand};
+use clap::{Args, FromArgMatches, Parser, Subcommand};
 use std::collections::HashMap;
 use std::{fs, path::Path};
 
 use extras_config::ExtrasConfig;
 use fields_config::FieldsConfig;
 
+pub use builder::ConfigBuilder;
+pub use user_grammars::UserGrammar;
+
+mod builder;
 mod extras_config;
 mod fields_config;
 pub mod lang_map;
@@ -21,6 +25,9 @@ mod user_grammars;
 /// machine's available parallelism via `--max-parallelism`.
 const DEFAULT_WORKERS: usize = 4;
 
+/// Default maximum number of files handed to a WASM plugin in one batch.
+const DEFAULT_PLUGIN_BATCH_SIZE: usize = 64;
+
 /// Subcommands for the application
 #[derive(Subcommand, Clone, Debug)]
 pub enum Commands {
@@ -39,6 +46,118 @@ pub enum Commands {
         #[command(subcommand)]
         action: PluginCommands,
     },
+    /// Inspect and clean the plugin cache files granted by `--plugin-cache`
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommands,
+    },
+    /// Look up tags by name in a sorted tags file, like readtags
+    Query {
+        #[command(flatten)]
+        query: QueryArgs,
+    },
+    /// Run a language server over stdio answering workspace and document
+    /// symbol, definition and folding range requests from tags
+    Lsp,
+    /// Print the definitions of one file as a tree, with their kinds, line
+    /// ranges and signatures
+    Outline {
+        /// File to outline
+        file: std::path::PathBuf,
+        /// Print JSON records with their nested `children` instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Arguments of `treetags query`.
+#[derive(Args, Clone, Debug)]
+pub struct QueryArgs {
+    /// Tag name to look up
+    pub name: String,
+    /// Tags file or `--output-format=sqlite` database to search
+    #[arg(
+        short = 't',
+        long = "tag-file",
+        value_name = "FILE",
+        default_value = "tags"
+    )]
+    pub tag_file: std::path::PathBuf,
+    /// Match every tag whose name starts with NAME
+    #[arg(short = 'p', long)]
+    pub prefix: bool,
+    /// Compare names ignoring case
+    #[arg(short = 'i', long)]
+    pub ignore_case: bool,
+    /// Only tags of this kind, as a letter or name. Can be repeated
+    #[arg(long, value_name = "KIND")]
+    pub kind: Vec<String>,
+    /// Only tags of this language, resolved from the tagged file's name.
+    /// Can be repeated
+    #[arg(long, value_name = "LANG")]
+    pub language: Vec<String>,
+    /// Only tags inside this scope, given as NAME or KIND:NAME
+    #[arg(long, value_name = "SCOPE")]
+    pub scope: Option<String>,
+    /// Only tags with this extension field, given as KEY or KEY=VALUE. Can be
+    /// repeated
+    #[arg(long, value_name = "KEY[=VALUE]")]
+    pub field: Vec<String>,
+    /// Print the matching tags as JSON records instead of tags lines
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Formats `treetags` can write tags in, selected with `--output-format`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A vi compatible tags file
+    #[default]
+    Ctags,
+    /// A SCIP index
+    Scip,
+    /// A SQLite database, updated incrementally
+    Sqlite,
+    /// A cross-reference table, like `ctags -x`
+    Xref,
+}
+
+/// Subcommands under `treetags cache`.
+#[derive(Subcommand, Clone, Debug)]
+pub enum CacheCommands {
+    /// List cache files per project and plugin
+    List {
+        #[command(flatten)]
+        filter: CacheFilterArgs,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the total size of the cache files
+    Size {
+        #[command(flatten)]
+        filter: CacheFilterArgs,
+    },
+    /// Remove cache files
+    Clean {
+        #[command(flatten)]
+        filter: CacheFilterArgs,
+    },
+}
+
+/// Selects the cache files a `treetags cache` subcommand acts on.
+#[derive(Args, Clone, Debug)]
+pub struct CacheFilterArgs {
+    /// Only the caches of the project rooted at PATH (default: the current
+    /// directory)
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = ".")]
+    pub project: Option<std::path::PathBuf>,
+    /// Only the caches of this plugin
+    #[arg(long, value_name = "NAME")]
+    pub plugin: Option<String>,
+    /// Only files last written longer ago than AGE, e.g. `30d`, `12h` or `2w`
+    #[arg(long, value_name = "AGE", value_parser = crate::plugin::cache::parse_duration)]
+    pub older_than: Option<std::time::Duration>,
 }
 
 /// Subcommands under `treetags plugin`.
@@ -50,9 +169,34 @@ pub enum PluginCommands {
         #[arg(long)]
         refresh: bool,
     },
+    /// Show everything known about a plugin: its index entry, installed
+    /// manifest, granted access and the native languages it shadows
+    Info {
+        /// Plugin name, optionally as `NAME@VERSION` to show a published version
+        name: String,
+        /// Bypass the cached index and re-fetch it
+        #[arg(long)]
+        refresh: bool,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Find available plugins by name, language, extension or filename pattern
+    Search {
+        /// Text to look for (case-insensitive); `*.ext` and `.ext` match extensions
+        term: String,
+        /// Bypass the cached index and re-fetch it
+        #[arg(long)]
+        refresh: bool,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
     /// Download and install a plugin by name
     Install {
-        /// Plugin name (as shown by `plugin available`)
+        /// Plugin name (as shown by `plugin available`), optionally pinned to
+        /// a published version as `NAME@VERSION`, or the path of a built plugin
+        /// directory or `.tar`/`.zip` bundle
         name: String,
         /// Reinstall even if an equal or newer version is present
         #[arg(long)]
@@ -60,6 +204,10 @@ pub enum PluginCommands {
         /// Bypass the cached index and re-fetch it
         #[arg(long)]
         refresh: bool,
+        /// Record the plugin in treetags-plugins.lock, creating it if needed
+        /// (an existing lockfile is always kept up to date)
+        #[arg(long)]
+        lock: bool,
     },
     /// Remove a locally installed plugin by name
     Uninstall {
@@ -76,6 +224,32 @@ pub enum PluginCommands {
         #[arg(long)]
         refresh: bool,
     },
+    /// Swap an installed plugin with the version its last install or update replaced
+    Rollback {
+        /// Plugin name to roll back
+        name: String,
+    },
+    /// Install exactly the plugin versions pinned in treetags-plugins.lock
+    Sync {
+        /// Bypass the cached index and re-fetch it
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Run a plugin project's golden tests in test_cases/ against the built plugin
+    Test {
+        /// Plugin project directory (default: current directory)
+        dir: Option<std::path::PathBuf>,
+        /// Built plugin directory to test (default: the project directory if it
+        /// holds the .wasm, else its dist/<name>)
+        #[arg(long, value_name = "DIR")]
+        built: Option<std::path::PathBuf>,
+        /// Only run test cases whose name contains this text
+        #[arg(long, value_name = "TEXT")]
+        filter: Option<String>,
+        /// Write the actual output as the new expected output
+        #[arg(long)]
+        bless: bool,
+    },
 }
 
 /// Configuration options for the tag generator.
@@ -92,6 +266,43 @@ pub struct Config {
     #[arg(short = 'f', default_value = "tags")]
     pub tag_file: String,
 
+    /// Format of the output file. `scip` writes a SCIP index for code
+    /// intelligence platforms, written to `index.scip` unless `-f` names
+    /// another file. `sqlite` stores the tags in a SQLite database, `tags.db`
+    /// unless `-f` names another file, retagging only files that changed
+    /// since the last run. `xref` prints a cross-reference table to stdout
+    /// unless `-f` names a file; see `-x`.
+    #[arg(
+        long = "output-format",
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Ctags,
+        verbatim_doc_comment
+    )]
+    pub output_format: OutputFormat,
+
+    /// Print a cross-reference table of the tags instead of writing a tags
+    /// file, one line per tag with its name, kind, line, file and source
+    /// line. Same as `--output-format=xref`.
+    #[arg(short = 'x', verbatim_doc_comment)]
+    pub xref: bool,
+
+    /// Columns of the `-x` table: text with `%` conversions, each optionally
+    /// padded as `%4n` (right-aligned) or `%-16N` (left-aligned).
+    ///   N name, F input file, P pattern, C compact source line, n line,
+    ///   e end line, k kind letter, K kind name, l language, s scope,
+    ///   p scope kind, S signature, a access, t typeref, r roles,
+    ///   %{NAME} any field by name, %% a literal %
+    #[arg(
+        long = "xformat",
+        alias = "_xformat",
+        value_name = "FORMAT",
+        value_parser = crate::tag_writer::XrefFormat::parse,
+        default_value = crate::tag_writer::DEFAULT_XREF_FORMAT,
+        verbatim_doc_comment
+    )]
+    pub xformat: crate::tag_writer::XrefFormat,
+
     /// Append tags to existing tag file instead of reginerating the file from scratch.
     /// Need to pass in list of file names for which new tags are to be generated.
     #[arg(long = "append", default_value = "false", verbatim_doc_comment, default_missing_value="true", num_args=0..=1)]
@@ -99,6 +310,19 @@ pub struct Config {
 
     /// List of file names to be processed when `--append` option is passed
     pub file_names: Vec<String>,
+
+    /// Read the source to tag from stdin, given as `-` in place of file names,
+    /// and tag it as the file NAME. NAME and the content select the language;
+    /// the file does not need to exist.
+    #[arg(long = "stdin-filename", value_name = "NAME", verbatim_doc_comment)]
+    pub stdin_filename: Option<String>,
+
+    /// Keep running and read JSON requests from stdin, one per line, writing
+    /// tags as JSON lines to stdout. Parsers and plugins stay loaded between
+    /// requests. See the README for the protocol.
+    #[arg(long, verbatim_doc_comment)]
+    pub interactive: bool,
+
     #[arg(long, default_value_t = DEFAULT_WORKERS)]
     /// Number of threads to use for parsing files
     pub workers: usize,
@@ -201,6 +425,10 @@ pub struct Config {
     #[clap(skip)]
     pub user_grammars: Vec<user_grammars::UserGrammar>,
 
+    /// Tag post-processing pipeline from `[[post_processors]]` in the config file.
+    #[clap(skip)]
+    pub post_processors: Vec<user_grammars::PostProcessorConfig>,
+
     /// Path to user languages config file. Overrides default config file paths.
     #[arg(long)]
     pub user_languages_config: Option<std::path::PathBuf>,
@@ -216,6 +444,27 @@ pub struct Config {
     #[arg(long = "plugin-cache", value_name = "NAME")]
     pub plugin_cache: Vec<String>,
 
+    /// Caps the total size of all projects' plugin cache files, e.g. `500M`.
+    /// When a run with `--plugin-cache` starts over the cap, the least recently
+    /// written files are removed
+    #[arg(long = "plugin-cache-max-size", value_name = "SIZE", value_parser = crate::plugin::cache::parse_size)]
+    pub plugin_cache_max_size: Option<u64>,
+
+    /// Grants a plugin read-only access to the project root (the current
+    /// directory), preopened at /project inside its sandbox, and passes it each
+    /// file's project-relative path. Specify the `name` field from the plugin's
+    /// plugin.toml. Can be repeated to grant access to multiple plugins. Adds to
+    /// the plugins with `project_read = true` under `[plugins.<name>]` in the
+    /// config file
+    #[arg(long = "plugin-project-read", value_name = "NAME")]
+    pub plugin_project_read: Vec<String>,
+
+    /// Maximum number of files handed to a WASM plugin in one call. Files for
+    /// the same plugin are grouped into batches that are spread across worker
+    /// threads; plugins without a `batch` export still get one call per file.
+    #[arg(long = "plugin-batch-size", value_name = "N", default_value_t = DEFAULT_PLUGIN_BATCH_SIZE, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub plugin_batch_size: usize,
+
     /// Directory to search recursively for WASM plugins. Defaults to ~/.config/treetags/plugins.
     #[arg(long = "plugins-dir", value_name = "PATH")]
     pub plugins_dir_arg: Option<std::path::PathBuf>,
@@ -226,10 +475,22 @@ pub struct Config {
 
     /// Base URL of the plugin distribution index. Overrides the compiled-in
     /// default and the `TREETAGS_PLUGIN_INDEX` env var. Used by `plugin`
-    /// subcommands, which fetch `{URL}/{ABI}/index.json`.
+    /// subcommands, which fetch `{URL}/{ABI}/index.json`. A comma-separated
+    /// list is tried in order as mirrors; `file://` URLs read local files.
     #[arg(long = "plugin-index-url", value_name = "URL")]
     pub plugin_index_url: Option<String>,
 
+    /// Hex ed25519 public key trusted to sign the plugin index and plugin
+    /// artifacts, in addition to the compiled-in keys and those listed in
+    /// `TREETAGS_PLUGIN_TRUSTED_KEYS`. Can be repeated.
+    #[arg(long = "plugin-trusted-key", value_name = "HEX")]
+    pub plugin_trusted_keys: Vec<String>,
+
+    /// Install and update plugins even when the index or a downloaded artifact
+    /// is unsigned or its signature does not verify.
+    #[arg(long = "allow-unsigned-plugins")]
+    pub allow_unsigned_plugins: bool,
+
     /// Instead of generating tags, report which available (uninstalled) plugins
     /// could handle files that no builtin, grammar, or installed plugin covers.
     #[arg(long = "suggest-plugins")]
@@ -298,6 +559,10 @@ impl Config {
         config.validate();
         config.parse_file_args();
 
+        if config.xref {
+            config.output_format = OutputFormat::Xref;
+        }
+
         if config.max_parallelism {
             config.workers = std::thread::available_parallelism()
                 .map(|n| n.get())
@@ -337,7 +602,8 @@ impl Config {
 
         config.extras_config = ExtrasConfig::from_string(&config.extras);
         config.fields_config = FieldsConfig::from_string(&config.fields);
-        config.user_grammars = user_grammars::load(config.user_languages_config.as_ref());
+        let file_config = user_grammars::load(config.user_languages_config.as_ref());
+        config.apply_file_config(file_config);
         config.plugins_dir = config
             .plugins_dir_arg
             .clone()
@@ -353,24 +619,32 @@ impl Config {
         config
     }
 
+    /// Takes the user grammars, post-processors and plugin grants of the
+    /// config file. Grants add to those given on the command line.
+    fn apply_file_config(&mut self, file_config: user_grammars::TOMLConfig) {
+        for name in file_config.project_read_plugins() {
+            if !self.plugin_project_read.iter().any(|n| n == name) {
+                self.plugin_project_read.push(name.to_string());
+            }
+        }
+        self.user_grammars = file_config.user_grammars;
+        self.post_processors = file_config.post_processors;
+    }
+
+    /// Starts a [`ConfigBuilder`] for configuring treetags from code rather
+    /// than from process arguments.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
     /// Builds a `Config` with default values for unit tests.
     ///
-    /// Unlike [`Config::new`], this does not read `std::env::args()`, scan
-    /// plugin directories, or load user-grammar config files. Plugin and
-    /// user-grammar sources are forced empty so that language resolution is
-    /// deterministic and independent of the host environment.
+    /// Like [`Config::default`], plugin and user-grammar sources are empty so
+    /// that language resolution is deterministic and independent of the host
+    /// environment.
     #[cfg(test)]
     pub(crate) fn for_test() -> Self {
-        let mut config = Self::try_parse_from(["treetags"]).expect("default args parse");
-        config.sort = true;
-        config.recurse = true;
-        config.append = false;
-        config.extras_config = ExtrasConfig::from_string(&config.extras);
-        config.fields_config = FieldsConfig::from_string(&config.fields);
-        config.user_grammars = Vec::new();
-        config.plugin_dirs = Vec::new();
-        config.plugins_dir = std::path::PathBuf::new();
-        config
+        Self::builder().build()
     }
 
     /// Extracts the `--options` value from raw args without clap.
@@ -504,6 +778,18 @@ impl Config {
         plugin_config::augment_list_kinds_for_completion(cmd, &self.plugin_langs)
     }
 
+    /// Replaces the `--fields` value and the fields enabled from it.
+    pub fn set_fields(&mut self, fields: &str) {
+        self.fields_config = FieldsConfig::from_string(fields);
+        self.fields = fields.to_string();
+    }
+
+    /// Replaces the `--extras` value and the extras enabled from it.
+    pub fn set_extras(&mut self, extras: &str) {
+        self.extras_config = ExtrasConfig::from_string(extras);
+        self.extras = extras.to_string();
+    }
+
     /// Returns the kinds filter string for the given language name (builtin or plugin).
     pub fn get_kinds(&self, lang: &str) -> &str {
         self.kinds_map.get(lang).map(|s| s.as_str()).unwrap_or("")
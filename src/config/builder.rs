@@ -0,0 +1,268 @@
+//! Programmatic construction of a [`Config`] for code linking the `treetags`
+//! crate.
+//!
+//! [`Config::new`] parses the process arguments and reads the user's config
+//! and plugin directories. A [`ConfigBuilder`] starts from the command-line
+//! defaults instead and only uses what it is given: no plugin directory is
+//! scanned and no `config.toml` is loaded unless set on the builder.
+
+use clap::Parser;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::lang_map::{self, LangMapEdit, LangMapEdits};
+use super::user_grammars::{self, UserGrammar};
+use super::{Config, DEFAULT_WORKERS};
+
+/// Builds a [`Config`] without reading process arguments.
+///
+/// ```
+/// let config = treetags::Config::builder()
+///     .fields("+n")
+///     .kinds("rust", "-m")
+///     .langmap("python:+.pyw")
+///     .build();
+/// assert_eq!(config.get_kinds("rust"), "-m");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    fields: String,
+    extras: String,
+    kinds: HashMap<String, String>,
+    langmap: Vec<String>,
+    map_edits: Vec<LangMapEdit>,
+    plugin_dirs: Vec<PathBuf>,
+    plugins_dir: Option<PathBuf>,
+    plugin_project_read: Vec<String>,
+    user_languages_config: Option<PathBuf>,
+    user_grammars: Vec<UserGrammar>,
+    exclude: Vec<String>,
+    language_force: String,
+    workers: usize,
+    sort: bool,
+    recurse: bool,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self {
+            fields: String::new(),
+            extras: String::new(),
+            kinds: HashMap::new(),
+            langmap: Vec::new(),
+            map_edits: Vec::new(),
+            plugin_dirs: Vec::new(),
+            plugins_dir: None,
+            plugin_project_read: Vec::new(),
+            user_languages_config: None,
+            user_grammars: Vec::new(),
+            exclude: Vec::new(),
+            language_force: String::new(),
+            workers: DEFAULT_WORKERS,
+            sort: true,
+            recurse: true,
+        }
+    }
+}
+
+impl ConfigBuilder {
+    /// Extension fields to include, as for `--fields` (e.g. `+nS`).
+    pub fn fields(mut self, fields: &str) -> Self {
+        self.fields = fields.to_string();
+        self
+    }
+
+    /// Extra tag entries to generate, as for `--extras` (e.g. `+q`).
+    pub fn extras(mut self, extras: &str) -> Self {
+        self.extras = extras.to_string();
+        self
+    }
+
+    /// Tag kinds for `lang`, as for `--kinds-<lang>`.
+    pub fn kinds(mut self, lang: &str, kinds: &str) -> Self {
+        self.kinds.insert(lang.to_string(), kinds.to_string());
+        self
+    }
+
+    /// Bulk language map edits, as for `--langmap`. Can be called repeatedly.
+    pub fn langmap(mut self, value: &str) -> Self {
+        self.langmap.push(value.to_string());
+        self
+    }
+
+    /// A single language map edit, the equivalent of one `--map-<LANG>`.
+    /// Applied after every [`langmap`](Self::langmap) value.
+    pub fn map_edit(mut self, edit: LangMapEdit) -> Self {
+        self.map_edits.push(edit);
+        self
+    }
+
+    /// A plugin directory holding `plugin.toml` and the plugin's `.wasm`, as
+    /// for `--plugin-dir`. Can be called repeatedly.
+    pub fn plugin_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.plugin_dirs.push(dir.into());
+        self
+    }
+
+    /// Directory searched recursively for installed plugins, as for
+    /// `--plugins-dir`. Unset by default; pass
+    /// [`paths::get_default_plugins_dir`](super::paths::get_default_plugins_dir)
+    /// to use the plugins installed for the command line tool.
+    pub fn plugins_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.plugins_dir = Some(dir.into());
+        self
+    }
+
+    /// Grants the plugin named `name` read-only access to the project root, as
+    /// for `--plugin-project-read`. Can be called repeatedly.
+    pub fn plugin_project_read(mut self, name: &str) -> Self {
+        self.plugin_project_read.push(name.to_string());
+        self
+    }
+
+    /// Loads user grammars, post-processors and plugin settings from a
+    /// `config.toml`, as for `--user-languages-config`.
+    pub fn user_languages_config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.user_languages_config = Some(path.into());
+        self
+    }
+
+    /// A user grammar, added after those of the config file.
+    pub fn user_grammar(mut self, grammar: UserGrammar) -> Self {
+        self.user_grammars.push(grammar);
+        self
+    }
+
+    /// Pattern of files and directories to skip, as for `--exclude`.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Language used for every file, as for `--language-force`.
+    pub fn language_force(mut self, lang: &str) -> Self {
+        self.language_force = lang.to_string();
+        self
+    }
+
+    /// Number of threads parsing files.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Whether tags are sorted. Defaults to `true`.
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Whether directories are searched recursively. Defaults to `true`.
+    pub fn recurse(mut self, recurse: bool) -> Self {
+        self.recurse = recurse;
+        self
+    }
+
+    /// Resolves the settings into a [`Config`].
+    pub fn build(self) -> Config {
+        let mut config = Config::try_parse_from(["treetags"]).expect("default args parse");
+        config.sort = self.sort;
+        config.recurse = self.recurse;
+        config.append = false;
+        config.workers = self.workers;
+        config.exclude = self.exclude;
+        config.language_force = self.language_force;
+        config.set_extras(&self.extras);
+        config.set_fields(&self.fields);
+        config.kinds_map = self.kinds;
+        config.plugin_project_read = self.plugin_project_read;
+
+        if let Some(path) = &self.user_languages_config {
+            config.apply_file_config(user_grammars::load(Some(path)));
+        }
+        config.user_grammars.extend(self.user_grammars);
+        config.user_languages_config = self.user_languages_config;
+
+        config.plugin_dirs = self.plugin_dirs;
+        config.plugins_dir = self.plugins_dir.clone().unwrap_or_default();
+        config.plugins_dir_arg = self.plugins_dir;
+
+        let mut edits = lang_map::parse_langmap_values(&self.langmap);
+        edits.extend(self.map_edits);
+        config.langmap = self.langmap;
+        config.lang_map_edits = LangMapEdits { edits };
+
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults() {
+        let config = ConfigBuilder::default().build();
+        assert!(config.sort);
+        assert!(config.recurse);
+        assert!(!config.append);
+        assert_eq!(config.workers, DEFAULT_WORKERS);
+        assert!(config.plugin_dirs.is_empty());
+        assert_eq!(config.plugins_dir, PathBuf::new());
+        assert!(config.user_grammars.is_empty());
+        assert!(config.lang_map_edits.edits.is_empty());
+        assert!(config.plugin_project_read.is_empty());
+    }
+
+    #[test]
+    fn test_builder_merges_project_read_grants() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[plugins.java]\nproject_read = true\n\n\
+             [plugins.kotlin]\nproject_read = true\n\n\
+             [plugins.lua]\nproject_read = false\n",
+        )
+        .unwrap();
+        let config = ConfigBuilder::default()
+            .plugin_project_read("java")
+            .plugin_project_read("scala")
+            .user_languages_config(&path)
+            .build();
+        assert_eq!(config.plugin_project_read, ["java", "scala", "kotlin"]);
+    }
+
+    #[test]
+    fn test_builder_settings() {
+        let config = ConfigBuilder::default()
+            .fields("+n")
+            .kinds("rust", "f")
+            .langmap("python:+.pyw")
+            .map_edit(LangMapEdit::RemoveExt {
+                lang: "python".to_string(),
+                ext: "py".to_string(),
+            })
+            .plugin_dir("plugins/echo")
+            .sort(false)
+            .build();
+        assert!(config.fields_config.is_field_enabled("line"));
+        assert_eq!(config.get_kinds("rust"), "f");
+        assert_eq!(config.get_kinds("go"), "");
+        assert_eq!(config.plugin_dirs, vec![PathBuf::from("plugins/echo")]);
+        assert!(!config.sort);
+        assert_eq!(
+            config.lang_map_edits.edits,
+            vec![
+                LangMapEdit::AddExt {
+                    lang: "python".to_string(),
+                    ext: "pyw".to_string(),
+                },
+                LangMapEdit::RemoveExt {
+                    lang: "python".to_string(),
+                    ext: "py".to_string(),
+                },
+            ]
+        );
+    }
+}
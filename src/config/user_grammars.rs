@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -17,19 +18,59 @@ pub struct UserGrammar {
     pub query_file_path: Option<PathBuf>,
 }
 
-#[derive(Debug, Deserialize)]
+/// One step of the tag post-processing pipeline.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PostProcessorConfig {
+    /// Name of an installed post-processor plugin, or the path of its
+    /// directory (relative to the config file) when it contains a `/`.
+    pub plugin: String,
+    /// Free-form arguments handed to the plugin with every file.
+    #[serde(default)]
+    pub args: String,
+    /// Whether the plugin also receives the contents of each file.
+    #[serde(default)]
+    pub source: bool,
+}
+
+/// Settings of one plugin from its `[plugins.<name>]` table.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PluginSettings {
+    /// Grants read-only access to the project root, like
+    /// `--plugin-project-read <name>`.
+    #[serde(default)]
+    pub project_read: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
 pub struct TOMLConfig {
+    #[serde(default)]
     pub user_grammars: Vec<UserGrammar>,
+    /// Post-processors run, in order, on the tags of every file.
+    #[serde(default)]
+    pub post_processors: Vec<PostProcessorConfig>,
+    /// Plugin name to its settings.
+    #[serde(default)]
+    pub plugins: BTreeMap<String, PluginSettings>,
+}
+
+impl TOMLConfig {
+    /// Names of the plugins granted read access to the project root.
+    pub fn project_read_plugins(&self) -> impl Iterator<Item = &str> {
+        self.plugins
+            .iter()
+            .filter(|(_, settings)| settings.project_read)
+            .map(|(name, _)| name.as_str())
+    }
 }
 
-pub fn load(config_path_override: Option<&PathBuf>) -> Vec<UserGrammar> {
+pub fn load(config_path_override: Option<&PathBuf>) -> TOMLConfig {
     let config_path = match config_path_override {
         Some(path) => path.clone(),
         None => get_config_path(),
     };
 
     if !config_path.exists() {
-        return vec![];
+        return TOMLConfig::default();
     }
 
     match fs::read_to_string(&config_path) {
@@ -42,7 +83,7 @@ pub fn load(config_path_override: Option<&PathBuf>) -> Vec<UserGrammar> {
                         config_path.display(),
                         e
                     );
-                    return vec![];
+                    return TOMLConfig::default();
                 }
             };
 
@@ -53,9 +94,16 @@ pub fn load(config_path_override: Option<&PathBuf>) -> Vec<UserGrammar> {
                         absolutize_path(config_dir, query_path);
                     }
                 }
+                for step in &mut toml_config.post_processors {
+                    if step.plugin.contains('/') {
+                        let mut path = PathBuf::from(&step.plugin);
+                        absolutize_path(config_dir, &mut path);
+                        step.plugin = path.to_string_lossy().into_owned();
+                    }
+                }
             }
 
-            toml_config.user_grammars
+            toml_config
         }
         Err(e) => {
             eprintln!(
@@ -63,7 +111,7 @@ pub fn load(config_path_override: Option<&PathBuf>) -> Vec<UserGrammar> {
                 config_path.display(),
                 e
             );
-            vec![]
+            TOMLConfig::default()
         }
     }
 }
@@ -0,0 +1,123 @@
+//! `--interactive`: a long-running mode answering JSON requests on stdin, so
+//! editor plugins that retag a buffer on every refresh load the grammars and
+//! JIT-compile the WASM plugins once instead of on every run.
+//!
+//! Every line of stdin is one request. The first output line describes the
+//! program and each request is answered by one JSON line per tag followed by
+//! a `completed` line, or by a single `error` line.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufWriter, Write};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::tag_processor::SourceTagger;
+use crate::tag_record::TagRecord;
+
+#[derive(Deserialize)]
+struct Request {
+    command: String,
+    /// File the tags are attributed to; read from disk unless `content` is
+    /// given.
+    filename: Option<String>,
+    content: Option<String>,
+    /// Overrides `--fields` for this request.
+    fields: Option<String>,
+    /// Overrides `--extras` for this request.
+    extras: Option<String>,
+    /// Overrides `--kinds-<LANG>` for this request, keyed by language.
+    #[serde(default)]
+    kinds: HashMap<String, String>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "_type", rename_all = "lowercase")]
+enum Output<'a> {
+    Program { name: &'a str, version: &'a str },
+    Tag(&'a TagRecord),
+    Completed { command: &'a str },
+    Error { message: String },
+}
+
+/// Answers requests until stdin is closed.
+pub fn run(config: &Config) -> anyhow::Result<()> {
+    let mut tagger = SourceTagger::new(config);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    emit(
+        &mut out,
+        &Output::Program {
+            name: "treetags",
+            version: env!("CARGO_PKG_VERSION"),
+        },
+    )?;
+    out.flush()?;
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answered = serde_json::from_str::<Request>(&line)
+            .context("invalid request")
+            .and_then(|request| match request.command.as_str() {
+                "generate" => {
+                    generate(&mut tagger, config, &request, &mut out).map(|()| Output::Completed {
+                        command: "generate",
+                    })
+                }
+                command => bail!("unknown command `{command}`"),
+            });
+        let last = answered.unwrap_or_else(|err| Output::Error {
+            message: format!("{err:#}"),
+        });
+        emit(&mut out, &last)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes the tags of one file.
+fn generate(
+    tagger: &mut SourceTagger,
+    config: &Config,
+    request: &Request,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let Some(filename) = &request.filename else {
+        bail!("generate needs a filename");
+    };
+    let code = match &request.content {
+        Some(content) => content.as_bytes().to_vec(),
+        None => fs::read(filename).with_context(|| format!("cannot read {filename}"))?,
+    };
+
+    let mut config = config.clone();
+    if let Some(fields) = &request.fields {
+        config.set_fields(fields);
+    }
+    if let Some(extras) = &request.extras {
+        config.set_extras(extras);
+    }
+    config.kinds_map.extend(request.kinds.clone());
+
+    let Some((lang, tags)) = tagger.tag(filename, &code, &config) else {
+        return Ok(());
+    };
+    let parser = tagger.registry().parser(lang);
+    let kinds = parser.kinds();
+    for tag in &tags {
+        let mut record = TagRecord::from(tag);
+        record.set_language(parser.language_name(), &kinds);
+        emit(out, &Output::Tag(&record))?;
+    }
+    Ok(())
+}
+
+fn emit(out: &mut impl Write, output: &Output) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *out, output)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
@@ -1,8 +1,9 @@
 //! Pure helpers for language resolution.
 //!
-//! Currently hosts the filename-pattern (glob) matcher used to map names like
-//! `Makefile`, `Rakefile`, or `*.gemspec` to a language. Kept free of registry
-//! state so it can be unit-tested in isolation.
+//! Hosts the filename-pattern (glob) matcher used to map names like
+//! `Makefile`, `Rakefile`, or `*.gemspec` to a language, and the content
+//! scorers used to pick between languages sharing a name. Kept free of
+//! registry state so it can be unit-tested in isolation.
 
 /// Matches a filename against an `fnmatch`-style glob pattern.
 ///
@@ -136,31 +137,136 @@ fn basename(s: &str) -> &str {
     s.rsplit(['/', '\\']).next().unwrap_or(s)
 }
 
+/// High-precision C++-only signals, chosen to rarely appear in plain C or in
+/// prose comments.
+const CPP_SIGNALS: &[&str] = &[
+    "::",
+    "namespace",
+    "template<",
+    "template <",
+    "class ",
+    "public:",
+    "private:",
+    "protected:",
+    "virtual ",
+    "nullptr",
+    "using namespace",
+    "extern \"C\"",
+    "std::",
+];
+
 /// Heuristically decides whether a header's content is C++ rather than C, for
 /// disambiguating ambiguous extensions like `.h`.
 ///
 /// Looks for high-precision C++-only signals in a bounded content prefix.
 /// Returns `false` (i.e. "assume C") when no signal is present — matching
-/// ctags, which defaults `.h` to C. The signals are chosen to rarely appear in
-/// plain C or in prose comments; this is a heuristic, not a parser.
+/// ctags, which defaults `.h` to C. This is a heuristic, not a parser.
 pub fn looks_like_cpp(prefix: &[u8]) -> bool {
-    const SIGNALS: &[&str] = &[
-        "::",
-        "namespace",
-        "template<",
-        "template <",
-        "class ",
-        "public:",
-        "private:",
-        "protected:",
-        "virtual ",
-        "nullptr",
-        "using namespace",
-        "extern \"C\"",
-        "std::",
-    ];
     let text = String::from_utf8_lossy(prefix);
-    SIGNALS.iter().any(|sig| text.contains(sig))
+    CPP_SIGNALS.iter().any(|sig| text.contains(sig))
+}
+
+/// Content signals of languages that commonly share extensions with another
+/// one (`.m`, `.pl`, `.v`, `.h`), keyed by the language names and aliases
+/// plugins are likely to use. A signal starting with `\n` only matches at the
+/// start of a line.
+const SCORERS: &[(&[&str], &[&str])] = &[
+    (&["c++", "cpp"], CPP_SIGNALS),
+    (
+        &["objective-c", "objectivec", "objc"],
+        &[
+            "@interface",
+            "@implementation",
+            "@protocol",
+            "@property",
+            "@end",
+            "\n#import",
+            "NSString",
+            "[super ",
+        ],
+    ),
+    (
+        &["matlab", "octave"],
+        &[
+            "\n%%",
+            "\n% ",
+            "\nfunction ",
+            "disp(",
+            "fprintf(",
+            "zeros(",
+            "numel(",
+            "nargin",
+        ],
+    ),
+    (
+        &["perl"],
+        &[
+            "use strict",
+            "use warnings",
+            "my $",
+            "my @",
+            "my %",
+            "\nsub ",
+            "=~",
+            "\n__END__",
+        ],
+    ),
+    (
+        &["prolog"],
+        &[
+            "\n:- module(",
+            "\n:- use_module(",
+            "\n:- dynamic",
+            ":-\n",
+            ") :- ",
+            "\n?- ",
+        ],
+    ),
+    (
+        &["verilog", "systemverilog"],
+        &[
+            "\nmodule ",
+            "endmodule",
+            "always @",
+            "\nassign ",
+            "posedge",
+            "negedge",
+            "\n`timescale",
+            "\n`define",
+        ],
+    ),
+    (
+        &["coq", "rocq"],
+        &[
+            "Require Import",
+            "\nTheorem ",
+            "\nLemma ",
+            "\nProof.",
+            "Qed.",
+            "\nDefinition ",
+            "\nInductive ",
+            "\nFixpoint ",
+        ],
+    ),
+];
+
+/// Scores how much `prefix`, the head of a file claimed by several languages,
+/// looks like `lang`: 10 per distinct signal found, up to 100. Returns `None`
+/// for languages without builtin signals.
+///
+/// C has no signals of its own: it scores 1 unless the content looks like
+/// C++, so it wins only when no other candidate finds evidence — matching
+/// ctags, which defaults `.h` to C.
+pub fn builtin_score(lang: &str, prefix: &[u8]) -> Option<u32> {
+    let lang = lang.to_lowercase();
+    if lang == "c" {
+        return Some(u32::from(!looks_like_cpp(prefix)));
+    }
+    let (_, signals) = SCORERS.iter().find(|(names, _)| names.contains(&&*lang))?;
+    // A leading newline lets line-start signals match on the first line.
+    let text = format!("\n{}", String::from_utf8_lossy(prefix));
+    let found = signals.iter().filter(|sig| text.contains(*sig)).count() as u32;
+    Some((found * 10).min(100))
 }
 
 /// Extracts a language/mode name from an editor modeline in the file's head or
@@ -318,7 +424,7 @@ fn parse_emacs_local_vars(tail: &str) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{glob_match, looks_like_cpp, parse_modeline, parse_shebang};
+    use super::{builtin_score, glob_match, looks_like_cpp, parse_modeline, parse_shebang};
 
     #[test]
     fn exact_and_literal() {
@@ -458,6 +564,31 @@ mod tests {
         assert!(!looks_like_cpp(b"typedef struct { int x; } Point;"));
     }
 
+    #[test]
+    fn builtin_scores() {
+        let objc = b"#import <Foundation/Foundation.h>\n@interface Foo : NSObject\n@end\n";
+        let matlab = b"% Compute the mean\nfunction m = avg(x)\n  m = sum(x) / numel(x);\nend\n";
+        assert!(builtin_score("Objective-C", objc) > builtin_score("matlab", objc));
+        assert!(builtin_score("matlab", matlab) > builtin_score("objc", matlab));
+
+        let perl = b"use strict;\nmy $x = shift;\nprint $x if $x =~ /a/;\n";
+        let prolog = b":- module(app, [run/0]).\nrun :- write(hello).\n";
+        assert!(builtin_score("perl", perl) > builtin_score("prolog", perl));
+        assert!(builtin_score("prolog", prolog) > builtin_score("perl", prolog));
+
+        let verilog = b"module top(input clk);\n  always @(posedge clk) begin end\nendmodule\n";
+        let coq =
+            b"Require Import Arith.\nLemma plus_0 : forall n, n + 0 = n.\nProof. auto. Qed.\n";
+        assert!(builtin_score("verilog", verilog) > builtin_score("coq", verilog));
+        assert!(builtin_score("coq", coq) > builtin_score("verilog", coq));
+
+        // C is the fallback unless the content looks like C++.
+        assert_eq!(builtin_score("c", b"int add(int a, int b);"), Some(1));
+        assert_eq!(builtin_score("c", b"namespace app {}"), Some(0));
+        assert_eq!(builtin_score("c++", b"int add(int a, int b);"), Some(0));
+        assert_eq!(builtin_score("rust", b"fn main() {}"), None);
+    }
+
     #[test]
     fn shebang_edge_cases() {
         assert_eq!(parse_shebang(b"#! /bin/sh\n").as_deref(), Some("sh"));
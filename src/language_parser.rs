@@ -44,6 +44,14 @@ pub struct LangSource {
 // Core trait
 // ---------------------------------------------------------------------------
 
+/// A source file handed to `LanguageParser::generate_tags_batch`.
+pub struct SourceFile<'a> {
+    pub code: &'a [u8],
+    /// Path written to the tags file (relative to the tag file's directory).
+    pub path: &'a str,
+    pub absolute_path: &'a Path,
+}
+
 /// Strategy for generating tags for a specific language.
 ///
 /// Implementations are stateless (or hold only configuration) and are shared
@@ -62,11 +70,38 @@ pub trait LanguageParser: Send + Sync {
         absolute_path: &Path,
     ) -> Vec<Tag>;
 
+    /// Whether `TagProcessor` should group files for this language into
+    /// batches for `generate_tags_batch` instead of parsing them one by one.
+    fn supports_batch(&self) -> bool {
+        false
+    }
+
+    /// Generate tags for several files at once. The default makes one
+    /// `generate_tags` call per file.
+    fn generate_tags_batch(
+        &self,
+        parser: &mut Parser,
+        files: &[SourceFile<'_>],
+        config: &Config,
+    ) -> Vec<Tag> {
+        files
+            .iter()
+            .flat_map(|f| self.generate_tags(parser, f.code, f.path, config, f.absolute_path))
+            .collect()
+    }
+
     /// Kind metadata for `--list-kinds`. Never requires a `Parser`.
     fn kinds(&self) -> Vec<KindInfo>;
 
     /// Canonical language name, matching the `--kinds-{lang}` CLI argument.
     fn language_name(&self) -> &str;
+
+    /// Scores how much `prefix`, the head of a file claimed by several
+    /// languages, looks like this language (0–100). `None` leaves scoring to
+    /// the builtin heuristics for the language name.
+    fn score(&self, _prefix: &[u8]) -> Option<u32> {
+        None
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -76,6 +111,8 @@ pub trait LanguageParser: Send + Sync {
 #[derive(Clone)]
 pub(crate) struct BuiltinLanguageParser {
     lang: &'static str,
+    /// The `--kinds-<lang>` value `kind_config` was parsed from.
+    kinds: String,
     kind_config: TagKindConfig,
     kind_defaults: &'static [(&'static [&'static str], &'static str)],
     kind_optionals: &'static [(&'static [&'static str], &'static str)],
@@ -89,6 +126,7 @@ impl BuiltinLanguageParser {
             TagKindConfig::from_string(kinds_str, desc.kind_defaults, desc.kind_optionals);
         Self {
             lang: desc.lang,
+            kinds: kinds_str.to_string(),
             kind_config,
             kind_defaults: desc.kind_defaults,
             kind_optionals: desc.kind_optionals,
@@ -106,7 +144,17 @@ impl LanguageParser for BuiltinLanguageParser {
         config: &Config,
         _absolute_path: &Path,
     ) -> Vec<Tag> {
-        (self.generate_fn)(&mut parser.ts_parser, code, path, &self.kind_config, config)
+        // A registry kept alive across requests may be handed a config with
+        // other kinds than the one it was built from.
+        let kinds = config.get_kinds(self.lang);
+        let fresh;
+        let kind_config = if kinds == self.kinds {
+            &self.kind_config
+        } else {
+            fresh = TagKindConfig::from_string(kinds, self.kind_defaults, self.kind_optionals);
+            &fresh
+        };
+        (self.generate_fn)(&mut parser.ts_parser, code, path, kind_config, config)
             .unwrap_or_default()
     }
 
@@ -124,9 +172,15 @@ impl LanguageParser for BuiltinLanguageParser {
 // ---------------------------------------------------------------------------
 
 pub(crate) struct WasmLanguageParser {
-    extension: String,
+    /// Name of the plugin, which routes files to it in the `PluginRegistry`.
+    plugin: String,
     lang: String,
     kind_infos: Vec<KindInfo>,
+    /// Declarative (grammar + query) plugins run in-process on the thread's
+    /// tags context and are never batched.
+    declarative: bool,
+    /// Used to score ambiguous files with the plugin's `detect` export.
+    registry: Arc<PluginRegistry>,
 }
 
 impl LanguageParser for WasmLanguageParser {
@@ -138,11 +192,29 @@ impl LanguageParser for WasmLanguageParser {
         config: &Config,
         absolute_path: &Path,
     ) -> Vec<Tag> {
+        if self.declarative {
+            return parser
+                .try_declarative_plugin(&self.plugin, code, path, config)
+                .unwrap_or_default();
+        }
         parser
-            .try_plugin(&self.extension, code, path, config, absolute_path)
+            .try_plugin(&self.plugin, code, path, config, absolute_path)
             .unwrap_or_default()
     }
 
+    fn supports_batch(&self) -> bool {
+        !self.declarative
+    }
+
+    fn generate_tags_batch(
+        &self,
+        parser: &mut Parser,
+        files: &[SourceFile<'_>],
+        config: &Config,
+    ) -> Vec<Tag> {
+        parser.try_plugin_batch(&self.plugin, files, config)
+    }
+
     fn kinds(&self) -> Vec<KindInfo> {
         self.kind_infos.clone()
     }
@@ -150,6 +222,13 @@ impl LanguageParser for WasmLanguageParser {
     fn language_name(&self) -> &str {
         &self.lang
     }
+
+    fn score(&self, prefix: &[u8]) -> Option<u32> {
+        if self.declarative {
+            return None;
+        }
+        self.registry.score(&self.plugin, prefix)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -195,10 +274,8 @@ pub type LangId = usize;
 /// Outcome of matching a file to languages by name (extension/pattern), before
 /// any content-based disambiguation.
 ///
-/// Today only extension matching populates this, and every extension resolves
-/// to at most one candidate, so `Ambiguous` is not yet produced. Later phases
-/// (filename patterns, and co-registering `.h` for both C and C++) will start
-/// returning `Ambiguous`, which the worker resolves via selectors.
+/// `Ambiguous` results (`.h` for C and C++, plugins sharing an extension,
+/// overlapping patterns) are resolved by the worker via `disambiguate`.
 pub enum NameResolution {
     /// Exactly one language matched.
     Unique(LangId),
@@ -281,7 +358,9 @@ impl LanguageParserRegistry {
             &config.plugin_dirs,
             Some(&config.plugins_dir),
             &config.plugin_cache,
+            &config.plugin_project_read,
         ));
+        plugin_registry.prepare_cache(config.plugin_cache_max_size);
 
         let mut parsers: Vec<Box<dyn LanguageParser>> = Vec::new();
         let mut by_extension: HashMap<String, Vec<LangId>> = HashMap::new();
@@ -297,21 +376,19 @@ impl LanguageParserRegistry {
         let mut interp_specs: Vec<(LangId, String)> = Vec::new();
 
         // Priorities are applied in order; the first tier to claim an extension
-        // owns it (matching the historical `or_insert_with` behaviour). Storing
-        // `Vec<LangId>` leaves room for later phases to register several
-        // candidates per extension and disambiguate by content.
+        // owns it (matching the historical `or_insert_with` behaviour). Within
+        // the plugin tier several plugins may claim an extension; they all
+        // become candidates, disambiguated by content.
 
         // Priority 1: WASM plugins
         let plugin_infos = scan_ext_infos(&config.plugin_dirs, Some(&config.plugins_dir));
         let mut plugin_src_idx: HashMap<String, usize> = HashMap::new();
         for info in plugin_infos {
             let lang = info.lang.clone().unwrap_or_else(|| info.ext.clone());
-            let claimed = !by_extension.contains_key(&info.ext);
             if !info.internal {
                 match plugin_src_idx.get(&lang) {
                     Some(&si) => {
                         sources[si].extensions.push(info.ext.clone());
-                        sources[si].won |= claimed;
                     }
                     None => {
                         plugin_src_idx.insert(lang.clone(), sources.len());
@@ -319,14 +396,11 @@ impl LanguageParserRegistry {
                             kind: SourceKind::Plugin,
                             name: lang.clone(),
                             extensions: vec![info.ext.clone()],
-                            won: claimed,
+                            won: true,
                         });
                     }
                 }
             }
-            if !claimed {
-                continue;
-            }
             let kind_infos: Vec<KindInfo> = info
                 .kinds
                 .into_iter()
@@ -347,11 +421,13 @@ impl LanguageParserRegistry {
                 interp_specs.push((id, interp.clone()));
             }
             parsers.push(Box::new(WasmLanguageParser {
-                extension: info.ext.clone(),
+                plugin: info.plugin,
                 lang,
                 kind_infos,
+                declarative: info.declarative,
+                registry: Arc::clone(&plugin_registry),
             }));
-            by_extension.insert(info.ext, vec![id]);
+            by_extension.entry(info.ext).or_default().push(id);
         }
 
         // Priority 2: Builtin tree-walker parsers.
@@ -666,27 +742,25 @@ impl LanguageParserRegistry {
         None
     }
 
-    /// Picks a single language from an ambiguous candidate set using a
-    /// content-based selector. Returns `None` when no selector applies to the
-    /// candidate group, leaving the caller to fall back to the highest-priority
-    /// candidate.
-    ///
-    /// `prefix` is a bounded head of the file's content.
+    /// Picks a single language from an ambiguous candidate set by scoring
+    /// `prefix`, a bounded head of the file's content, for each candidate: with
+    /// the plugin's `detect` export when it has one, else with the builtin
+    /// scorer for its language name. The highest score wins, ties going to the
+    /// higher-priority candidate. Returns `None` when every candidate scores 0,
+    /// leaving the caller to fall back to the highest-priority candidate.
     pub fn disambiguate(&self, cands: &[LangId], prefix: &[u8]) -> Option<LangId> {
-        let name_of = |id: LangId| self.parsers[id].language_name();
-        let has = |name: &str| cands.iter().any(|&id| name_of(id) == name);
-
-        // C vs C++ (e.g. a `.h` header): default to C, choose C++ on evidence.
-        if has("c") && has("c++") {
-            let want = if crate::lang_resolve::looks_like_cpp(prefix) {
-                "c++"
-            } else {
-                "c"
-            };
-            return cands.iter().copied().find(|&id| name_of(id) == want);
+        let mut best: Option<(LangId, u32)> = None;
+        for &id in cands {
+            let parser = &self.parsers[id];
+            let score = parser
+                .score(prefix)
+                .or_else(|| crate::lang_resolve::builtin_score(parser.language_name(), prefix))
+                .unwrap_or(0);
+            if score > best.map_or(0, |(_, s)| s) {
+                best = Some((id, score));
+            }
         }
-
-        None
+        best.map(|(id, _)| id)
     }
 
     /// Resolves a language name or alias (case-insensitive) to a `LangId`.
@@ -6,14 +6,16 @@ in the various official language parsers to detect tags.
 
 ## Usage
 
-```rust,compile_fail
-use treetags::Parser;
+```rust,no_run
+use treetags::Config;
 
-let file_path_relative_to_tag_file = "path/to/source_file.rs"; // file path relative to tag file
-let extension = "rs";
-let file_path_str = "source.file"; // file path relative to current directory
-let mut parser = Parser::new();
-let tags = parser.parse_file(&file_path_relative_to_tag_file, &file_path, extension);
+let config = Config::builder()
+    .fields("+n")
+    .kinds("rust", "-m")
+    .build();
+for tag in treetags::generate(&["src"], &config) {
+    println!("{}\t{}", tag.name, tag.file_name);
+}
 ```
  */
 
@@ -23,19 +25,31 @@ pub mod config;
 pub mod file_finder;
 pub mod lang_resolve;
 pub mod language_parser;
+pub mod outline;
 pub mod parser;
 pub mod plugin;
 pub mod queries;
+#[cfg(feature = "scip")]
+pub mod scip;
 pub mod shell_to_regex;
 pub mod split_by_newlines;
 pub mod tag;
+#[cfg(feature = "sqlite")]
+pub mod tag_db;
+pub mod tag_lookup;
 pub mod tag_processor;
+pub mod tag_record;
 pub mod tag_writer;
 pub mod tags_config;
 pub mod user_grammars;
 
 // Re-export commonly used items
-pub use config::Config;
+pub use config::{Config, ConfigBuilder};
 pub use language_parser::LanguageParserRegistry;
 pub use parser::Parser;
 pub use tag::{parse_tag_file, Tag};
+#[cfg(feature = "sqlite")]
+pub use tag_db::TagDatabase;
+pub use tag_lookup::{lookup, TagQuery};
+pub use tag_processor::{generate, generate_source, SourceTagger};
+pub use tag_record::{Scope, TagRecord};
@@ -0,0 +1,112 @@
+//! The tags of the workspace, kept per file so a single file can be retagged
+//! when its buffer changes.
+
+use std::collections::BTreeMap;
+
+use crate::tag_record::TagRecord;
+
+/// Most symbols returned for one `workspace/symbol` request.
+const MAX_WORKSPACE_SYMBOLS: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct TagIndex {
+    /// Tags per file, keyed by the path the tags name.
+    files: BTreeMap<String, Vec<TagRecord>>,
+}
+
+impl TagIndex {
+    /// Replaces the tags of `path`. An empty list drops the file.
+    pub fn set_file(&mut self, path: &str, records: Vec<TagRecord>) {
+        if records.is_empty() {
+            self.files.remove(path);
+        } else {
+            self.files.insert(path.to_string(), records);
+        }
+    }
+
+    pub fn file(&self, path: &str) -> &[TagRecord] {
+        self.files.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.values().map(Vec::len).sum()
+    }
+
+    /// Tags whose name contains `query`, ignoring case. An empty query matches
+    /// every tag.
+    pub fn workspace_symbols(&self, query: &str) -> Vec<&TagRecord> {
+        let query = query.to_lowercase();
+        self.files
+            .values()
+            .flatten()
+            .filter(|record| query.is_empty() || record.name.to_lowercase().contains(&query))
+            .take(MAX_WORKSPACE_SYMBOLS)
+            .collect()
+    }
+
+    /// Tags named exactly `name`, those of `current_file` first.
+    pub fn definitions(&self, name: &str, current_file: &str) -> Vec<&TagRecord> {
+        let mut found: Vec<&TagRecord> = self
+            .files
+            .values()
+            .flatten()
+            .filter(|record| record.name == name)
+            .collect();
+        found.sort_by_key(|record| record.path != current_file);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, path: &str) -> TagRecord {
+        TagRecord::new(name.to_string(), path)
+    }
+
+    #[test]
+    fn test_set_file_replaces_and_drops() {
+        let mut index = TagIndex::default();
+        index.set_file(
+            "a.rs",
+            vec![record("area", "a.rs"), record("Circle", "a.rs")],
+        );
+        index.set_file("b.rs", vec![record("area", "b.rs")]);
+        assert_eq!(index.len(), 3);
+
+        index.set_file("a.rs", vec![record("perimeter", "a.rs")]);
+        assert_eq!(index.file("a.rs")[0].name, "perimeter");
+        index.set_file("b.rs", Vec::new());
+        assert!(index.file("b.rs").is_empty());
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn test_symbol_and_definition_lookup() {
+        let mut index = TagIndex::default();
+        index.set_file(
+            "a.rs",
+            vec![record("area", "a.rs"), record("Circle", "a.rs")],
+        );
+        index.set_file(
+            "b.rs",
+            vec![record("area", "b.rs"), record("AreaOf", "b.rs")],
+        );
+
+        let names: Vec<_> = index
+            .workspace_symbols("AREA")
+            .iter()
+            .map(|r| format!("{}@{}", r.name, r.path))
+            .collect();
+        assert_eq!(names, ["area@a.rs", "area@b.rs", "AreaOf@b.rs"]);
+        assert_eq!(index.workspace_symbols("").len(), 4);
+
+        let paths: Vec<_> = index
+            .definitions("area", "b.rs")
+            .iter()
+            .map(|r| r.path.as_str())
+            .collect();
+        assert_eq!(paths, ["b.rs", "a.rs"]);
+    }
+}
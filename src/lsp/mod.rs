@@ -0,0 +1,373 @@
+//! `treetags lsp`: a language server over stdio answering symbol, definition
+//! and folding requests from tags.
+//!
+//! The workspace is tagged once at startup with [`TagProcessor`]. Open files
+//! are retagged with a [`SourceTagger`] from their in-memory buffer on every
+//! change and save, and from disk when closed, so answers follow unsaved
+//! edits.
+
+mod index;
+mod symbols;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _,
+};
+use lsp_types::request::{
+    DocumentSymbolRequest, FoldingRangeRequest, GotoDefinition, Request as _,
+    WorkspaceSymbolRequest,
+};
+use lsp_types::{
+    DocumentSymbolParams, DocumentSymbolResponse, FoldingRangeParams,
+    FoldingRangeProviderCapability, GotoDefinitionParams, GotoDefinitionResponse, InitializeParams,
+    InitializeResult, Location, OneOf, ServerCapabilities, ServerInfo, SymbolInformation,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url, WorkspaceSymbolParams, WorkspaceSymbolResponse,
+};
+use serde::de::DeserializeOwned;
+
+use crate::config::Config;
+use crate::file_finder::FileFinder;
+use crate::language_parser::LangId;
+use crate::parser::KindInfo;
+use crate::tag::Tag;
+use crate::tag_processor::{self, SourceTagger, TagProcessor};
+use crate::tag_record::TagRecord;
+use index::TagIndex;
+
+/// Runs the server on stdin/stdout until the client sends `exit`.
+pub fn run(config: &Config) -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let (id, params) = connection.initialize_start()?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let root = workspace_root(&params)?;
+    let result = InitializeResult {
+        capabilities: capabilities(),
+        server_info: Some(ServerInfo {
+            name: "treetags".to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }),
+    };
+    connection.initialize_finish(id, serde_json::to_value(result)?)?;
+
+    // Tag names are relative to the working directory, as for a tags file
+    // written at the workspace root.
+    std::env::set_current_dir(&root)
+        .with_context(|| format!("cannot enter workspace {}", root.display()))?;
+    let mut server = Server::new(root, config);
+    server.index_workspace();
+    server.serve(&connection)?;
+
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn workspace_root(params: &InitializeParams) -> anyhow::Result<PathBuf> {
+    #[allow(deprecated)]
+    let uri = params
+        .workspace_folders
+        .as_ref()
+        .and_then(|folders| folders.first())
+        .map(|folder| &folder.uri)
+        .or(params.root_uri.as_ref());
+    match uri.and_then(|uri| uri.to_file_path().ok()) {
+        Some(root) => Ok(root),
+        None => Ok(std::env::current_dir()?),
+    }
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..TextDocumentSyncOptions::default()
+            },
+        )),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+struct Server {
+    root: PathBuf,
+    config: Config,
+    processor: TagProcessor,
+    /// Retags single files with parsers kept loaded between edits.
+    tagger: SourceTagger,
+    index: TagIndex,
+    /// Text of the open documents, keyed by tag path.
+    buffers: HashMap<String, String>,
+}
+
+impl Server {
+    fn new(root: PathBuf, config: &Config) -> Self {
+        let mut config = config.clone();
+        // Symbols need both ends of a definition.
+        for field in ["line", "end"] {
+            config
+                .fields_config
+                .enabled_fields
+                .insert(field.to_string());
+        }
+        let processor = TagProcessor::new("-".to_string(), config.workers, config.clone());
+        let tagger = SourceTagger::new(&config);
+        Self {
+            root,
+            config,
+            processor,
+            tagger,
+            index: TagIndex::default(),
+            buffers: HashMap::new(),
+        }
+    }
+
+    fn index_workspace(&mut self) {
+        let files = match FileFinder::from_patterns(self.config.exclude.clone(), true) {
+            Ok(finder) => {
+                let result = finder.get_files_from_paths(&[".".to_string()]);
+                result.print_errors();
+                result.files
+            }
+            Err(err) => {
+                eprintln!("treetags: {err}");
+                Vec::new()
+            }
+        };
+        let mut by_file: HashMap<String, Vec<Tag>> = HashMap::new();
+        for tag in self.processor.process_files(files) {
+            by_file
+                .entry(tag.file_name.to_string())
+                .or_default()
+                .push(tag);
+        }
+        for (path, tags) in by_file {
+            let language = tag_processor::select_language(
+                self.tagger.registry(),
+                &self.config,
+                &self.root.join(&path),
+                Path::new(&path),
+            )
+            .map(|selection| selection.lang);
+            let records = self.records(&tags, language);
+            self.index.set_file(&path, records);
+        }
+        eprintln!("treetags: indexed {} tags", self.index.len());
+    }
+
+    /// Retags `path` from its open buffer, or from disk when it is not open.
+    fn retag(&mut self, path: &str) {
+        let code = match self.buffers.get(path) {
+            Some(text) => text.clone().into_bytes(),
+            None => fs::read(self.root.join(path)).unwrap_or_default(),
+        };
+        let (language, tags) = match self.tagger.tag(path, &code, &self.config) {
+            Some((lang, tags)) => (Some(lang), tags),
+            None => (None, Vec::new()),
+        };
+        let records = self.records(&tags, language);
+        self.index.set_file(path, records);
+    }
+
+    fn records(&self, tags: &[Tag], language: Option<LangId>) -> Vec<TagRecord> {
+        let described: Option<(&str, Vec<KindInfo>)> = language.map(|lang| {
+            let parser = self.tagger.registry().parser(lang);
+            (parser.language_name(), parser.kinds())
+        });
+        tags.iter()
+            .map(|tag| {
+                let mut record = TagRecord::from(tag);
+                if let Some((language, kinds)) = &described {
+                    record.set_language(language, kinds);
+                }
+                record
+            })
+            .collect()
+    }
+
+    /// The path tags use for the document at `uri`.
+    fn tag_path(&self, uri: &Url) -> Option<String> {
+        let path = uri.to_file_path().ok()?;
+        let path = path.strip_prefix(&self.root).unwrap_or(&path);
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    fn location(&self, record: &TagRecord) -> Option<Location> {
+        let uri = Url::from_file_path(self.root.join(&record.path)).ok()?;
+        Some(Location::new(uri, symbols::name_range(record)))
+    }
+
+    fn text(&self, path: &str) -> String {
+        match self.buffers.get(path) {
+            Some(text) => text.clone(),
+            None => fs::read_to_string(self.root.join(path)).unwrap_or_default(),
+        }
+    }
+
+    fn serve(&mut self, connection: &Connection) -> anyhow::Result<()> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification),
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            WorkspaceSymbolRequest::METHOD => {
+                params(request).map(|p| to_value(self.workspace_symbol(p)))
+            }
+            DocumentSymbolRequest::METHOD => {
+                params(request).map(|p| to_value(self.document_symbol(p)))
+            }
+            GotoDefinition::METHOD => params(request).map(|p| to_value(self.definition(p))),
+            FoldingRangeRequest::METHOD => params(request).map(|p| to_value(self.folding_range(p))),
+            method => Err((
+                ErrorCode::MethodNotFound,
+                format!("unsupported request {method}"),
+            )),
+        };
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = notification
+                    .extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)
+                else {
+                    return;
+                };
+                if let Some(path) = self.tag_path(&params.text_document.uri) {
+                    self.buffers.insert(path.clone(), params.text_document.text);
+                    self.retag(&path);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(mut params) = notification
+                    .extract::<lsp_types::DidChangeTextDocumentParams>(
+                        DidChangeTextDocument::METHOD,
+                    )
+                else {
+                    return;
+                };
+                // Full sync: the last change holds the whole text.
+                let (Some(path), Some(change)) = (
+                    self.tag_path(&params.text_document.uri),
+                    params.content_changes.pop(),
+                ) else {
+                    return;
+                };
+                self.buffers.insert(path.clone(), change.text);
+                self.retag(&path);
+            }
+            DidSaveTextDocument::METHOD => {
+                let Ok(params) = notification
+                    .extract::<lsp_types::DidSaveTextDocumentParams>(DidSaveTextDocument::METHOD)
+                else {
+                    return;
+                };
+                if let Some(path) = self.tag_path(&params.text_document.uri) {
+                    if let Some(text) = params.text {
+                        self.buffers.insert(path.clone(), text);
+                    }
+                    self.retag(&path);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = notification
+                    .extract::<lsp_types::DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)
+                else {
+                    return;
+                };
+                if let Some(path) = self.tag_path(&params.text_document.uri) {
+                    self.buffers.remove(&path);
+                    self.retag(&path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn workspace_symbol(&self, params: WorkspaceSymbolParams) -> WorkspaceSymbolResponse {
+        let symbols = self
+            .index
+            .workspace_symbols(&params.query)
+            .into_iter()
+            .filter_map(|record| {
+                #[allow(deprecated)]
+                Some(SymbolInformation {
+                    name: record.name.clone(),
+                    kind: symbols::symbol_kind(record),
+                    tags: None,
+                    deprecated: None,
+                    location: self.location(record)?,
+                    container_name: record.scope.as_ref().map(|scope| scope.name.clone()),
+                })
+            })
+            .collect();
+        WorkspaceSymbolResponse::Flat(symbols)
+    }
+
+    fn document_symbol(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let path = self.tag_path(&params.text_document.uri)?;
+        let records = self.index.file(&path);
+        Some(DocumentSymbolResponse::Nested(symbols::document_symbols(
+            records,
+            &self.text(&path),
+        )))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params.position;
+        let path = self.tag_path(&params.text_document_position_params.text_document.uri)?;
+        let text = self.text(&path);
+        let line = text.lines().nth(position.line as usize)?;
+        let name = symbols::word_at(line, position.character)?;
+        let locations: Vec<Location> = self
+            .index
+            .definitions(name, &path)
+            .into_iter()
+            .filter_map(|record| self.location(record))
+            .collect();
+        (!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations))
+    }
+
+    fn folding_range(&self, params: FoldingRangeParams) -> Option<Vec<lsp_types::FoldingRange>> {
+        let path = self.tag_path(&params.text_document.uri)?;
+        Some(symbols::folding_ranges(self.index.file(&path)))
+    }
+}
+
+fn params<P: DeserializeOwned>(request: Request) -> Result<P, (ErrorCode, String)> {
+    serde_json::from_value(request.params).map_err(|e| (ErrorCode::InvalidParams, e.to_string()))
+}
+
+fn to_value(value: impl serde::Serialize) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or(serde_json::Value::Null)
+}
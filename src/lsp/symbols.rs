@@ -0,0 +1,297 @@
+//! Translation of tags into LSP symbols, locations and folding ranges.
+//!
+//! Positions are in UTF-16 code units, the LSP default encoding.
+
+use lsp_types::{DocumentSymbol, FoldingRange, Position, Range, SymbolKind};
+
+use crate::outline::{outline, OutlineNode};
+use crate::tag_record::TagRecord;
+
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+/// 0-based line of a tag. Tags without a line number sort to the top.
+fn start_line(record: &TagRecord) -> u32 {
+    record.line.unwrap_or(1).saturating_sub(1)
+}
+
+fn end_line(record: &TagRecord) -> u32 {
+    record
+        .end_line
+        .map_or(start_line(record), |end| end.saturating_sub(1))
+        .max(start_line(record))
+}
+
+/// Range of the tag's name within `line`, or an empty range at the start of
+/// the line when the name is not found there.
+fn name_range_in(record: &TagRecord, line: &str) -> Range {
+    let row = start_line(record);
+    match line.find(&record.name) {
+        Some(byte) => {
+            let start = utf16_len(&line[..byte]);
+            Range::new(
+                Position::new(row, start),
+                Position::new(row, start + utf16_len(&record.name)),
+            )
+        }
+        None => Range::new(Position::new(row, 0), Position::new(row, 0)),
+    }
+}
+
+/// Range of the tag's name, located in the source line kept as its pattern.
+pub fn name_range(record: &TagRecord) -> Range {
+    name_range_in(record, record.pattern.as_deref().unwrap_or(""))
+}
+
+/// The closest LSP symbol kind for the tag's kind name, or its kind letter
+/// when the language is unknown.
+pub fn symbol_kind(record: &TagRecord) -> SymbolKind {
+    let kind = record
+        .kind_name
+        .as_deref()
+        .or(record.kind.as_deref())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let kind = kind.as_str();
+    let has = |names: &[&str]| names.iter().any(|name| kind.contains(name));
+    if has(&["method"]) {
+        SymbolKind::METHOD
+    } else if has(&["constructor"]) {
+        SymbolKind::CONSTRUCTOR
+    } else if has(&["function", "prototype", "subroutine", "generator"]) || kind == "f" {
+        SymbolKind::FUNCTION
+    } else if has(&["enumerator", "enumarator", "variant"]) || kind == "e" {
+        SymbolKind::ENUM_MEMBER
+    } else if has(&["enum"]) || kind == "g" {
+        SymbolKind::ENUM
+    } else if has(&["interface", "trait", "protocol"]) || kind == "i" {
+        SymbolKind::INTERFACE
+    } else if has(&["struct", "union", "record"]) || kind == "s" {
+        SymbolKind::STRUCT
+    } else if has(&["field", "member"]) || kind == "m" {
+        SymbolKind::FIELD
+    } else if has(&["class", "impl", "object"]) || kind == "c" {
+        SymbolKind::CLASS
+    } else if has(&["namespace"]) {
+        SymbolKind::NAMESPACE
+    } else if has(&["package"]) {
+        SymbolKind::PACKAGE
+    } else if has(&["module", "header"]) || kind == "n" {
+        SymbolKind::MODULE
+    } else if has(&["propert"]) {
+        SymbolKind::PROPERTY
+    } else if has(&["constant", "macro", "define"]) {
+        SymbolKind::CONSTANT
+    } else if has(&["typedef", "type", "alias"]) || kind == "t" {
+        SymbolKind::TYPE_PARAMETER
+    } else {
+        SymbolKind::VARIABLE
+    }
+}
+
+/// The symbol tree of one file, nested as by [`outline`].
+pub fn document_symbols(records: &[TagRecord], text: &str) -> Vec<DocumentSymbol> {
+    let lines: Vec<&str> = text.lines().collect();
+    outline(records)
+        .iter()
+        .map(|node| build_symbol(node, &lines))
+        .collect()
+}
+
+fn build_symbol(node: &OutlineNode, lines: &[&str]) -> DocumentSymbol {
+    let line = |row: u32| lines.get(row as usize).copied().unwrap_or("");
+    let record = &node.record;
+    let end = end_line(record);
+    let range = Range::new(
+        Position::new(start_line(record), 0),
+        Position::new(end, utf16_len(line(end))),
+    );
+    let mut selection_range = name_range_in(record, line(start_line(record)));
+    if selection_range.end > range.end {
+        selection_range = Range::new(range.start, range.start);
+    }
+    #[allow(deprecated)]
+    DocumentSymbol {
+        name: record.name.clone(),
+        detail: record.field("signature").map(str::to_string),
+        kind: symbol_kind(record),
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: Some(
+            node.children
+                .iter()
+                .map(|child| build_symbol(child, lines))
+                .collect(),
+        ),
+    }
+}
+
+/// A folding range for every tag spanning several lines.
+pub fn folding_ranges(records: &[TagRecord]) -> Vec<FoldingRange> {
+    let mut spans: Vec<(u32, u32)> = records
+        .iter()
+        .map(|record| (start_line(record), end_line(record)))
+        .filter(|(start, end)| end > start)
+        .collect();
+    spans.sort_unstable();
+    spans.dedup();
+    spans
+        .into_iter()
+        .map(|(start_line, end_line)| FoldingRange {
+            start_line,
+            end_line,
+            ..FoldingRange::default()
+        })
+        .collect()
+}
+
+/// The identifier under or just before `character` (UTF-16) in `line`.
+pub fn word_at(line: &str, character: u32) -> Option<&str> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let mut units = 0;
+    let cursor = line
+        .char_indices()
+        .find(|(_, c)| {
+            let before = units;
+            units += c.len_utf16() as u32;
+            before >= character
+        })
+        .map_or(line.len(), |(byte, _)| byte);
+
+    let start = line[..cursor]
+        .rfind(|c: char| !is_word(c))
+        .map_or(0, |byte| {
+            byte + line[byte..].chars().next().map_or(1, char::len_utf8)
+        });
+    let end = line[cursor..]
+        .find(|c: char| !is_word(c))
+        .map_or(line.len(), |byte| cursor + byte);
+    (start < end).then(|| &line[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag_record::Scope;
+
+    fn record(name: &str, kind: &str, line: u32, end: Option<u32>) -> TagRecord {
+        let mut record = TagRecord::new(name.to_string(), "shapes.rs");
+        record.kind_name = Some(kind.to_string());
+        record.line = Some(line);
+        record.end_line = end;
+        record
+    }
+
+    fn scoped(mut record: TagRecord, kind: &str, scope: &str) -> TagRecord {
+        record.scope = Some(Scope::new(kind, scope));
+        record
+    }
+
+    const SOURCE: &str = "struct Circle;\n\
+        impl Circle {\n\
+        \x20   fn area() {}\n\
+        }\n\
+        mod shapes {\n\
+        \x20   struct Circle;\n\
+        \x20   fn helper() {}\n\
+        }\n";
+
+    #[test]
+    fn test_document_symbols_nest_by_scope_and_end() {
+        let records = vec![
+            scoped(
+                record("area", "method", 3, Some(3)),
+                "implementation",
+                "Circle",
+            ),
+            record("Circle", "struct", 1, Some(1)),
+            record("Circle", "implementation", 2, Some(4)),
+            record("shapes", "module", 5, Some(8)),
+            scoped(record("Circle", "struct", 6, Some(6)), "module", "shapes"),
+            scoped(
+                record("helper", "function", 7, None),
+                "module",
+                "crate::shapes",
+            ),
+        ];
+        let symbols = document_symbols(&records, SOURCE);
+        let tree: Vec<_> = symbols
+            .iter()
+            .map(|s| {
+                let children: Vec<_> = s
+                    .children
+                    .iter()
+                    .flatten()
+                    .map(|c| (c.name.as_str(), c.kind))
+                    .collect();
+                (s.name.as_str(), s.kind, children)
+            })
+            .collect();
+        assert_eq!(
+            tree,
+            vec![
+                ("Circle", SymbolKind::STRUCT, vec![]),
+                (
+                    "Circle",
+                    SymbolKind::CLASS,
+                    vec![("area", SymbolKind::METHOD)]
+                ),
+                (
+                    "shapes",
+                    SymbolKind::MODULE,
+                    vec![
+                        ("Circle", SymbolKind::STRUCT),
+                        ("helper", SymbolKind::FUNCTION)
+                    ]
+                ),
+            ]
+        );
+
+        let area = &symbols[1].children.as_ref().unwrap()[0];
+        assert_eq!(
+            area.range,
+            Range::new(Position::new(2, 0), Position::new(2, 16))
+        );
+        assert_eq!(
+            area.selection_range,
+            Range::new(Position::new(2, 7), Position::new(2, 11))
+        );
+        assert_eq!(symbols[2].range.end, Position::new(7, 1));
+    }
+
+    #[test]
+    fn test_folding_ranges_and_name_range() {
+        let records = vec![
+            record("Circle", "implementation", 2, Some(4)),
+            record("Circle", "struct", 2, Some(4)),
+            record("area", "method", 3, Some(3)),
+            record("CONST", "constant", 9, None),
+        ];
+        let folds: Vec<_> = folding_ranges(&records)
+            .iter()
+            .map(|f| (f.start_line, f.end_line))
+            .collect();
+        assert_eq!(folds, [(1, 3)]);
+
+        let mut area = record("área", "function", 3, None);
+        area.pattern = Some("    fn área() {}".to_string());
+        assert_eq!(
+            name_range(&area),
+            Range::new(Position::new(2, 7), Position::new(2, 11))
+        );
+    }
+
+    #[test]
+    fn test_word_at() {
+        let line = "    let a = área_of(circle) + x;";
+        assert_eq!(word_at(line, 12), Some("área_of"));
+        assert_eq!(word_at(line, 19), Some("área_of"));
+        assert_eq!(word_at(line, 20), Some("circle"));
+        assert_eq!(word_at(line, 30), Some("x"));
+        assert_eq!(word_at(line, 2), None);
+        assert_eq!(word_at("", 0), None);
+    }
+}
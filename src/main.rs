@@ -1,27 +1,24 @@
 #![doc = include_str!("../README.md")]
 
+use std::io::Read;
 use std::path::Path;
 use std::process;
 
-mod built_in_grammars;
-mod builtin_langs;
-mod config;
-mod file_finder;
+mod interactive;
 mod kinds_listing;
-mod lang_resolve;
-mod language_parser;
-mod parser;
-mod plugin;
-mod queries;
-mod shell_to_regex;
-mod split_by_newlines;
-mod tag;
-mod tag_processor;
-mod tag_writer;
-mod tags_config;
-mod user_grammars;
-
-use crate::config::Config;
+#[cfg(feature = "lsp")]
+mod lsp;
+
+#[cfg(feature = "scip")]
+use treetags::scip;
+#[cfg(feature = "sqlite")]
+use treetags::tag_db;
+use treetags::{
+    config, file_finder, language_parser, outline, parser, plugin, tag, tag_lookup, tag_processor,
+    tag_record, tag_writer,
+};
+
+use crate::config::{Config, OutputFormat};
 use crate::file_finder::FileFinder;
 use crate::tag_processor::TagProcessor;
 use crate::tag_writer::TagWriter;
@@ -30,13 +27,73 @@ use rayon::slice::ParallelSliceMut;
 use clap_complete::generate;
 
 fn main() {
-    let config = Config::new();
+    let mut config = Config::new();
 
     if handle_early_exit_commands(&config) {
         return;
     }
 
-    let tag_file_path = match file_finder::determine_tag_file_path(&config.tag_file, config.append)
+    if config.append
+        && matches!(
+            config.output_format,
+            OutputFormat::Scip | OutputFormat::Xref
+        )
+    {
+        eprintln!("treetags: --append only works with --output-format=ctags");
+        process::exit(1);
+    }
+    match config.output_format {
+        OutputFormat::Ctags => {}
+        OutputFormat::Scip => {
+            if !cfg!(feature = "scip") {
+                eprintln!("treetags: --output-format=scip needs the `scip` feature");
+                process::exit(1);
+            }
+            if config.tag_file == "tags" {
+                config.tag_file = "index.scip".to_string();
+            }
+        }
+        OutputFormat::Xref => {
+            if config.tag_file == "tags" {
+                config.tag_file = "-".to_string();
+            }
+            let columns: Vec<String> = config.xformat.field_names().map(str::to_string).collect();
+            config.fields_config.enabled_fields.extend(columns);
+        }
+        OutputFormat::Sqlite => {
+            if !cfg!(feature = "sqlite") {
+                eprintln!("treetags: --output-format=sqlite needs the `sqlite` feature");
+                process::exit(1);
+            }
+            if config.tag_file == "-" {
+                eprintln!("treetags: --output-format=sqlite needs a database file, not stdout");
+                process::exit(1);
+            }
+            if config.stdin_filename.is_some() {
+                eprintln!(
+                    "treetags: --stdin-filename cannot update a --output-format=sqlite database"
+                );
+                process::exit(1);
+            }
+            if config.tag_file == "tags" {
+                config.tag_file = "tags.db".to_string();
+            }
+        }
+    }
+    if config.output_format != OutputFormat::Ctags {
+        // SCIP occurrences and database symbols need the line of each tag and
+        // the extent of definitions.
+        for field in ["line", "end"] {
+            config
+                .fields_config
+                .enabled_fields
+                .insert(field.to_string());
+        }
+    }
+
+    // An existing database is not a tags file to validate before appending.
+    let append_to_tags = config.append && config.output_format == OutputFormat::Ctags;
+    let tag_file_path = match file_finder::determine_tag_file_path(&config.tag_file, append_to_tags)
     {
         Ok(path) => path,
         Err(err) => {
@@ -45,6 +102,208 @@ fn main() {
         }
     };
 
+    let tag_processor = TagProcessor::new(tag_file_path.clone(), config.workers, config.clone());
+    let mut tags = match &config.stdin_filename {
+        Some(name) => tag_stdin(&config, &tag_processor, name),
+        None => {
+            if config.file_names.iter().any(|name| name == "-") {
+                eprintln!("treetags: `-` reads the source from stdin and needs --stdin-filename");
+                process::exit(1);
+            }
+            let files = find_files(&config);
+            if config.suggest_plugins {
+                suggest_plugins(&config, &files);
+                return;
+            }
+            #[cfg(feature = "sqlite")]
+            if config.output_format == OutputFormat::Sqlite {
+                if let Err(err) = update_database(&config, &tag_processor, &files, &tag_file_path) {
+                    eprintln!("treetags: {err:#}");
+                    process::exit(1);
+                }
+                return;
+            }
+            tag_processor.process_files(files)
+        }
+    };
+
+    if config.append {
+        let existing_tags = file_finder::parse_tag_file(&tag_file_path);
+        tags.extend(existing_tags);
+    }
+
+    if config.sort {
+        tags.par_sort_unstable_by(|a, b| a.sort_cmp(b));
+    }
+
+    match config.output_format {
+        OutputFormat::Ctags => {
+            let tag_writer = TagWriter::new(tag_file_path);
+            tag_writer.write_tags(&mut tags, true, config.sort);
+        }
+        #[cfg(feature = "scip")]
+        OutputFormat::Scip => write_scip(&tag_processor, &tags, &tag_file_path),
+        #[cfg(not(feature = "scip"))]
+        OutputFormat::Scip => unreachable!("scip output is rejected without the scip feature"),
+        OutputFormat::Sqlite => unreachable!("databases are updated before tagging"),
+        OutputFormat::Xref => {
+            let describe = describe_tagged(&tag_processor);
+            TagWriter::new(tag_file_path).write_xref(&tags, &config.xformat, describe);
+        }
+    }
+}
+
+/// The directory the paths in the tag file at `path` are relative to.
+#[cfg(feature = "scip")]
+fn tag_file_dir(path: &str) -> std::path::PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| Path::new(".").to_path_buf());
+    if path == "-" {
+        cwd
+    } else {
+        Path::new(path).parent().unwrap_or(&cwd).to_path_buf()
+    }
+}
+
+/// Fills in the language and kind names of records from their tagged file,
+/// resolved once per file as tagging would. Record paths are relative to
+/// `root`.
+fn describe_language<'a>(
+    registry: &'a language_parser::LanguageParserRegistry,
+    config: &'a Config,
+    root: &'a Path,
+) -> impl FnMut(&mut tag_record::TagRecord) + 'a {
+    let mut languages = std::collections::HashMap::new();
+    move |record| {
+        let described = languages.entry(record.path.clone()).or_insert_with(|| {
+            let path = Path::new(&record.path);
+            tag_processor::select_language(registry, config, &root.join(path), path).map(
+                |selection| {
+                    let parser = registry.parser(selection.lang);
+                    (parser.language_name().to_string(), parser.kinds())
+                },
+            )
+        });
+        if let Some((language, kinds)) = described {
+            record.set_language(language, kinds);
+        }
+    }
+}
+
+/// Fills in the language and kind names of records from the language their
+/// file was tagged as by `tag_processor`.
+fn describe_tagged(tag_processor: &TagProcessor) -> impl FnMut(&mut tag_record::TagRecord) + '_ {
+    let registry = tag_processor.registry();
+    let mut kinds = std::collections::HashMap::new();
+    move |record| {
+        if let Some(lang) = tag_processor.language_of(&record.path) {
+            let parser = registry.parser(lang);
+            let kinds = kinds.entry(lang).or_insert_with(|| parser.kinds());
+            record.set_language(parser.language_name(), kinds);
+        }
+    }
+}
+
+/// Updates the database at `path` with the tags of `files`, retagging only
+/// those that changed since they were stored. Stored files missing from
+/// `files` are dropped unless `--append` is given.
+#[cfg(feature = "sqlite")]
+fn update_database(
+    config: &Config,
+    tag_processor: &TagProcessor,
+    files: &[String],
+    path: &str,
+) -> anyhow::Result<()> {
+    use std::collections::HashSet;
+    use tag_db::{FileEntry, TagDatabase};
+
+    let mut db = TagDatabase::open(Path::new(path))?;
+    let mut kinds: Vec<_> = config.kinds_map.iter().collect();
+    kinds.sort();
+    db.set_options(&format!(
+        "treetags {} --fields={} --extras={} kinds={kinds:?}",
+        env!("CARGO_PKG_VERSION"),
+        config.fields,
+        config.extras
+    ))?;
+
+    let registry = tag_processor.registry();
+    let cwd = std::env::current_dir().unwrap_or_else(|_| Path::new(".").to_path_buf());
+    let root = Path::new(path).parent().unwrap_or(&cwd);
+    let mut present = HashSet::new();
+    let mut changed = Vec::new();
+    let mut entries = Vec::new();
+    for name in files {
+        let file = cwd.join(name);
+        let Some(selection) =
+            tag_processor::select_language(registry, config, &file, Path::new(name))
+        else {
+            continue;
+        };
+        let key = tag_processor::relative_path(&file, root, name);
+        let state = match db.check_file(&key, &file) {
+            Ok(state) => state,
+            Err(err) => {
+                eprintln!("treetags: {err:#}");
+                continue;
+            }
+        };
+        present.insert(key.clone());
+        let Some(state) = state else {
+            continue;
+        };
+        entries.push(FileEntry {
+            path: key,
+            language: Some(registry.parser(selection.lang).language_name().to_string()),
+            state,
+        });
+        changed.push(name.clone());
+    }
+
+    let tags = tag_processor.process_files(changed);
+    let mut describe = describe_tagged(tag_processor);
+    let records: Vec<tag_record::TagRecord> = tags
+        .iter()
+        .map(|tag| {
+            let mut record = tag_record::TagRecord::from(tag);
+            describe(&mut record);
+            record
+        })
+        .collect();
+    db.update(&entries, &records, |path| {
+        config.append || present.contains(path)
+    })
+}
+
+/// Writes `tags` as a SCIP index rooted at the directory of the output file.
+#[cfg(feature = "scip")]
+fn write_scip(tag_processor: &TagProcessor, tags: &[tag::Tag], path: &str) {
+    let root = tag_file_dir(path);
+    let mut describe = describe_tagged(tag_processor);
+    let records: Vec<tag_record::TagRecord> = tags
+        .iter()
+        .map(|tag| {
+            let mut record = tag_record::TagRecord::from(tag);
+            describe(&mut record);
+            record
+        })
+        .collect();
+
+    let index = scip::encode_index(&records, &root);
+    let written = if path == "-" {
+        use std::io::Write;
+        std::io::stdout().write_all(&index)
+    } else {
+        std::fs::write(path, &index)
+    };
+    if let Err(err) = written {
+        eprintln!("treetags: cannot write {path}: {err}");
+        process::exit(1);
+    }
+}
+
+/// Expands the file names given on the command line (or the current
+/// directory) into the files to tag.
+fn find_files(config: &Config) -> Vec<String> {
     let file_finder = match FileFinder::from_patterns(config.exclude.clone(), config.recurse) {
         Ok(finder) => finder,
         Err(err) => {
@@ -66,27 +325,21 @@ fn main() {
         process::exit(1);
     }
 
-    let files = file_result.files;
-
-    if config.suggest_plugins {
-        suggest_plugins(&config, &files);
-        return;
-    }
-
-    let tag_processor = TagProcessor::new(tag_file_path.clone(), config.workers, config.clone());
-    let mut tags = tag_processor.process_files(files);
+    file_result.files
+}
 
-    if config.append {
-        let existing_tags = file_finder::parse_tag_file(&tag_file_path);
-        tags.extend(existing_tags);
+/// Tags the source read from stdin as the file `name` (`--stdin-filename`).
+fn tag_stdin(config: &Config, tag_processor: &TagProcessor, name: &str) -> Vec<tag::Tag> {
+    if config.file_names.iter().any(|file| file != "-") {
+        eprintln!("treetags: --stdin-filename tags stdin only; pass `-` instead of file names");
+        process::exit(1);
     }
-
-    if config.sort {
-        tags.par_sort_unstable_by(|a, b| a.sort_cmp(b));
+    let mut code = Vec::new();
+    if let Err(err) = std::io::stdin().read_to_end(&mut code) {
+        eprintln!("treetags: cannot read stdin: {err}");
+        process::exit(1);
     }
-
-    let tag_writer = TagWriter::new(tag_file_path);
-    tag_writer.write_tags(&mut tags, true, config.sort);
+    tag_processor.process_source(name, &code)
 }
 
 fn handle_early_exit_commands(config: &Config) -> bool {
@@ -106,6 +359,39 @@ fn handle_early_exit_commands(config: &Config) -> bool {
                     process::exit(1);
                 }
             }
+            config::Commands::Cache { action } => {
+                if let Err(err) = handle_cache_command(action) {
+                    eprintln!("error: {err:#}");
+                    process::exit(1);
+                }
+            }
+            config::Commands::Lsp => {
+                if let Err(err) = run_lsp(config) {
+                    eprintln!("error: {err:#}");
+                    process::exit(1);
+                }
+            }
+            config::Commands::Outline { file, json } => {
+                if let Err(err) = handle_outline_command(file, *json, config) {
+                    eprintln!("error: {err:#}");
+                    process::exit(1);
+                }
+            }
+            config::Commands::Query { query } => match handle_query_command(query, config) {
+                Ok(true) => {}
+                Ok(false) => process::exit(1),
+                Err(err) => {
+                    eprintln!("error: {err:#}");
+                    process::exit(1);
+                }
+            },
+        }
+        return true;
+    }
+    if config.interactive {
+        if let Err(err) = interactive::run(config) {
+            eprintln!("error: {err:#}");
+            process::exit(1);
         }
         return true;
     }
@@ -133,6 +419,17 @@ fn handle_early_exit_commands(config: &Config) -> bool {
     false
 }
 
+/// Runs `treetags lsp`.
+#[cfg(feature = "lsp")]
+fn run_lsp(config: &Config) -> anyhow::Result<()> {
+    lsp::run(config)
+}
+
+#[cfg(not(feature = "lsp"))]
+fn run_lsp(_config: &Config) -> anyhow::Result<()> {
+    anyhow::bail!("treetags was built without the `lsp` feature")
+}
+
 /// Dispatches a `treetags plugin ...` subcommand.
 fn handle_plugin_command(action: &config::PluginCommands, config: &Config) -> anyhow::Result<()> {
     use config::PluginCommands;
@@ -140,22 +437,191 @@ fn handle_plugin_command(action: &config::PluginCommands, config: &Config) -> an
 
     let base = client::resolve_base_url(config.plugin_index_url.as_deref());
     let plugins_dir = &config.plugins_dir;
+    let trust = || {
+        plugin::signing::TrustPolicy::new(
+            &config.plugin_trusted_keys,
+            config.allow_unsigned_plugins,
+        )
+    };
     match action {
         PluginCommands::Available { refresh } => client::available(&base, *refresh, plugins_dir),
+        PluginCommands::Info {
+            name,
+            refresh,
+            json,
+        } => {
+            let registry = language_parser::LanguageParserRegistry::new(config);
+            let native_languages: Vec<client::NativeLanguage> = registry
+                .language_sources()
+                .iter()
+                .filter(|s| s.kind == language_parser::SourceKind::Native)
+                .map(|s| client::NativeLanguage {
+                    name: s.name.clone(),
+                    extensions: s.extensions.clone(),
+                })
+                .collect();
+            let host = client::HostContext {
+                plugins_dir,
+                cache_grants: &config.plugin_cache,
+                project_read_grants: &config.plugin_project_read,
+                native_languages: &native_languages,
+            };
+            client::info(&base, name, *refresh, &host, *json)
+        }
+        PluginCommands::Search {
+            term,
+            refresh,
+            json,
+        } => client::search(&base, term, *refresh, plugins_dir, *json),
         PluginCommands::Install {
             name,
             force,
             refresh,
-        } => client::install(&base, name, *force, *refresh, plugins_dir),
+            lock,
+        } => client::install(&base, name, *force, *refresh, plugins_dir, &trust()?, *lock),
         PluginCommands::Uninstall { name } => client::uninstall(name, plugins_dir),
         PluginCommands::Installed => {
             plugin::print_plugin_list(&config.plugin_dirs, plugins_dir);
             Ok(())
         }
         PluginCommands::Update { name, refresh } => {
-            client::update(&base, name.as_deref(), *refresh, plugins_dir)
+            client::update(&base, name.as_deref(), *refresh, plugins_dir, &trust()?)
+        }
+        PluginCommands::Rollback { name } => client::rollback(name, plugins_dir),
+        PluginCommands::Test {
+            dir,
+            built,
+            filter,
+            bless,
+        } => plugin::golden::run(
+            dir.as_deref().unwrap_or(Path::new(".")),
+            built.as_deref(),
+            filter.as_deref(),
+            *bless,
+        ),
+        PluginCommands::Sync { refresh } => client::sync(
+            config.plugin_index_url.as_deref(),
+            *refresh,
+            plugins_dir,
+            &trust()?,
+        ),
+    }
+}
+
+/// Dispatches a `treetags cache ...` subcommand.
+fn handle_cache_command(action: &config::CacheCommands) -> anyhow::Result<()> {
+    use config::CacheCommands;
+    use plugin::cache;
+
+    let filter = |args: &config::CacheFilterArgs| cache::CacheFilter {
+        project: args.project.clone(),
+        plugin: args.plugin.clone(),
+        older_than: args.older_than,
+    };
+    match action {
+        CacheCommands::List { filter: args, json } => cache::list(&filter(args), *json),
+        CacheCommands::Size { filter: args } => cache::size(&filter(args)),
+        CacheCommands::Clean { filter: args } => cache::clean(&filter(args)),
+    }
+}
+
+/// Runs `treetags query`. Returns whether any tag matched.
+fn handle_query_command(args: &config::QueryArgs, config: &Config) -> anyhow::Result<bool> {
+    use tag_lookup::{TagFilter, TagQuery};
+
+    let query = TagQuery {
+        name: args.name.clone(),
+        prefix: args.prefix,
+        ignore_case: args.ignore_case,
+        filter: TagFilter {
+            kinds: args.kind.clone(),
+            languages: args.language.clone(),
+            scope: args.scope.clone(),
+            fields: args.field.clone(),
+        },
+    };
+    let kind_letters_only = args.kind.iter().all(|kind| kind.chars().count() == 1);
+    let matches = if let Some(matches) = lookup_database(&args.tag_file, &query)? {
+        matches
+    } else if args.language.is_empty() && !args.json && kind_letters_only {
+        tag_lookup::lookup(&args.tag_file, &query)?
+    } else {
+        // Tags lines do not record their language, nor kind names without
+        // the `kind` field; resolve them from the tagged file, once per file,
+        // as tagging would.
+        let registry = language_parser::LanguageParserRegistry::new(config);
+        let tag_dir = args.tag_file.parent().unwrap_or(Path::new(""));
+        let describe = describe_language(&registry, config, tag_dir);
+        tag_lookup::lookup_with(&args.tag_file, &query, describe)?
+    };
+
+    if args.json {
+        let records: Vec<_> = matches.iter().map(|m| &m.record).collect();
+        println!("{}", serde_json::to_string_pretty(&records)?);
+    } else {
+        for m in &matches {
+            println!("{}", m.line);
         }
     }
+    Ok(!matches.is_empty())
+}
+
+/// Looks `query` up in the `--output-format=sqlite` database at `path`, or
+/// returns `None` when `path` is not one.
+#[cfg(feature = "sqlite")]
+fn lookup_database(
+    path: &Path,
+    query: &tag_lookup::TagQuery,
+) -> anyhow::Result<Option<Vec<tag_lookup::TagMatch>>> {
+    if !tag_db::is_database(path) {
+        return Ok(None);
+    }
+    tag_db::TagDatabase::open(path)?.lookup(query).map(Some)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn lookup_database(
+    _path: &Path,
+    _query: &tag_lookup::TagQuery,
+) -> anyhow::Result<Option<Vec<tag_lookup::TagMatch>>> {
+    Ok(None)
+}
+
+/// Runs `treetags outline`.
+fn handle_outline_command(file: &Path, json: bool, config: &Config) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let mut config = config.clone();
+    // Nesting needs the scope and both ends of each definition.
+    for field in ["line", "end", "scope", "signature"] {
+        config
+            .fields_config
+            .enabled_fields
+            .insert(field.to_string());
+    }
+    let code = std::fs::read(file).with_context(|| format!("cannot read {}", file.display()))?;
+    let mut tagger = tag_processor::SourceTagger::new(&config);
+    let Some((lang, tags)) = tagger.tag(&file.to_string_lossy(), &code, &config) else {
+        anyhow::bail!("no language handles {}", file.display());
+    };
+    let parser = tagger.registry().parser(lang);
+    let kinds = parser.kinds();
+    let records: Vec<_> = tags
+        .iter()
+        .map(|tag| {
+            let mut record = tag_record::TagRecord::from(tag);
+            record.set_language(parser.language_name(), &kinds);
+            record
+        })
+        .collect();
+
+    let tree = outline::outline(&records);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tree)?);
+    } else {
+        outline::write_text(&tree, &mut std::io::stdout().lock())?;
+    }
+    Ok(())
 }
 
 /// Reports available (uninstalled) plugins that could handle files in the tree —
@@ -0,0 +1,181 @@
+//! The definitions of one file as a tree, for `treetags outline` and the
+//! language server's document symbols.
+//!
+//! Nesting follows the scope field each tag carries, which the builtin
+//! walkers fill from the scope stack they keep while walking the tree (e.g.
+//! `RustContext`, `GoContext`), and the lines each definition spans, which
+//! plugins and query-based languages report through `end`.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::tag_record::TagRecord;
+
+/// A tag and the tags defined inside it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutlineNode {
+    #[serde(flatten)]
+    pub record: TagRecord,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<OutlineNode>,
+}
+
+/// 1-based first line of a tag. Tags without a line number sort to the top.
+fn start_line(record: &TagRecord) -> u32 {
+    record.line.unwrap_or(1)
+}
+
+/// 1-based last line of a tag, its first line when the end is unknown.
+fn end_line(record: &TagRecord) -> u32 {
+    record
+        .end_line
+        .unwrap_or(start_line(record))
+        .max(start_line(record))
+}
+
+/// The last component of a scope name such as `Outer.Inner` or
+/// `crate::shapes::Circle`.
+fn scope_leaf(scope: &str) -> &str {
+    scope.rsplit(['.', ':', '/', '\\']).next().unwrap_or(scope)
+}
+
+/// The tags of one file as a tree, each level ordered by line.
+///
+/// A tag goes under the innermost enclosing tag (by line and `end`) named like
+/// the last component of its scope. Languages without end lines fall back to
+/// the latest tag of that name; tags without a scope are top level.
+pub fn outline(records: &[TagRecord]) -> Vec<OutlineNode> {
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    order.sort_by_key(|&i| {
+        (
+            start_line(&records[i]),
+            std::cmp::Reverse(end_line(&records[i])),
+        )
+    });
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); records.len()];
+    let mut roots = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut latest: HashMap<&str, usize> = HashMap::new();
+    for i in order {
+        let record = &records[i];
+        let line = start_line(record);
+        while open
+            .last()
+            .is_some_and(|&top| end_line(&records[top]) < line)
+        {
+            open.pop();
+        }
+        let parent = record.scope.as_ref().and_then(|scope| {
+            let leaf = scope_leaf(&scope.name);
+            open.iter()
+                .rev()
+                .copied()
+                .find(|&candidate| records[candidate].name == leaf)
+                .or_else(|| latest.get(leaf).copied())
+        });
+        match parent {
+            Some(parent) => children[parent].push(i),
+            None => roots.push(i),
+        }
+        open.push(i);
+        latest.insert(&record.name, i);
+    }
+
+    fn build(i: usize, records: &[TagRecord], children: &[Vec<usize>]) -> OutlineNode {
+        OutlineNode {
+            record: records[i].clone(),
+            children: children[i]
+                .iter()
+                .map(|&child| build(child, records, children))
+                .collect(),
+        }
+    }
+    roots
+        .into_iter()
+        .map(|i| build(i, records, &children))
+        .collect()
+}
+
+/// Writes the tree one tag per line, indented by depth: kind, name with its
+/// signature, and the lines it spans.
+///
+/// ```text
+/// struct Circle  1-4
+/// implementation Circle  6-12
+///   method area(&self) -> f64  7-9
+/// ```
+pub fn write_text(nodes: &[OutlineNode], out: &mut impl Write) -> io::Result<()> {
+    fn write_node(node: &OutlineNode, depth: usize, out: &mut impl Write) -> io::Result<()> {
+        let record = &node.record;
+        let kind = record
+            .kind_name
+            .as_deref()
+            .or(record.kind.as_deref())
+            .unwrap_or("tag");
+        let signature = record.field("signature").unwrap_or("");
+        let (start, end) = (start_line(record), end_line(record));
+        write!(
+            out,
+            "{:indent$}{kind} {}{signature}  {start}",
+            "",
+            record.name,
+            indent = depth * 2
+        )?;
+        if end > start {
+            write!(out, "-{end}")?;
+        }
+        writeln!(out)?;
+        node.children
+            .iter()
+            .try_for_each(|child| write_node(child, depth + 1, out))
+    }
+    nodes.iter().try_for_each(|node| write_node(node, 0, out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag_record::Scope;
+
+    fn record(name: &str, kind: &str, line: u32, end: Option<u32>) -> TagRecord {
+        let mut record = TagRecord::new(name.to_string(), "shapes.rs");
+        record.kind_name = Some(kind.to_string());
+        record.line = Some(line);
+        record.end_line = end;
+        record
+    }
+
+    #[test]
+    fn test_outline_text_and_json() {
+        let mut area = record("area", "method", 7, Some(9));
+        area.scope = Some(Scope::new("implementation", "Circle"));
+        area.fields = vec![("signature".to_string(), "(&self) -> f64".to_string())];
+        let records = vec![
+            area,
+            record("Circle", "implementation", 6, Some(12)),
+            record("Circle", "struct", 1, Some(4)),
+            record("ORIGIN", "constant", 14, None),
+        ];
+        let tree = outline(&records);
+
+        let mut text = Vec::new();
+        write_text(&tree, &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "struct Circle  1-4\n\
+             implementation Circle  6-12\n\
+             \x20 method area(&self) -> f64  7-9\n\
+             constant ORIGIN  14\n"
+        );
+
+        let json = serde_json::to_value(&tree[1]).unwrap();
+        assert_eq!(json["name"], "Circle");
+        assert_eq!(json["end_line"], 12);
+        assert_eq!(json["children"][0]["name"], "area");
+        assert_eq!(json["children"][0]["fields"]["signature"], "(&self) -> f64");
+        assert!(json["children"][0].get("children").is_none());
+    }
+}
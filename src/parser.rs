@@ -5,6 +5,7 @@
 //! - `ts_parser` — used by builtin tree-walker parsers
 //! - `tags_context` / `grammar_store` — used by query-based fallback parsers
 //! - `shared_registry` / `local_instances` — used by WASM plugin parsers
+//!   (declarative plugins reuse `tags_context` with a WASM grammar store)
 //!
 //! Language routing lives in `LanguageParser` / `LanguageParserRegistry`
 //! (`language_parser.rs`); this module is a pure execution engine.
@@ -94,6 +95,8 @@ pub struct Parser {
     pub(crate) ts_parser: TSParser,
     pub(crate) shared_registry: Option<Arc<PluginRegistry>>,
     pub(crate) local_instances: HashMap<String, WasmInstance>,
+    /// Whether `tags_context` has a WASM store for declarative plugin grammars.
+    grammar_store_attached: bool,
 }
 
 impl Default for Parser {
@@ -110,6 +113,7 @@ impl Parser {
             ts_parser: TSParser::new(),
             shared_registry: None,
             local_instances: HashMap::new(),
+            grammar_store_attached: false,
         }
     }
 
@@ -129,14 +133,15 @@ impl Parser {
                 Some(registry)
             },
             local_instances: HashMap::new(),
+            grammar_store_attached: false,
         }
     }
 
-    /// Attempt to generate tags for `extension` using a WASM plugin.
-    /// Returns `None` if no plugin handles this extension or the plugin errors.
+    /// Attempt to generate tags using the WASM plugin named `plugin`.
+    /// Returns `None` if no such plugin is registered or the plugin errors.
     pub(crate) fn try_plugin(
         &mut self,
-        extension: &str,
+        plugin: &str,
         code: &[u8],
         path: &str,
         config: &Config,
@@ -144,7 +149,7 @@ impl Parser {
     ) -> Option<Vec<tag::Tag>> {
         self.shared_registry.as_ref()?.try_generate(
             &mut self.local_instances,
-            extension,
+            plugin,
             code,
             path,
             absolute_path,
@@ -152,6 +157,41 @@ impl Parser {
         )
     }
 
+    /// Attempt to generate tags using the declarative plugin named `plugin`.
+    /// Returns `None` if no such plugin is registered or it fails to load.
+    pub(crate) fn try_declarative_plugin(
+        &mut self,
+        plugin: &str,
+        code: &[u8],
+        path: &str,
+        config: &Config,
+    ) -> Option<Vec<tag::Tag>> {
+        let registry = self.shared_registry.as_ref()?;
+        if !self.grammar_store_attached {
+            if !registry.attach_grammar_store(&mut self.tags_context) {
+                return None;
+            }
+            self.grammar_store_attached = true;
+        }
+        registry.try_generate_declarative(&mut self.tags_context, plugin, code, path, config)
+    }
+
+    /// Generate tags for a batch of files claimed by the WASM plugin named
+    /// `plugin`. Returns no tags when no such plugin is registered.
+    pub(crate) fn try_plugin_batch(
+        &mut self,
+        plugin: &str,
+        files: &[crate::language_parser::SourceFile<'_>],
+        config: &Config,
+    ) -> Vec<tag::Tag> {
+        match self.shared_registry.as_ref() {
+            Some(registry) => {
+                registry.try_generate_batch(&mut self.local_instances, plugin, files, config)
+            }
+            None => Vec::new(),
+        }
+    }
+
     /// Generate tags via tree-sitter tag queries (fallback for non-builtin languages).
     pub(crate) fn generate_by_tag_query(
         &mut self,
@@ -0,0 +1,322 @@
+//! Plugins installed from the local filesystem rather than an index: a built
+//! plugin directory, or a `.tar`/`.zip` bundle written by
+//! `treetags-build-plugin --bundle`.
+//!
+//! Bundles carry a `SHA256SUMS` file (in `sha256sum` format) listing every
+//! other file; it is checked on install, as is one found in a plugin
+//! directory. The manifest and ABI checks match those applied to downloads.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use super::index::sha256_hex;
+use super::manifest::PluginManifest;
+use super::{supports_abi, SUPPORTED_ABI_VERSIONS};
+
+/// Checksum file shipped inside bundles.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Archive formats accepted for plugin bundles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BundleFormat {
+    Tar,
+    Zip,
+}
+
+impl BundleFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            BundleFormat::Tar => "tar",
+            BundleFormat::Zip => "zip",
+        }
+    }
+
+    /// The format of a bundle at `path`, judged by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "tar" => Some(BundleFormat::Tar),
+            "zip" => Some(BundleFormat::Zip),
+            _ => None,
+        }
+    }
+}
+
+/// A plugin read from disk and checked, ready to be placed.
+pub struct LocalPlugin {
+    pub manifest: PluginManifest,
+    /// Files to install, keyed by name: `plugin.toml`, the `.wasm` and any
+    /// query files the manifest references.
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl LocalPlugin {
+    /// Reads a plugin directory or bundle.
+    pub fn read(path: &Path) -> Result<Self> {
+        let files = if path.is_dir() {
+            read_dir_files(path)?
+        } else {
+            let format = BundleFormat::from_path(path).ok_or_else(|| {
+                anyhow!(
+                    "{} is neither a plugin directory nor a .tar/.zip bundle",
+                    path.display()
+                )
+            })?;
+            read_bundle(path, format)?
+        };
+        Self::from_files(files, &path.display().to_string())
+    }
+
+    /// Checks the files of a plugin read from `source`: a manifest for a
+    /// supported ABI, every file it references present, and the `SHA256SUMS` if any.
+    fn from_files(mut files: BTreeMap<String, Vec<u8>>, source: &str) -> Result<Self> {
+        if let Some(sums) = files.remove(CHECKSUMS_FILE) {
+            verify_checksums(&files, &String::from_utf8_lossy(&sums), source)?;
+        }
+        let manifest_bytes = files
+            .get("plugin.toml")
+            .ok_or_else(|| anyhow!("no plugin.toml in {source}"))?;
+        let manifest: PluginManifest = toml::from_str(&String::from_utf8_lossy(manifest_bytes))
+            .with_context(|| format!("parsing plugin.toml in {source}"))?;
+        if !supports_abi(manifest.abi_version) {
+            bail!(
+                "plugin `{}` targets ABI {} but this treetags supports ABI {:?}",
+                manifest.name,
+                manifest.abi_version,
+                SUPPORTED_ABI_VERSIONS
+            );
+        }
+
+        let mut wanted = vec!["plugin.toml", manifest.wasm_file.as_str()];
+        wanted.extend(manifest.query_files());
+        for name in &wanted {
+            if !is_plain_file_name(name) {
+                bail!(
+                    "plugin `{}` in {source} references `{name}`, which is not a file next to \
+                     plugin.toml; install the output of `treetags-build-plugin`",
+                    manifest.name
+                );
+            }
+            if !files.contains_key(*name) {
+                bail!("plugin `{}` in {source} is missing `{name}`", manifest.name);
+            }
+        }
+        let wanted: Vec<String> = wanted.into_iter().map(str::to_string).collect();
+        files.retain(|name, _| wanted.contains(name));
+        Ok(Self { manifest, files })
+    }
+}
+
+/// Renders `SHA256SUMS` for `files`.
+pub fn checksums(files: &BTreeMap<String, Vec<u8>>) -> String {
+    files
+        .iter()
+        .map(|(name, bytes)| format!("{}  {name}\n", sha256_hex(bytes)))
+        .collect()
+}
+
+/// Writes `files` plus their `SHA256SUMS` as a bundle under a `<prefix>/`
+/// directory.
+pub fn write_bundle(
+    path: &Path,
+    format: BundleFormat,
+    prefix: &str,
+    files: &BTreeMap<String, Vec<u8>>,
+) -> Result<()> {
+    let mut all = files.clone();
+    all.insert(CHECKSUMS_FILE.to_string(), checksums(files).into_bytes());
+    let out =
+        std::fs::File::create(path).with_context(|| format!("cannot create {}", path.display()))?;
+    match format {
+        BundleFormat::Tar => {
+            let mut builder = tar::Builder::new(out);
+            for (name, bytes) in &all {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(bytes.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, format!("{prefix}/{name}"), bytes.as_slice())?;
+            }
+            builder.into_inner()?.flush()?;
+        }
+        BundleFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(out);
+            let options = zip::write::SimpleFileOptions::default();
+            for (name, bytes) in &all {
+                zip.start_file(format!("{prefix}/{name}"), options)?;
+                zip.write_all(bytes)?;
+            }
+            zip.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Regular files directly inside `dir`.
+fn read_dir_files(dir: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let bytes = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        files.insert(name.into_owned(), bytes);
+    }
+    Ok(files)
+}
+
+/// Files in a bundle, with a single top-level directory stripped.
+fn read_bundle(path: &Path, format: BundleFormat) -> Result<BTreeMap<String, Vec<u8>>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let mut entries = Vec::new();
+    match format {
+        BundleFormat::Tar => {
+            let mut archive = tar::Archive::new(file);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().into_owned();
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)?;
+                entries.push((name, bytes));
+            }
+        }
+        BundleFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(file)
+                .with_context(|| format!("reading {}", path.display()))?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)?;
+                entries.push((name, bytes));
+            }
+        }
+    }
+    flatten_entries(entries, path)
+}
+
+/// Maps archive paths to file names, accepting either a flat archive or one
+/// whose files all sit in the same top-level directory.
+fn flatten_entries(
+    entries: Vec<(String, Vec<u8>)>,
+    path: &Path,
+) -> Result<BTreeMap<String, Vec<u8>>> {
+    let prefix = entries
+        .first()
+        .and_then(|(name, _)| name.split_once('/'))
+        .map(|(dir, _)| format!("{dir}/"))
+        .filter(|prefix| entries.iter().all(|(name, _)| name.starts_with(prefix)));
+    let mut files = BTreeMap::new();
+    for (name, bytes) in entries {
+        let stripped = match &prefix {
+            Some(prefix) => &name[prefix.len()..],
+            None => name.as_str(),
+        };
+        if !is_plain_file_name(stripped) {
+            bail!("unexpected entry `{name}` in {}", path.display());
+        }
+        files.insert(stripped.to_string(), bytes);
+    }
+    Ok(files)
+}
+
+fn verify_checksums(files: &BTreeMap<String, Vec<u8>>, sums: &str, source: &str) -> Result<()> {
+    for line in sums.lines().filter(|l| !l.trim().is_empty()) {
+        let (expected, name) = line
+            .split_once(char::is_whitespace)
+            .map(|(sha, name)| (sha, name.trim_start().trim_start_matches('*')))
+            .ok_or_else(|| anyhow!("malformed {CHECKSUMS_FILE} line `{line}` in {source}"))?;
+        let bytes = files.get(name).ok_or_else(|| {
+            anyhow!("`{name}` is listed in {CHECKSUMS_FILE} but missing from {source}")
+        })?;
+        let got = sha256_hex(bytes);
+        if got != expected {
+            bail!("checksum mismatch for `{name}` in {source}: expected {expected}, got {got}");
+        }
+    }
+    Ok(())
+}
+
+/// Same rule the index client applies to extra file names.
+fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\']) && !name.starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::PLUGIN_ABI_VERSION;
+
+    fn plugin_files() -> BTreeMap<String, Vec<u8>> {
+        BTreeMap::from([
+            (
+                "plugin.toml".to_string(),
+                format!(
+                    "name = \"demo\"\nversion = \"0.1.0\"\nabi_version = {PLUGIN_ABI_VERSION}\n\
+                     extensions = [\"demo\"]\nwasm_file = \"plugin.wasm\"\n"
+                )
+                .into_bytes(),
+            ),
+            ("plugin.wasm".to_string(), b"\0asm".to_vec()),
+        ])
+    }
+
+    #[test]
+    fn bundles_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for format in [BundleFormat::Tar, BundleFormat::Zip] {
+            let path = dir.path().join(format!("demo.{}", format.extension()));
+            write_bundle(&path, format, "demo", &plugin_files()).unwrap();
+            let plugin = LocalPlugin::read(&path).unwrap();
+            assert_eq!(plugin.manifest.name, "demo");
+            assert_eq!(plugin.files, plugin_files());
+        }
+    }
+
+    #[test]
+    fn checksum_mismatch_is_rejected() {
+        let mut files = plugin_files();
+        let sums = checksums(&files);
+        files.insert(CHECKSUMS_FILE.to_string(), sums.into_bytes());
+        files.insert("plugin.wasm".to_string(), b"tampered".to_vec());
+        let err = LocalPlugin::from_files(files, "test").err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+    }
+
+    #[test]
+    fn wrong_abi_and_missing_files_are_rejected() {
+        let mut files = plugin_files();
+        files.remove("plugin.wasm");
+        assert!(LocalPlugin::from_files(files, "test").is_err());
+
+        let mut files = plugin_files();
+        files.insert(
+            "plugin.toml".to_string(),
+            b"name = \"demo\"\nversion = \"0.1.0\"\nabi_version = 1\nextensions = []\n".to_vec(),
+        );
+        let err = LocalPlugin::from_files(files, "test").err().unwrap();
+        assert!(err.to_string().contains("ABI"), "{err}");
+    }
+
+    #[test]
+    fn flat_and_prefixed_archives_map_to_file_names() {
+        let path = Path::new("x.tar");
+        let flat = flatten_entries(vec![("a".into(), vec![]), ("b".into(), vec![])], path);
+        assert_eq!(flat.unwrap().keys().collect::<Vec<_>>(), ["a", "b"]);
+        let prefixed = flatten_entries(vec![("p/a".into(), vec![]), ("p/b".into(), vec![])], path);
+        assert_eq!(prefixed.unwrap().keys().collect::<Vec<_>>(), ["a", "b"]);
+        assert!(
+            flatten_entries(vec![("p/a".into(), vec![]), ("q/b".into(), vec![])], path).is_err()
+        );
+    }
+}
@@ -0,0 +1,476 @@
+//! Plugin cache files granted by `--plugin-cache`, kept under
+//! `~/.cache/treetags/<project-hash>/<plugin>/`, one file per source file.
+//!
+//! Each project directory also holds a `project.toml` recording the project
+//! path it was created for and when it was last used, so `treetags cache`
+//! can map hashes back to projects. `--plugin-cache-max-size` caps the total
+//! size of every project's caches by evicting the least recently written
+//! files at the start of a run.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::paths::get_cache_dir;
+
+/// Name of the per-project metadata file.
+pub const PROJECT_FILE: &str = "project.toml";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct ProjectRecord {
+    /// The project root the caches belong to.
+    path: PathBuf,
+    /// Unix time of the last run using the caches.
+    last_used: u64,
+}
+
+/// Selects the cache files `treetags cache` acts on.
+#[derive(Debug, Default, Clone)]
+pub struct CacheFilter {
+    /// Only the caches of the project rooted here.
+    pub project: Option<PathBuf>,
+    /// Only the caches of this plugin.
+    pub plugin: Option<String>,
+    /// Only files not written for at least this long.
+    pub older_than: Option<Duration>,
+}
+
+/// Cache files of one plugin in one project.
+#[derive(Debug, Serialize)]
+struct CacheEntry {
+    hash: String,
+    project: Option<PathBuf>,
+    plugin: String,
+    files: u64,
+    bytes: u64,
+    /// Unix time the most recent file was written.
+    last_written: u64,
+    #[serde(skip)]
+    paths: Vec<PathBuf>,
+}
+
+/// FNV-1a 64-bit hash — deterministic across runs, no new dependencies.
+pub(crate) fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const BASIS: u64 = 14695981039346656037;
+    const PRIME: u64 = 1099511628211;
+    let mut h = BASIS;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(PRIME);
+    }
+    h
+}
+
+/// Name of the cache directory of the project rooted at `root`.
+pub fn project_hash(root: &Path) -> String {
+    format!("{:016x}", fnv1a_64(root.as_os_str().as_encoded_bytes()))
+}
+
+/// Records that the caches in `project_dir` were used for `root` just now,
+/// and evicts old files of every project when they exceed `max_size` bytes.
+pub fn prepare(project_dir: &Path, root: &Path, max_size: Option<u64>) {
+    if let (Some(max_size), Some(cache_root)) = (max_size, project_dir.parent()) {
+        if let Err(e) = enforce_max_size(cache_root, max_size) {
+            eprintln!("treetags: cannot trim plugin caches: {e:#}");
+        }
+    }
+    let record = ProjectRecord {
+        path: root.to_path_buf(),
+        last_used: unix_secs(SystemTime::now()),
+    };
+    let result = std::fs::create_dir_all(project_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| Ok(toml::to_string(&record)?))
+        .and_then(|text| Ok(std::fs::write(project_dir.join(PROJECT_FILE), text)?));
+    if let Err(e) = result {
+        eprintln!(
+            "treetags: cannot record project for cache dir {}: {e}",
+            project_dir.display()
+        );
+    }
+}
+
+/// `treetags cache list` — one row per project and plugin.
+pub fn list(filter: &CacheFilter, json: bool) -> Result<()> {
+    let entries = scan(&get_cache_dir(), filter)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No plugin caches found.");
+        return Ok(());
+    }
+    let now = SystemTime::now();
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|e| {
+            vec![
+                e.hash.clone(),
+                e.plugin.clone(),
+                e.files.to_string(),
+                format_size(e.bytes),
+                format_age(now, e.last_written),
+                e.project
+                    .as_ref()
+                    .map_or_else(|| "(unknown)".to_string(), |p| p.display().to_string()),
+            ]
+        })
+        .collect();
+    super::client::print_rows(
+        &["HASH", "PLUGIN", "FILES", "SIZE", "WRITTEN", "PROJECT"],
+        &rows,
+    );
+    Ok(())
+}
+
+/// `treetags cache size` — total size of the selected cache files.
+pub fn size(filter: &CacheFilter) -> Result<()> {
+    let entries = scan(&get_cache_dir(), filter)?;
+    let files: u64 = entries.iter().map(|e| e.files).sum();
+    let bytes: u64 = entries.iter().map(|e| e.bytes).sum();
+    println!("{} in {files} file(s)", format_size(bytes));
+    Ok(())
+}
+
+/// `treetags cache clean` — removes the selected cache files.
+pub fn clean(filter: &CacheFilter) -> Result<()> {
+    let cache_root = get_cache_dir();
+    let entries = scan(&cache_root, filter)?;
+    let mut files = 0;
+    let mut bytes = 0;
+    for entry in &entries {
+        for path in &entry.paths {
+            std::fs::remove_file(path).with_context(|| format!("removing {}", path.display()))?;
+        }
+        files += entry.files;
+        bytes += entry.bytes;
+    }
+    prune_empty(&cache_root);
+    println!("Removed {files} file(s), {}.", format_size(bytes));
+    Ok(())
+}
+
+/// Collects the cache files selected by `filter`, grouped by project and
+/// plugin. Entries without any selected file are left out.
+fn scan(cache_root: &Path, filter: &CacheFilter) -> Result<Vec<CacheEntry>> {
+    let project_hash = match &filter.project {
+        Some(path) => {
+            let root = std::fs::canonicalize(path)
+                .with_context(|| format!("project directory {}", path.display()))?;
+            Some(project_hash(&root))
+        }
+        None => None,
+    };
+    let cutoff = filter
+        .older_than
+        .map(|age| SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH));
+
+    let mut entries = Vec::new();
+    for project_dir in project_dirs(cache_root) {
+        let hash = file_name(&project_dir);
+        if project_hash.as_ref().is_some_and(|h| *h != hash) {
+            continue;
+        }
+        let project = read_record(&project_dir).map(|r| r.path);
+        let Ok(plugins) = std::fs::read_dir(&project_dir) else {
+            continue;
+        };
+        let mut plugin_dirs: Vec<PathBuf> = plugins
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect();
+        plugin_dirs.sort();
+        for plugin_dir in plugin_dirs {
+            let plugin = file_name(&plugin_dir);
+            if filter.plugin.as_ref().is_some_and(|p| *p != plugin) {
+                continue;
+            }
+            let mut entry = CacheEntry {
+                hash: hash.clone(),
+                project: project.clone(),
+                plugin,
+                files: 0,
+                bytes: 0,
+                last_written: 0,
+                paths: Vec::new(),
+            };
+            for (path, len, modified) in cache_files(&plugin_dir) {
+                if cutoff.is_some_and(|cutoff| modified > cutoff) {
+                    continue;
+                }
+                entry.files += 1;
+                entry.bytes += len;
+                entry.last_written = entry.last_written.max(unix_secs(modified));
+                entry.paths.push(path);
+            }
+            if entry.files > 0 {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Removes the least recently written cache files of every project until
+/// their total size is at most `max_size` bytes.
+fn enforce_max_size(cache_root: &Path, max_size: u64) -> Result<()> {
+    let mut files: Vec<(PathBuf, u64, SystemTime)> = project_dirs(cache_root)
+        .iter()
+        .flat_map(|project_dir| std::fs::read_dir(project_dir).into_iter().flatten())
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .flat_map(|plugin_dir| cache_files(&plugin_dir))
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if total <= max_size {
+        return Ok(());
+    }
+    files.sort_by_key(|(_, _, modified)| *modified);
+    for (path, len, _) in files {
+        if total <= max_size {
+            break;
+        }
+        std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+        total -= len;
+    }
+    prune_empty(cache_root);
+    Ok(())
+}
+
+/// Removes empty plugin directories, and project directories left with
+/// nothing but their `project.toml`.
+fn prune_empty(cache_root: &Path) {
+    for project_dir in project_dirs(cache_root) {
+        let Ok(children) = std::fs::read_dir(&project_dir) else {
+            continue;
+        };
+        let mut keep = false;
+        for child in children.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if child.is_dir() {
+                // Only succeeds when the directory is empty.
+                keep |= std::fs::remove_dir(&child).is_err();
+            } else if file_name(&child) != PROJECT_FILE {
+                keep = true;
+            }
+        }
+        if !keep {
+            let _ = std::fs::remove_file(project_dir.join(PROJECT_FILE));
+            let _ = std::fs::remove_dir(&project_dir);
+        }
+    }
+}
+
+/// Project cache directories under `cache_root`: those named by a 16-digit
+/// hex project hash. The cache root also holds the plugin index caches.
+fn project_dirs(cache_root: &Path) -> Vec<PathBuf> {
+    let Ok(read_dir) = std::fs::read_dir(cache_root) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = read_dir
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .filter(|p| {
+            let name = file_name(p);
+            name.len() == 16 && name.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Files directly inside `plugin_dir`, with their size and modification time.
+fn cache_files(plugin_dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(read_dir) = std::fs::read_dir(plugin_dir) else {
+        return Vec::new();
+    };
+    read_dir
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            meta.is_file()
+                .then(|| (e.path(), meta.len(), meta.modified().unwrap_or(UNIX_EPOCH)))
+        })
+        .collect()
+}
+
+fn read_record(project_dir: &Path) -> Option<ProjectRecord> {
+    let text = std::fs::read_to_string(project_dir.join(PROJECT_FILE)).ok()?;
+    toml::from_str(&text).ok()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Parses an age such as `30d`, `12h`, `2w`, `90m` or `45s`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let Ok(n) = digits.parse::<u64>() else {
+        bail!("invalid age `{s}`; expected a number with a unit, e.g. `30d`");
+    };
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("invalid age unit in `{s}`; use s, m, h, d or w"),
+    };
+    Ok(Duration::from_secs(n.saturating_mul(secs)))
+}
+
+/// Parses a size such as `500M`, `2G`, `64K` or `1048576` (bytes).
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let Ok(n) = digits.parse::<u64>() else {
+        bail!("invalid size `{s}`; expected a number with an optional unit, e.g. `500M`");
+    };
+    let factor: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" | "KI" => 1 << 10,
+        "M" | "MI" => 1 << 20,
+        "G" | "GI" => 1 << 30,
+        _ => bail!("invalid size unit in `{s}`; use K, M or G"),
+    };
+    Ok(n.saturating_mul(factor))
+}
+
+/// Formats a byte count for display, e.g. `1.5 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{value:.1} {unit}")
+}
+
+/// Formats how long ago `secs` (Unix time) was, e.g. `3d ago`.
+fn format_age(now: SystemTime, secs: u64) -> String {
+    let age = unix_secs(now).saturating_sub(secs);
+    match age {
+        0..=59 => format!("{age}s ago"),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_cache_file(root: &Path, hash: &str, plugin: &str, name: &str, len: usize, age: u64) {
+        let dir = root.join(hash).join(plugin);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, vec![b'x'; len]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn scan_filters_by_plugin_and_age() {
+        let root = tempfile::tempdir().unwrap();
+        let a = "00000000000000aa";
+        write_cache_file(root.path(), a, "java", "1.cache", 10, 0);
+        write_cache_file(root.path(), a, "java", "2.cache", 20, 3 * 86400);
+        write_cache_file(root.path(), a, "kotlin", "3.cache", 5, 0);
+        // Not a project hash: ignored.
+        fs::create_dir_all(root.path().join("index-dir")).unwrap();
+        fs::write(root.path().join("index-v4.json"), "{}").unwrap();
+
+        let all = scan(root.path(), &CacheFilter::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(
+            (all[0].plugin.as_str(), all[0].files, all[0].bytes),
+            ("java", 2, 30)
+        );
+        assert_eq!(all[0].project, None);
+
+        let old = CacheFilter {
+            older_than: Some(Duration::from_secs(86400)),
+            ..Default::default()
+        };
+        let entries = scan(root.path(), &old).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].bytes, 20);
+
+        let kotlin = CacheFilter {
+            plugin: Some("kotlin".into()),
+            ..Default::default()
+        };
+        assert_eq!(scan(root.path(), &kotlin).unwrap()[0].bytes, 5);
+    }
+
+    #[test]
+    fn max_size_evicts_oldest_files_and_prunes_projects() {
+        let root = tempfile::tempdir().unwrap();
+        let (a, b) = ("00000000000000aa", "00000000000000bb");
+        write_cache_file(root.path(), a, "java", "old.cache", 100, 300);
+        write_cache_file(root.path(), b, "java", "mid.cache", 100, 200);
+        write_cache_file(root.path(), b, "java", "new.cache", 100, 100);
+        prepare(&root.path().join(b), Path::new("/work/b"), None);
+
+        enforce_max_size(root.path(), 250).unwrap();
+        assert!(!root.path().join(a).exists());
+        assert!(root.path().join(b).join("java/mid.cache").exists());
+
+        enforce_max_size(root.path(), 150).unwrap();
+        assert!(!root.path().join(b).join("java/mid.cache").exists());
+        assert!(root.path().join(b).join("java/new.cache").exists());
+        assert_eq!(
+            read_record(&root.path().join(b)).map(|r| r.path),
+            Some(PathBuf::from("/work/b"))
+        );
+    }
+
+    #[test]
+    fn parses_ages_and_sizes() {
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86400)
+        );
+        assert_eq!(
+            parse_duration("2w").unwrap(),
+            Duration::from_secs(14 * 86400)
+        );
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64K").unwrap(), 64 << 10);
+        assert_eq!(parse_size("500MB").unwrap(), 500 << 20);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 << 30);
+        assert!(parse_size("5T").is_err());
+        assert_eq!(format_size(1536), "1.5 KiB");
+    }
+}
@@ -1,9 +1,19 @@
 //! Client-side plugin management: discovering, installing, and updating plugins
 //! from the distribution index published for this build's `PLUGIN_ABI_VERSION`.
 //!
-//! The binary only ever talks to its own ABI bucket (`{base}/{abi}/index.json`),
-//! so an older treetags keeps resolving the last plugins that were compatible
-//! with it. Downloads are verified against the `wasm_sha256` in the index.
+//! The binary reads its own ABI bucket (`{base}/{abi}/index.json`), so an
+//! older treetags keeps resolving the last plugins that were compatible with
+//! it. Plugins missing from that bucket are taken from the buckets of the
+//! older ABIs it still loads (`SUPPORTED_ABI_VERSIONS`), so a plugin not yet
+//! rebuilt after an ABI bump stays installable. The base URL may list several
+//! comma-separated mirrors, tried in order, and `file://` URLs read from the
+//! local filesystem. Relative artifact URLs in an index are resolved against
+//! the mirror that served it, falling back to the other mirrors. Plugins can
+//! also be installed straight from a built directory or bundle (see
+//! [`super::bundle`]).
+//! Downloads are verified against the `wasm_sha256` in the index, and
+//! installs additionally require the index and every artifact to carry a
+//! signature from a trusted key (see [`super::signing`]).
 
 use std::cmp::Ordering;
 use std::collections::{BTreeSet, HashMap};
@@ -12,23 +22,32 @@ use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
 
-use super::index::{sha256_hex, IndexEntry, PluginIndex};
+use super::bundle::{BundleFormat, LocalPlugin};
+use super::index::{sha256_hex, IndexEntry, IndexKind, PluginIndex};
+use super::lockfile::{LockedPlugin, Lockfile, LOCKFILE_NAME};
 use super::manifest::PluginManifest;
-use super::PLUGIN_ABI_VERSION;
+use super::signing::TrustPolicy;
+use super::{PLUGIN_ABI_VERSION, SUPPORTED_ABI_VERSIONS};
 use crate::config::paths::get_cache_dir;
 
 /// Default site hosting the per-ABI plugin index (Jekyll site assumed).
 /// Overridable via `--plugin-index-url` or the `TREETAGS_PLUGIN_INDEX` env var.
 pub const DEFAULT_PLUGIN_INDEX_BASE: &str = "https://namanjha.in/treetags/plugins";
 
+/// Directory under the plugins directory holding each plugin's previous install.
+const ROLLBACK_DIR: &str = ".rollback";
+
 /// How long a cached index is served before we re-fetch.
 const INDEX_CACHE_TTL: Duration = Duration::from_secs(3600);
 
 /// A plugin found in the local plugins directory.
 struct InstalledPlugin {
     version: String,
+    abi_version: u32,
     dir: PathBuf,
+    wasm_path: PathBuf,
 }
 
 /// Resolves the index base URL: `--plugin-index-url` > `$TREETAGS_PLUGIN_INDEX`
@@ -47,16 +66,47 @@ pub fn resolve_base_url(cli_override: Option<&str>) -> String {
     DEFAULT_PLUGIN_INDEX_BASE.to_string()
 }
 
-fn index_url(base: &str) -> String {
-    format!(
-        "{}/{}/index.json",
-        base.trim_end_matches('/'),
-        PLUGIN_ABI_VERSION
-    )
+/// The mirrors listed in a base URL, in the order they are tried.
+fn mirrors(base: &str) -> impl Iterator<Item = &str> {
+    base.split(',').map(str::trim).filter(|m| !m.is_empty())
+}
+
+fn index_url(base: &str, abi: u32) -> String {
+    format!("{}/{abi}/index.json", base.trim_end_matches('/'))
+}
+
+/// The URLs to try, in order, for an artifact `url` listed in `entry`'s index.
+/// An absolute URL is used as is. A relative one is resolved against the ABI
+/// bucket of the mirror that served the index, then of the other mirrors of
+/// `base` in order.
+fn artifact_urls(url: &str, base: &str, entry: &IndexEntry) -> Vec<String> {
+    if url.contains("://") {
+        return vec![url.to_string()];
+    }
+    let abi = entry_abi(entry);
+    let relative = url.trim_start_matches("./");
+    entry
+        .mirror
+        .as_deref()
+        .into_iter()
+        .chain(mirrors(base).filter(|m| Some(*m) != entry.mirror.as_deref()))
+        .map(|mirror| format!("{}/{abi}/{relative}", mirror.trim_end_matches('/')))
+        .collect()
+}
+
+/// Cache file of the `abi` bucket fetched from `base`. Keyed by the base URL
+/// as well, so switching `--plugin-index-url` never serves another source's
+/// index or resolves its relative artifact URLs against the new mirrors.
+fn index_cache_path(base: &str, abi: u32) -> PathBuf {
+    let key = &sha256_hex(base.as_bytes())[..16];
+    get_cache_dir().join(format!("index-v{abi}-{key}.json"))
 }
 
-fn index_cache_path() -> PathBuf {
-    get_cache_dir().join(format!("index-v{PLUGIN_ABI_VERSION}.json"))
+/// Detached signature published next to a file, e.g. `index.json.sig`.
+fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
 }
 
 // ---------------------------------------------------------------------------
@@ -65,60 +115,340 @@ fn index_cache_path() -> PathBuf {
 
 /// `treetags plugin available` — list plugins downloadable for this ABI.
 pub fn available(base: &str, refresh: bool, plugins_dir: &Path) -> Result<()> {
-    let index = fetch_index(base, refresh)?;
+    let index = fetch_index(base, refresh, None)?;
     if index.plugins.is_empty() {
         println!("No plugins available for ABI {PLUGIN_ABI_VERSION}.");
         return Ok(());
     }
     let installed = installed_plugins(plugins_dir);
-
     let rows: Vec<Vec<String>> = index
         .plugins
         .iter()
-        .map(|p| {
-            let status = match installed.get(&p.name) {
-                None => String::new(),
-                Some(local) => status_label(&local.version, &p.version),
-            };
-            vec![
-                p.name.clone(),
-                p.version.clone(),
-                p.language.clone().unwrap_or_default(),
-                p.extensions.join(", "),
-                status,
-            ]
-        })
+        .map(|p| index_row(p, &installed))
         .collect();
+    print_rows(INDEX_HEADERS, &rows);
+    Ok(())
+}
 
-    print_rows(
-        &["NAME", "VERSION", "LANGUAGE", "EXTENSIONS", "STATUS"],
-        &rows,
-    );
+/// `treetags plugin search <term>` — list plugins whose name, language,
+/// aliases, extensions or filename patterns match `term`.
+pub fn search(base: &str, term: &str, refresh: bool, plugins_dir: &Path, json: bool) -> Result<()> {
+    let index = fetch_index(base, refresh, None)?;
+    let installed = installed_plugins(plugins_dir);
+    let hits: Vec<&IndexEntry> = index
+        .plugins
+        .iter()
+        .filter(|p| matches_term(p, term))
+        .collect();
+
+    if json {
+        let results: Vec<SearchResult> = hits
+            .iter()
+            .map(|entry| SearchResult {
+                entry,
+                installed_version: installed.get(&entry.name).map(|p| p.version.as_str()),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+    if hits.is_empty() {
+        println!("No plugins matching `{term}` available for ABI {PLUGIN_ABI_VERSION}.");
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = hits.iter().map(|p| index_row(p, &installed)).collect();
+    print_rows(INDEX_HEADERS, &rows);
     Ok(())
 }
 
-/// `treetags plugin install <name>` — download, verify, and place a plugin.
+/// A `plugin search --json` result: the full index entry plus the installed
+/// version, if any.
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    #[serde(flatten)]
+    entry: &'a IndexEntry,
+    installed_version: Option<&'a str>,
+}
+
+/// A language treetags supports natively, for `plugin info` to report which
+/// ones a plugin takes extensions from.
+pub struct NativeLanguage {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+/// Local state `plugin info` reports on besides the index: the plugins
+/// directory, the sandbox grants on the command line and the native languages.
+pub struct HostContext<'a> {
+    pub plugins_dir: &'a Path,
+    pub cache_grants: &'a [String],
+    pub project_read_grants: &'a [String],
+    pub native_languages: &'a [NativeLanguage],
+}
+
+/// `treetags plugin info <name>[@<version>]` — the plugin's index entry (or
+/// that of the given version), its installed manifest, the access it is
+/// granted and the native languages it shadows. An installed plugin missing
+/// from the index, or an unreachable index, still shows the local details.
+pub fn info(base: &str, spec: &str, refresh: bool, host: &HostContext, json: bool) -> Result<()> {
+    let (name, version) = parse_spec(spec);
+    let installed = installed_plugins(host.plugins_dir).remove(name);
+    let local_only = installed.is_some() && version.is_none();
+    let entry = match fetch_index(base, refresh, None) {
+        Ok(index) => match find_entry(&index, name) {
+            Ok(entry) => Some(match version {
+                Some(version) => find_version(entry, version)?.clone(),
+                None => entry.clone(),
+            }),
+            Err(_) if local_only => None,
+            Err(e) => return Err(e),
+        },
+        Err(e) if local_only => {
+            eprintln!("warning: {e:#}; showing the installed plugin only");
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    let installed = installed.map(|local| InstalledInfo::read(&local));
+    let extensions = match (&installed, &entry) {
+        (Some(local), _) => &local.extensions,
+        (None, Some(entry)) => &entry.extensions,
+        (None, None) => unreachable!("either the index or the plugins dir has the plugin"),
+    };
+    let info = PluginInfo {
+        name: name.to_string(),
+        shadows: shadowed_languages(extensions, host.native_languages),
+        index: entry,
+        installed,
+        cache_access: host.cache_grants.iter().any(|g| g == name),
+        project_read: host.project_read_grants.iter().any(|g| g == name),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        info.print();
+    }
+    Ok(())
+}
+
+/// Everything `plugin info` reports, as printed by `--json`.
+#[derive(Serialize)]
+struct PluginInfo {
+    name: String,
+    /// The index entry, with every earlier published version.
+    index: Option<IndexEntry>,
+    installed: Option<InstalledInfo>,
+    /// Granted with `--plugin-cache`.
+    cache_access: bool,
+    /// Granted with `--plugin-project-read`.
+    project_read: bool,
+    /// Native languages whose extensions this plugin takes over.
+    shadows: Vec<ShadowedLanguage>,
+}
+
+/// The installed copy of a plugin, from its `plugin.toml` and `.wasm`.
+#[derive(Serialize)]
+struct InstalledInfo {
+    version: String,
+    dir: PathBuf,
+    language: Option<String>,
+    extensions: Vec<String>,
+    aliases: Vec<String>,
+    patterns: Vec<String>,
+    interpreters: Vec<String>,
+    kinds: Vec<IndexKind>,
+    /// Set for declarative plugins.
+    grammar: Option<String>,
+    wasm_size: Option<u64>,
+    wasm_sha256: Option<String>,
+}
+
+impl InstalledInfo {
+    fn read(local: &InstalledPlugin) -> Self {
+        let manifest = read_manifest(&local.dir);
+        let wasm = std::fs::read(&local.wasm_path).ok();
+        let m = manifest.as_ref();
+        InstalledInfo {
+            version: local.version.clone(),
+            dir: local.dir.clone(),
+            language: m.and_then(|m| m.language.clone()),
+            extensions: m.map(|m| m.extensions.clone()).unwrap_or_default(),
+            aliases: m.map(|m| m.aliases.clone()).unwrap_or_default(),
+            patterns: m.map(|m| m.patterns.clone()).unwrap_or_default(),
+            interpreters: m.map(|m| m.interpreters.clone()).unwrap_or_default(),
+            kinds: m
+                .and_then(|m| m.kinds.as_ref())
+                .map(|kinds| kinds.iter().map(IndexKind::from).collect())
+                .unwrap_or_default(),
+            grammar: m.and_then(|m| m.grammar.clone()),
+            wasm_size: wasm.as_ref().map(|w| w.len() as u64),
+            wasm_sha256: wasm.as_deref().map(sha256_hex),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct ShadowedLanguage {
+    language: String,
+    extensions: Vec<String>,
+}
+
+impl PluginInfo {
+    fn print(&self) {
+        println!("{}", self.name);
+        if let Some(entry) = &self.index {
+            let older: Vec<&str> = entry
+                .previous_versions
+                .iter()
+                .map(|v| v.version.as_str())
+                .collect();
+            println!("\nIndex (ABI {}):", entry_abi(entry));
+            print_fields(&[
+                ("Version", entry.version.clone()),
+                ("Older versions", older.join(", ")),
+                ("Language", entry.language.clone().unwrap_or_default()),
+                ("Extensions", ext_globs(&entry.extensions)),
+                ("Aliases", entry.aliases.join(", ")),
+                ("Patterns", entry.patterns.join(", ")),
+                ("Interpreters", entry.interpreters.join(", ")),
+                (
+                    "Kinds",
+                    kinds_label(entry.kinds.as_deref().unwrap_or_default()),
+                ),
+                ("Size", format!("{} bytes", entry.wasm_size)),
+                ("SHA-256", entry.wasm_sha256.clone()),
+                ("Signed", yes_no(entry.wasm_signature.is_some()).to_string()),
+                ("Download", entry.wasm_url.clone()),
+            ]);
+        }
+        println!("\nInstalled:");
+        match &self.installed {
+            None => println!("  no"),
+            Some(local) => {
+                let status = match &self.index {
+                    Some(entry) => status_label(&local.version, &entry.version),
+                    None => "not in the index".to_string(),
+                };
+                print_fields(&[
+                    ("Version", format!("{} ({status})", local.version)),
+                    ("Directory", local.dir.display().to_string()),
+                    ("Language", local.language.clone().unwrap_or_default()),
+                    ("Extensions", ext_globs(&local.extensions)),
+                    ("Aliases", local.aliases.join(", ")),
+                    ("Patterns", local.patterns.join(", ")),
+                    ("Interpreters", local.interpreters.join(", ")),
+                    ("Kinds", kinds_label(&local.kinds)),
+                    ("Grammar", local.grammar.clone().unwrap_or_default()),
+                    (
+                        "Size",
+                        local
+                            .wasm_size
+                            .map(|size| format!("{size} bytes"))
+                            .unwrap_or_else(|| "missing .wasm".to_string()),
+                    ),
+                    ("SHA-256", local.wasm_sha256.clone().unwrap_or_default()),
+                ]);
+            }
+        }
+        println!("\nAccess:");
+        let grant = |granted: bool, flag: &str| {
+            if granted {
+                "granted".to_string()
+            } else {
+                format!("not granted (pass `{flag} {}`)", self.name)
+            }
+        };
+        print_fields(&[
+            ("Cache files", grant(self.cache_access, "--plugin-cache")),
+            (
+                "Project read",
+                grant(self.project_read, "--plugin-project-read"),
+            ),
+        ]);
+        if !self.shadows.is_empty() {
+            println!("\nShadows native languages:");
+            for shadowed in &self.shadows {
+                println!(
+                    "  {} ({})",
+                    shadowed.language,
+                    ext_globs(&shadowed.extensions)
+                );
+            }
+        }
+    }
+}
+
+/// Native languages sharing an extension with `extensions`, which the plugin
+/// takes over because plugins are tried first.
+fn shadowed_languages(extensions: &[String], native: &[NativeLanguage]) -> Vec<ShadowedLanguage> {
+    native
+        .iter()
+        .filter_map(|lang| {
+            let shared: Vec<String> = lang
+                .extensions
+                .iter()
+                .filter(|e| extensions.iter().any(|p| p.eq_ignore_ascii_case(e)))
+                .cloned()
+                .collect();
+            (!shared.is_empty()).then(|| ShadowedLanguage {
+                language: lang.name.clone(),
+                extensions: shared,
+            })
+        })
+        .collect()
+}
+
+/// Whether `term` (case-insensitive) appears in a plugin's name, language or
+/// aliases, is one of its extensions (with or without a leading `*.`/`.`) or
+/// appears in one of its filename patterns.
+fn matches_term(entry: &IndexEntry, term: &str) -> bool {
+    let term = term.to_lowercase();
+    let ext = term.trim_start_matches('*').trim_start_matches('.');
+    let contains = |s: &str| s.to_lowercase().contains(&term);
+    contains(&entry.name)
+        || entry.language.as_deref().is_some_and(contains)
+        || entry.aliases.iter().any(|a| contains(a))
+        || entry.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+        || entry.patterns.iter().any(|p| contains(p))
+}
+
+/// `treetags plugin install <name>[@<version>]` — download, verify, and place
+/// a plugin, recording it in the project lockfile if there is one (or `lock`
+/// is set). An explicit version may downgrade an installed plugin. A `spec`
+/// that looks like a path installs a local plugin directory or bundle instead.
 pub fn install(
     base: &str,
-    name: &str,
+    spec: &str,
     force: bool,
     refresh: bool,
     plugins_dir: &Path,
+    trust: &TrustPolicy,
+    lock: bool,
 ) -> Result<()> {
-    let index = fetch_index(base, refresh)?;
-    let entry = find_entry(&index, name)?;
+    if is_local_spec(spec) {
+        return install_local(Path::new(spec), plugins_dir, lock);
+    }
+    let (name, version) = parse_spec(spec);
+    let index = fetch_index(base, refresh, Some(trust))?;
+    let latest = find_entry(&index, name)?;
+    let entry = match version {
+        None => latest,
+        Some(version) => find_version(latest, version)?,
+    };
 
     if !force {
         if let Some(local) = installed_plugins(plugins_dir).get(name) {
             match cmp_versions(&local.version, &entry.version) {
-                Some(Ordering::Equal) => {
+                Some(Ordering::Equal) if local.abi_version >= entry_abi(entry) => {
                     println!(
                         "`{name}` {} is already installed and up to date.",
                         local.version
                     );
-                    return Ok(());
+                    return record_lock(base, entry, lock);
                 }
-                Some(Ordering::Greater) => {
+                Some(Ordering::Greater) if version.is_none() => {
                     println!(
                         "`{name}` {} is newer than the available {} — keeping it (use --force to override).",
                         local.version, entry.version
@@ -130,16 +460,53 @@ pub fn install(
         }
     }
 
-    let dir = download_and_place(entry, plugins_dir)?;
+    let dir = download_and_place(base, entry, plugins_dir, trust)?;
     println!(
         "Installed `{}` {} to {}",
         entry.name,
         entry.version,
         dir.display()
     );
+    if let Some(abi) = entry.abi_version {
+        eprintln!(
+            "warning: `{name}` has not been rebuilt for ABI {PLUGIN_ABI_VERSION} yet; \
+             installed its ABI {abi} build"
+        );
+    }
+    record_lock(base, entry, lock)
+}
+
+/// Installs a built plugin directory or `.tar`/`.zip` bundle. It is trusted
+/// as given, so no index signatures apply, but the manifest, ABI and any
+/// `SHA256SUMS` are checked like a download.
+fn install_local(path: &Path, plugins_dir: &Path, lock: bool) -> Result<()> {
+    let plugin = LocalPlugin::read(path)?;
+    let files: Vec<(&str, Vec<u8>)> = plugin
+        .files
+        .iter()
+        .map(|(name, bytes)| (name.as_str(), bytes.clone()))
+        .collect();
+    let dir = place(plugins_dir, &plugin.manifest.name, &files)?;
+    println!(
+        "Installed `{}` {} from {} to {}",
+        plugin.manifest.name,
+        plugin.manifest.version,
+        path.display(),
+        dir.display()
+    );
+    if lock {
+        eprintln!(
+            "warning: plugins installed from a local path are not recorded in {LOCKFILE_NAME}"
+        );
+    }
     Ok(())
 }
 
+/// Whether an install spec names a local path rather than a plugin.
+fn is_local_spec(spec: &str) -> bool {
+    spec.contains(['/', '\\']) || BundleFormat::from_path(Path::new(spec)).is_some()
+}
+
 /// `treetags --suggest-plugins` — list uninstalled plugins from the index that
 /// could handle files in the tree. Covers both file types nothing handles today
 /// (the plugin *adds* support) and natively-supported types (the plugin is an
@@ -154,7 +521,7 @@ pub fn suggest(
     handled_exts: &BTreeSet<String>,
     plugins_dir: &Path,
 ) -> Result<()> {
-    let index = fetch_index(base, false)?;
+    let index = fetch_index(base, false, None)?;
     let installed = installed_plugins(plugins_dir);
 
     let mut rows: Vec<Vec<String>> = Vec::new();
@@ -216,7 +583,9 @@ pub fn suggest(
     Ok(())
 }
 
-/// `treetags plugin uninstall <name>` — remove a locally installed plugin.
+/// `treetags plugin uninstall <name>` — remove a locally installed plugin,
+/// dropping it from the project lockfile if there is one so `sync` does not
+/// bring it back.
 pub fn uninstall(name: &str, plugins_dir: &Path) -> Result<()> {
     match installed_plugins(plugins_dir).get(name) {
         None => {
@@ -226,15 +595,75 @@ pub fn uninstall(name: &str, plugins_dir: &Path) -> Result<()> {
         Some(local) => {
             std::fs::remove_dir_all(&local.dir)
                 .with_context(|| format!("removing {}", local.dir.display()))?;
+            let _ = std::fs::remove_dir_all(rollback_dir(plugins_dir, name));
             println!("Uninstalled `{name}` from {}", local.dir.display());
+
+            let path = Path::new(LOCKFILE_NAME);
+            if let Some(mut lock) = Lockfile::load(path)? {
+                if lock.unlock(name) {
+                    lock.save(path)?;
+                }
+            }
             Ok(())
         }
     }
 }
 
+/// `treetags plugin rollback <name>` — swap the installed plugin with the
+/// version it replaced. Rolling back twice restores the newer version.
+pub fn rollback(name: &str, plugins_dir: &Path) -> Result<()> {
+    let previous_dir = rollback_dir(plugins_dir, name);
+    let Some(previous) = read_manifest(&previous_dir) else {
+        bail!("no previous version of `{name}` to roll back to");
+    };
+    let current_dir = plugins_dir.join(name);
+    let current = read_manifest(&current_dir);
+    let tmp_dir = plugins_dir.join(format!(".{name}.tmp"));
+
+    let _ = std::fs::remove_dir_all(&tmp_dir);
+    if current_dir.exists() {
+        std::fs::rename(&current_dir, &tmp_dir)
+            .with_context(|| format!("moving aside {}", current_dir.display()))?;
+    }
+    std::fs::rename(&previous_dir, &current_dir)
+        .with_context(|| format!("restoring {}", previous_dir.display()))?;
+    if tmp_dir.exists() {
+        std::fs::rename(&tmp_dir, &previous_dir)
+            .with_context(|| format!("keeping {}", previous_dir.display()))?;
+    }
+
+    match &current {
+        Some(current) => println!(
+            "Rolled back `{name}` {} -> {}",
+            current.version, previous.version
+        ),
+        None => println!("Restored `{name}` {}", previous.version),
+    }
+
+    // Keep an existing lockfile pointing at what is now installed.
+    let path = Path::new(LOCKFILE_NAME);
+    if let Some(mut lock) = Lockfile::load(path)? {
+        let wasm = std::fs::read(previous.wasm_path(&current_dir))
+            .with_context(|| format!("reading the restored `{name}` plugin"))?;
+        lock.lock(LockedPlugin {
+            name: name.to_string(),
+            version: previous.version,
+            wasm_sha256: sha256_hex(&wasm),
+        });
+        lock.save(path)?;
+    }
+    Ok(())
+}
+
 /// `treetags plugin update [name]` — reinstall installed plugins whose available
 /// version is newer. With no name, updates all installed plugins.
-pub fn update(base: &str, name: Option<&str>, refresh: bool, plugins_dir: &Path) -> Result<()> {
+pub fn update(
+    base: &str,
+    name: Option<&str>,
+    refresh: bool,
+    plugins_dir: &Path,
+    trust: &TrustPolicy,
+) -> Result<()> {
     let installed = installed_plugins(plugins_dir);
     if installed.is_empty() {
         println!("No plugins installed under {}.", plugins_dir.display());
@@ -246,7 +675,7 @@ pub fn update(base: &str, name: Option<&str>, refresh: bool, plugins_dir: &Path)
         }
     }
 
-    let index = fetch_index(base, refresh)?;
+    let index = fetch_index(base, refresh, Some(trust))?;
     let mut updated = 0;
     for (pname, local) in &installed {
         if let Some(filter) = name {
@@ -257,9 +686,16 @@ pub fn update(base: &str, name: Option<&str>, refresh: bool, plugins_dir: &Path)
         let Some(entry) = index.plugins.iter().find(|p| &p.name == pname) else {
             continue;
         };
-        if cmp_versions(&local.version, &entry.version) == Some(Ordering::Less) {
-            download_and_place(entry, plugins_dir)?;
+        let newer = match cmp_versions(&local.version, &entry.version) {
+            Some(Ordering::Less) => true,
+            // The same version rebuilt for a newer ABI.
+            Some(Ordering::Equal) => local.abi_version < entry_abi(entry),
+            _ => false,
+        };
+        if newer {
+            download_and_place(base, entry, plugins_dir, trust)?;
             println!("Updated `{pname}` {} -> {}", local.version, entry.version);
+            record_lock(base, entry, false)?;
             updated += 1;
         }
     }
@@ -269,47 +705,257 @@ pub fn update(base: &str, name: Option<&str>, refresh: bool, plugins_dir: &Path)
     Ok(())
 }
 
+/// `treetags plugin sync` — install exactly the plugin versions pinned in the
+/// project lockfile. The index URL comes from `--plugin-index-url` when given,
+/// else from the lockfile. Plugins not in the lockfile are left alone.
+pub fn sync(
+    base_override: Option<&str>,
+    refresh: bool,
+    plugins_dir: &Path,
+    trust: &TrustPolicy,
+) -> Result<()> {
+    let Some(lock) = Lockfile::load(Path::new(LOCKFILE_NAME))? else {
+        bail!(
+            "no {LOCKFILE_NAME} in the current directory\n\
+             Create one with `treetags plugin install --lock <name>`."
+        );
+    };
+    if lock.plugins.is_empty() {
+        println!("{LOCKFILE_NAME} does not lock any plugins.");
+        return Ok(());
+    }
+    let base = resolve_base_url(base_override.or(Some(lock.index_url.as_str())));
+    let installed = installed_plugins(plugins_dir);
+
+    // Resolve every locked plugin before touching the plugins directory.
+    let mut pending = Vec::new();
+    let mut index = None;
+    for locked in &lock.plugins {
+        let in_sync = installed.get(&locked.name).is_some_and(|local| {
+            local.version == locked.version
+                && std::fs::read(&local.wasm_path)
+                    .is_ok_and(|wasm| sha256_hex(&wasm) == locked.wasm_sha256)
+        });
+        if in_sync {
+            continue;
+        }
+        if index.is_none() {
+            index = Some(fetch_index(&base, refresh, Some(trust))?);
+        }
+        let latest = find_entry(index.as_ref().unwrap(), &locked.name)?;
+        let entry = find_version(latest, &locked.version)?;
+        if entry.wasm_sha256 != locked.wasm_sha256 {
+            bail!(
+                "`{}` {} in the index at {base} does not match the checksum in {LOCKFILE_NAME}",
+                locked.name,
+                locked.version,
+            );
+        }
+        pending.push(entry.clone());
+    }
+
+    if pending.is_empty() {
+        println!("All locked plugins are installed.");
+        return Ok(());
+    }
+    for entry in &pending {
+        download_and_place(&base, entry, plugins_dir, trust)?;
+        println!("Installed `{}` {}", entry.name, entry.version);
+    }
+    Ok(())
+}
+
+/// Records `entry` in the project lockfile if one exists, creating it first
+/// when `create` is set.
+fn record_lock(base: &str, entry: &IndexEntry, create: bool) -> Result<()> {
+    let path = Path::new(LOCKFILE_NAME);
+    let mut lock = match Lockfile::load(path)? {
+        Some(lock) => lock,
+        None if create => Lockfile::default(),
+        None => return Ok(()),
+    };
+    if lock.index_url != base {
+        if !lock.index_url.is_empty() {
+            eprintln!(
+                "warning: {LOCKFILE_NAME} index URL changed from {} to {base}",
+                lock.index_url
+            );
+        }
+        lock.index_url = base.to_string();
+    }
+    lock.lock(LockedPlugin::from(entry));
+    lock.save(path)
+}
+
 // ---------------------------------------------------------------------------
 // Index fetching + caching
 // ---------------------------------------------------------------------------
 
-/// Fetches this ABI's index, using a short-lived on-disk cache. On a network
-/// failure it falls back to a stale cache if one exists.
-fn fetch_index(base: &str, refresh: bool) -> Result<PluginIndex> {
-    let cache = index_cache_path();
+/// Fetches this ABI's index and adds the plugins that only the buckets of
+/// older supported ABIs have, marking them with their [`IndexEntry::abi_version`].
+/// Older buckets are optional: one that cannot be fetched is skipped, and
+/// only when this ABI's bucket cannot be fetched either does this fail.
+fn fetch_index(base: &str, refresh: bool, trust: Option<&TrustPolicy>) -> Result<PluginIndex> {
+    let (mut index, current_error) = match fetch_bucket(base, PLUGIN_ABI_VERSION, refresh, trust) {
+        Ok(index) => (index, None),
+        Err(e) => (
+            PluginIndex {
+                abi_version: PLUGIN_ABI_VERSION,
+                generated_at: None,
+                plugins: Vec::new(),
+            },
+            Some(e),
+        ),
+    };
+    let mut found_older = false;
+    for &abi in SUPPORTED_ABI_VERSIONS
+        .iter()
+        .filter(|&&abi| abi != PLUGIN_ABI_VERSION)
+    {
+        if let Ok(older) = fetch_bucket(base, abi, refresh, trust) {
+            merge_older_bucket(&mut index, older);
+            found_older = true;
+        }
+    }
+    match current_error {
+        None => Ok(index),
+        Some(e) if found_older => {
+            eprintln!("warning: {e:#}; listing plugins built for older ABIs only");
+            Ok(index)
+        }
+        Some(e) => Err(e),
+    }
+}
+
+/// Adds the plugins of `older` that `index` lacks, marked with their ABI.
+fn merge_older_bucket(index: &mut PluginIndex, older: PluginIndex) {
+    for mut entry in older.plugins {
+        if index.plugins.iter().any(|p| p.name == entry.name) {
+            continue;
+        }
+        entry.abi_version = Some(older.abi_version);
+        for previous in &mut entry.previous_versions {
+            previous.abi_version = Some(older.abi_version);
+        }
+        index.plugins.push(entry);
+    }
+    index.plugins.sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+/// The ABI bucket an index entry was read from.
+fn entry_abi(entry: &IndexEntry) -> u32 {
+    entry.abi_version.unwrap_or(PLUGIN_ABI_VERSION)
+}
+
+/// Fetches the index of one ABI bucket, using a short-lived on-disk cache. On
+/// a network failure it falls back to a stale cache if one exists. With a
+/// `trust` policy the index must verify against its `index.json.sig`, cached
+/// or not.
+fn fetch_bucket(
+    base: &str,
+    abi: u32,
+    refresh: bool,
+    trust: Option<&TrustPolicy>,
+) -> Result<PluginIndex> {
+    let cache = index_cache_path(base, abi);
     if !refresh {
-        if let Some(index) = read_cache(&cache, true) {
-            return Ok(index);
+        if let Some(cached) = read_cache(&cache, true) {
+            return parse_index(&cached, &cache.display().to_string(), abi, trust);
         }
     }
 
-    let url = index_url(base);
-    match http_get_string(&url) {
-        Ok(body) => {
-            let index: PluginIndex = serde_json::from_str(&body)
-                .with_context(|| format!("parsing plugin index from {url}"))?;
-            if index.abi_version != PLUGIN_ABI_VERSION {
-                bail!(
-                    "index at {url} targets ABI {} but this treetags supports ABI {PLUGIN_ABI_VERSION}",
-                    index.abi_version
-                );
+    let mut errors = Vec::new();
+    for mirror in mirrors(base) {
+        let url = index_url(mirror, abi);
+        let fetched = http_get_string(&url).and_then(|body| {
+            let signature = http_get_optional_string(&format!("{url}.sig"))?;
+            Ok(CachedIndex { body, signature })
+        });
+        match fetched {
+            Ok(fetched) => {
+                for (failed, e) in &errors {
+                    eprintln!("warning: could not fetch {failed} ({e}); using {url}");
+                }
+                let mut index = parse_index(&fetched, &url, abi, trust)?;
+                let _ = write_cache(&cache, &fetched);
+                served_by(&mut index, mirror);
+                return Ok(index);
             }
-            let _ = write_cache(&cache, &body);
-            Ok(index)
+            Err(e) => errors.push((url, e)),
         }
-        Err(e) => match read_cache(&cache, false) {
-            Some(index) => {
-                eprintln!("warning: could not fetch {url} ({e}); using cached index");
-                Ok(index)
+    }
+
+    match read_cache(&cache, false) {
+        Some(cached) => {
+            for (url, e) in &errors {
+                eprintln!("warning: could not fetch {url} ({e})");
+            }
+            eprintln!("warning: using cached index");
+            parse_index(&cached, &cache.display().to_string(), abi, trust)
+        }
+        None => match errors.len() {
+            0 => bail!("no plugin index URL configured"),
+            1 => {
+                let (url, e) = errors.pop().unwrap();
+                Err(e.context(format!("fetching plugin index {url}")))
             }
-            None => Err(e.context(format!("fetching plugin index {url}"))),
+            _ => bail!(
+                "fetching the plugin index failed on every mirror:\n{}",
+                errors
+                    .iter()
+                    .map(|(url, e)| format!("  {url}: {e}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
         },
     }
 }
 
+/// Records `mirror` as the source of every entry of `index`.
+fn served_by(index: &mut PluginIndex, mirror: &str) {
+    for entry in &mut index.plugins {
+        entry.mirror = Some(mirror.to_string());
+        for previous in &mut entry.previous_versions {
+            previous.mirror = Some(mirror.to_string());
+        }
+    }
+}
+
+/// The raw index body and its detached signature, as fetched or cached.
+struct CachedIndex {
+    body: String,
+    signature: Option<String>,
+}
+
+/// Verifies (when `trust` is given) and parses the index of the `abi` bucket
+/// read from `source`.
+fn parse_index(
+    cached: &CachedIndex,
+    source: &str,
+    abi: u32,
+    trust: Option<&TrustPolicy>,
+) -> Result<PluginIndex> {
+    if let Some(trust) = trust {
+        trust.verify(
+            &format!("plugin index {source}"),
+            cached.body.as_bytes(),
+            cached.signature.as_deref(),
+        )?;
+    }
+    let index: PluginIndex = serde_json::from_str(&cached.body)
+        .with_context(|| format!("parsing plugin index from {source}"))?;
+    if index.abi_version != abi {
+        bail!(
+            "index at {source} targets ABI {} but was published as the ABI {abi} index",
+            index.abi_version
+        );
+    }
+    Ok(index)
+}
+
 /// Reads the cached index. When `require_fresh`, only returns it if younger than
 /// [`INDEX_CACHE_TTL`]; otherwise returns it regardless of age (stale fallback).
-fn read_cache(path: &Path, require_fresh: bool) -> Option<PluginIndex> {
+fn read_cache(path: &Path, require_fresh: bool) -> Option<CachedIndex> {
     let meta = std::fs::metadata(path).ok()?;
     if require_fresh {
         let age = meta.modified().ok()?.elapsed().unwrap_or(INDEX_CACHE_TTL);
@@ -317,15 +963,24 @@ fn read_cache(path: &Path, require_fresh: bool) -> Option<PluginIndex> {
             return None;
         }
     }
-    let body = std::fs::read_to_string(path).ok()?;
-    serde_json::from_str(&body).ok()
+    Some(CachedIndex {
+        body: std::fs::read_to_string(path).ok()?,
+        signature: std::fs::read_to_string(signature_path(path)).ok(),
+    })
 }
 
-fn write_cache(path: &Path, body: &str) -> Result<()> {
+fn write_cache(path: &Path, cached: &CachedIndex) -> Result<()> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    std::fs::write(path, body)?;
+    let sig_path = signature_path(path);
+    match &cached.signature {
+        Some(signature) => std::fs::write(&sig_path, signature)?,
+        None => {
+            let _ = std::fs::remove_file(&sig_path);
+        }
+    }
+    std::fs::write(path, &cached.body)?;
     Ok(())
 }
 
@@ -333,12 +988,19 @@ fn write_cache(path: &Path, body: &str) -> Result<()> {
 // Download + install
 // ---------------------------------------------------------------------------
 
-/// Downloads the plugin's `.wasm` (verifying its SHA-256) and manifest, then
-/// installs into `plugins_dir/<name>/` via a temp dir + rename so a failed or
-/// interrupted download never corrupts an existing install.
-fn download_and_place(entry: &IndexEntry, plugins_dir: &Path) -> Result<PathBuf> {
-    let wasm = http_get_bytes(&entry.wasm_url)
-        .with_context(|| format!("downloading {}", entry.wasm_url))?;
+/// Downloads the plugin's `.wasm` (verifying its SHA-256), manifest and any
+/// extra files (a declarative plugin's queries, also verified), checks each
+/// one's signature against `trust`, then installs into `plugins_dir/<name>/`
+/// via a temp dir + rename so a failed or interrupted download never corrupts
+/// an existing install. The install it replaces is kept for
+/// `treetags plugin rollback`. Relative URLs are tried on each mirror of `base`.
+fn download_and_place(
+    base: &str,
+    entry: &IndexEntry,
+    plugins_dir: &Path,
+    trust: &TrustPolicy,
+) -> Result<PathBuf> {
+    let wasm = download_artifact(&entry.wasm_url, base, entry)?;
     let got = sha256_hex(&wasm);
     if got != entry.wasm_sha256 {
         bail!(
@@ -349,9 +1011,19 @@ fn download_and_place(entry: &IndexEntry, plugins_dir: &Path) -> Result<PathBuf>
             got
         );
     }
+    trust.verify(
+        &format!("`{}` plugin.wasm", entry.name),
+        &wasm,
+        entry.wasm_signature.as_deref(),
+    )?;
 
-    let manifest_text = http_get_string(&entry.manifest_url)
-        .with_context(|| format!("downloading {}", entry.manifest_url))?;
+    let manifest_text = String::from_utf8(download_artifact(&entry.manifest_url, base, entry)?)
+        .with_context(|| format!("reading the downloaded manifest for `{}`", entry.name))?;
+    trust.verify(
+        &format!("`{}` plugin.toml", entry.name),
+        manifest_text.as_bytes(),
+        entry.manifest_signature.as_deref(),
+    )?;
     let manifest: PluginManifest = toml::from_str(&manifest_text)
         .with_context(|| format!("parsing downloaded manifest for `{}`", entry.name))?;
     if manifest.name != entry.name {
@@ -361,30 +1033,114 @@ fn download_and_place(entry: &IndexEntry, plugins_dir: &Path) -> Result<PathBuf>
             entry.name
         );
     }
-    if manifest.abi_version != PLUGIN_ABI_VERSION {
+    if manifest.abi_version != entry_abi(entry) {
         bail!(
-            "plugin `{}` targets ABI {} but this treetags supports ABI {PLUGIN_ABI_VERSION}",
+            "plugin `{}` targets ABI {} but was published in the ABI {} index",
             entry.name,
-            manifest.abi_version
+            manifest.abi_version,
+            entry_abi(entry)
         );
     }
 
+    let mut files = Vec::with_capacity(entry.files.len() + 2);
+    files.push(("plugin.wasm", wasm));
+    files.push(("plugin.toml", manifest_text.into_bytes()));
+    for file in &entry.files {
+        if file.name.contains(['/', '\\']) || file.name.starts_with('.') {
+            bail!(
+                "plugin `{}` ships an invalid file name `{}`",
+                entry.name,
+                file.name
+            );
+        }
+        let bytes = download_artifact(&file.url, base, entry)?;
+        let got = sha256_hex(&bytes);
+        if got != file.sha256 {
+            bail!(
+                "checksum mismatch for `{}` file `{}`: expected {}, got {}",
+                entry.name,
+                file.name,
+                file.sha256,
+                got
+            );
+        }
+        trust.verify(
+            &format!("`{}` {}", entry.name, file.name),
+            &bytes,
+            file.signature.as_deref(),
+        )?;
+        files.push((file.name.as_str(), bytes));
+    }
+
+    place(plugins_dir, &entry.name, &files)
+}
+
+/// Downloads an artifact of `entry` from the first of its [`artifact_urls`]
+/// that can be fetched.
+fn download_artifact(url: &str, base: &str, entry: &IndexEntry) -> Result<Vec<u8>> {
+    let mut errors = Vec::new();
+    for candidate in artifact_urls(url, base, entry) {
+        match http_get_bytes(&candidate) {
+            Ok(bytes) => {
+                for (failed, e) in &errors {
+                    eprintln!("warning: could not fetch {failed} ({e}); using {candidate}");
+                }
+                return Ok(bytes);
+            }
+            Err(e) => errors.push((candidate, e)),
+        }
+    }
+    match errors.len() {
+        0 => bail!("no plugin index URL configured to resolve {url} against"),
+        1 => {
+            let (candidate, e) = errors.pop().unwrap();
+            Err(e.context(format!("downloading {candidate}")))
+        }
+        _ => bail!(
+            "downloading {url} failed on every mirror:\n{}",
+            errors
+                .iter()
+                .map(|(candidate, e)| format!("  {candidate}: {e}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    }
+}
+
+/// Writes `files` into `plugins_dir/<name>/` via a temp dir + rename, keeping
+/// the install it replaces for `treetags plugin rollback`.
+fn place(plugins_dir: &Path, name: &str, files: &[(&str, Vec<u8>)]) -> Result<PathBuf> {
     std::fs::create_dir_all(plugins_dir)
         .with_context(|| format!("creating {}", plugins_dir.display()))?;
-    let final_dir = plugins_dir.join(&entry.name);
-    let tmp_dir = plugins_dir.join(format!(".{}.tmp", entry.name));
+    let final_dir = plugins_dir.join(name);
+    let tmp_dir = plugins_dir.join(format!(".{name}.tmp"));
 
     let _ = std::fs::remove_dir_all(&tmp_dir);
     std::fs::create_dir_all(&tmp_dir)?;
-    std::fs::write(tmp_dir.join("plugin.wasm"), &wasm)?;
-    std::fs::write(tmp_dir.join("plugin.toml"), manifest_text.as_bytes())?;
+    for (file, bytes) in files {
+        std::fs::write(tmp_dir.join(file), bytes)?;
+    }
 
-    let _ = std::fs::remove_dir_all(&final_dir);
+    if final_dir.exists() {
+        let previous_dir = rollback_dir(plugins_dir, name);
+        let _ = std::fs::remove_dir_all(&previous_dir);
+        if let Some(parent) = previous_dir.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&final_dir, &previous_dir)
+            .with_context(|| format!("keeping previous install in {}", previous_dir.display()))?;
+    }
     std::fs::rename(&tmp_dir, &final_dir)
         .with_context(|| format!("installing into {}", final_dir.display()))?;
     Ok(final_dir)
 }
 
+/// Where the install replaced by the last install or update of `name` is kept.
+/// Hidden directories are skipped by plugin discovery.
+fn rollback_dir(plugins_dir: &Path, name: &str) -> PathBuf {
+    plugins_dir.join(ROLLBACK_DIR).join(name)
+}
+
 // ---------------------------------------------------------------------------
 // Local scanning + helpers
 // ---------------------------------------------------------------------------
@@ -404,7 +1160,9 @@ fn collect_installed(dir: &Path, out: &mut HashMap<String, InstalledPlugin>) {
     for entry in read_dir.flatten() {
         let path = entry.path();
         if path.is_dir() {
-            collect_installed(&path, out);
+            if !super::is_hidden(&path) {
+                collect_installed(&path, out);
+            }
         } else if path
             .file_name()
             .map(|n| n == "plugin.toml")
@@ -412,11 +1170,14 @@ fn collect_installed(dir: &Path, out: &mut HashMap<String, InstalledPlugin>) {
         {
             if let Ok(text) = std::fs::read_to_string(&path) {
                 if let Ok(m) = toml::from_str::<PluginManifest>(&text) {
+                    let plugin_dir = path.parent().unwrap_or(dir).to_path_buf();
                     out.insert(
-                        m.name,
+                        m.name.clone(),
                         InstalledPlugin {
+                            wasm_path: m.wasm_path(&plugin_dir),
+                            abi_version: m.abi_version,
                             version: m.version,
-                            dir: path.parent().unwrap_or(dir).to_path_buf(),
+                            dir: plugin_dir,
                         },
                     );
                 }
@@ -425,6 +1186,32 @@ fn collect_installed(dir: &Path, out: &mut HashMap<String, InstalledPlugin>) {
     }
 }
 
+/// Reads the manifest of the plugin installed in `dir`, if any.
+fn read_manifest(dir: &Path) -> Option<PluginManifest> {
+    let text = std::fs::read_to_string(dir.join("plugin.toml")).ok()?;
+    toml::from_str(&text).ok()
+}
+
+/// Splits an install spec `name[@version]`.
+fn parse_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (spec, None),
+    }
+}
+
+fn find_version<'a>(entry: &'a IndexEntry, version: &str) -> Result<&'a IndexEntry> {
+    entry.find_version(version).ok_or_else(|| {
+        let available: Vec<&str> = entry.versions().map(|v| v.version.as_str()).collect();
+        anyhow!(
+            "`{}` {version} is not available for ABI {PLUGIN_ABI_VERSION}\n\
+             Available versions: {}",
+            entry.name,
+            available.join(", ")
+        )
+    })
+}
+
 fn find_entry<'a>(index: &'a PluginIndex, name: &str) -> Result<&'a IndexEntry> {
     index
         .plugins
@@ -454,6 +1241,55 @@ fn status_label(local: &str, remote: &str) -> String {
     }
 }
 
+/// Columns of [`index_row`].
+const INDEX_HEADERS: &[&str] = &["NAME", "VERSION", "LANGUAGE", "EXTENSIONS", "STATUS"];
+
+/// A `plugin available`/`search` table row.
+fn index_row(p: &IndexEntry, installed: &HashMap<String, InstalledPlugin>) -> Vec<String> {
+    let status = match installed.get(&p.name) {
+        None => String::new(),
+        Some(local) => status_label(&local.version, &p.version),
+    };
+    let version = match p.abi_version {
+        Some(abi) => format!("{} (ABI {abi})", p.version),
+        None => p.version.clone(),
+    };
+    vec![
+        p.name.clone(),
+        version,
+        p.language.clone().unwrap_or_default(),
+        p.extensions.join(", "),
+        status,
+    ]
+}
+
+/// Renders kinds as `letter name` pairs, marking those off by default.
+fn kinds_label(kinds: &[IndexKind]) -> String {
+    kinds
+        .iter()
+        .map(|k| {
+            let off = if k.default { "" } else { " (off by default)" };
+            format!("{} {}{off}", k.letter, k.name)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Prints indented `label: value` lines, skipping empty values.
+fn print_fields(fields: &[(&str, String)]) {
+    for (label, value) in fields.iter().filter(|(_, value)| !value.is_empty()) {
+        println!("  {:<16}{value}", format!("{label}:"));
+    }
+}
+
 /// Renders a slice of extensions as a comma-separated `*.ext` list.
 fn ext_globs(exts: &[String]) -> String {
     exts.iter()
@@ -469,7 +1305,7 @@ fn ext_globs_iter<'a>(exts: impl IntoIterator<Item = &'a String>) -> String {
 
 /// Prints a left-aligned, column-padded table. Every row must have the same
 /// number of cells as `headers`; the final column is not padded.
-fn print_rows(headers: &[&str], rows: &[Vec<String>]) {
+pub(crate) fn print_rows(headers: &[&str], rows: &[Vec<String>]) {
     let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
     for row in rows {
         for (i, cell) in row.iter().enumerate() {
@@ -498,13 +1334,48 @@ fn print_rows(headers: &[&str], rows: &[Vec<String>]) {
 // HTTP
 // ---------------------------------------------------------------------------
 
+/// The local path of a `file://` URL.
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    url.strip_prefix("file://").map(PathBuf::from)
+}
+
+fn read_file_url(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow!("reading {}: {e}", path.display()))
+}
+
 fn http_get_string(url: &str) -> Result<String> {
+    if let Some(path) = file_url_path(url) {
+        return String::from_utf8(read_file_url(&path)?)
+            .map_err(|e| anyhow!("reading {}: {e}", path.display()));
+    }
     let resp = ureq::get(url).call().map_err(map_ureq_err)?;
     resp.into_string()
         .map_err(|e| anyhow!("reading response body from {url}: {e}"))
 }
 
+/// Like [`http_get_string`], but a 404 yields `None` (e.g. an unsigned index
+/// has no `.sig`).
+fn http_get_optional_string(url: &str) -> Result<Option<String>> {
+    if let Some(path) = file_url_path(url) {
+        if !path.exists() {
+            return Ok(None);
+        }
+        return http_get_string(url).map(Some);
+    }
+    match ureq::get(url).call() {
+        Ok(resp) => resp
+            .into_string()
+            .map(Some)
+            .map_err(|e| anyhow!("reading response body from {url}: {e}")),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(e) => Err(map_ureq_err(e)),
+    }
+}
+
 fn http_get_bytes(url: &str) -> Result<Vec<u8>> {
+    if let Some(path) = file_url_path(url) {
+        return read_file_url(&path);
+    }
     let resp = ureq::get(url).call().map_err(map_ureq_err)?;
     let mut buf = Vec::new();
     resp.into_reader()
@@ -526,6 +1397,104 @@ fn map_ureq_err(e: ureq::Error) -> anyhow::Error {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::plugin::signing::{public_key_hex, sign_hex};
+    use ed25519_dalek::SigningKey;
+
+    /// Writes a signed `demo` plugin and its signed index into `site`'s ABI
+    /// bucket, with artifact URLs starting with `asset_base`.
+    fn publish_demo(site: &Path, key: &SigningKey, asset_base: &str) -> CachedIndex {
+        let bucket = site.join(PLUGIN_ABI_VERSION.to_string());
+        std::fs::create_dir_all(&bucket).unwrap();
+        let wasm = b"\0asm demo".to_vec();
+        let manifest = format!(
+            "name = \"demo\"\nversion = \"0.1.0\"\nabi_version = {PLUGIN_ABI_VERSION}\n\
+             extensions = [\"demo\"]\n"
+        );
+        std::fs::write(bucket.join("demo-0.1.0.wasm"), &wasm).unwrap();
+        std::fs::write(bucket.join("demo-0.1.0.toml"), &manifest).unwrap();
+
+        let mut entry: IndexEntry = serde_json::from_value(serde_json::json!({
+            "name": "demo",
+            "version": "0.1.0",
+            "extensions": ["demo"],
+            "wasm_url": format!("{asset_base}demo-0.1.0.wasm"),
+            "manifest_url": format!("{asset_base}demo-0.1.0.toml"),
+            "wasm_sha256": sha256_hex(&wasm),
+            "wasm_size": wasm.len(),
+        }))
+        .unwrap();
+        entry.wasm_signature = Some(sign_hex(key, &wasm));
+        entry.manifest_signature = Some(sign_hex(key, manifest.as_bytes()));
+        let body = serde_json::to_string(&PluginIndex {
+            abi_version: PLUGIN_ABI_VERSION,
+            generated_at: None,
+            plugins: vec![entry],
+        })
+        .unwrap();
+        let signature = sign_hex(key, body.as_bytes());
+        std::fs::write(bucket.join("index.json"), &body).unwrap();
+        std::fs::write(bucket.join("index.json.sig"), &signature).unwrap();
+        CachedIndex {
+            body,
+            signature: Some(signature),
+        }
+    }
+
+    #[test]
+    fn signed_entry_installs_with_builtin_keys_only() {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let site = tempfile::tempdir().unwrap();
+        let bucket = site.path().join(PLUGIN_ABI_VERSION.to_string());
+        let asset_base = format!("file://{}/", bucket.display());
+        let published = publish_demo(site.path(), &key, &asset_base);
+
+        let trust = TrustPolicy::with_keys(&[&public_key_hex(&key)], &[], false).unwrap();
+        let index = parse_index(&published, "test", PLUGIN_ABI_VERSION, Some(&trust)).unwrap();
+        let plugins = tempfile::tempdir().unwrap();
+        let dir = download_and_place("", &index.plugins[0], plugins.path(), &trust).unwrap();
+        assert!(dir.join("plugin.wasm").is_file());
+        assert_eq!(read_manifest(&dir).unwrap().version, "0.1.0");
+
+        let other = SigningKey::from_bytes(&[4u8; 32]);
+        let untrusted = TrustPolicy::with_keys(&[&public_key_hex(&other)], &[], false).unwrap();
+        assert!(parse_index(&published, "test", PLUGIN_ABI_VERSION, Some(&untrusted)).is_err());
+    }
+
+    #[test]
+    fn relative_artifacts_resolve_against_the_serving_mirror() {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let site = tempfile::tempdir().unwrap();
+        let published = publish_demo(site.path(), &key, "");
+        let trust = TrustPolicy::with_keys(&[&public_key_hex(&key)], &[], false).unwrap();
+        let mirror = format!("file://{}", site.path().display());
+        let base = format!("file:///nonexistent-treetags-mirror,{mirror}");
+
+        let mut index = parse_index(&published, "test", PLUGIN_ABI_VERSION, Some(&trust)).unwrap();
+        let entry = &index.plugins[0];
+        assert_eq!(
+            artifact_urls(&entry.wasm_url, &base, entry),
+            [
+                format!("file:///nonexistent-treetags-mirror/{PLUGIN_ABI_VERSION}/demo-0.1.0.wasm"),
+                format!("{mirror}/{PLUGIN_ABI_VERSION}/demo-0.1.0.wasm"),
+            ]
+        );
+        // From a cached index: falls back past the mirror that cannot serve it.
+        let plugins = tempfile::tempdir().unwrap();
+        download_and_place(&base, entry, plugins.path(), &trust).unwrap();
+
+        served_by(&mut index, &mirror);
+        let entry = &index.plugins[0];
+        assert_eq!(
+            artifact_urls(&entry.manifest_url, &base, entry)[0],
+            format!("{mirror}/{PLUGIN_ABI_VERSION}/demo-0.1.0.toml")
+        );
+        let dir = download_and_place(&base, entry, plugins.path(), &trust).unwrap();
+        assert_eq!(read_manifest(&dir).unwrap().name, "demo");
+        assert_eq!(
+            artifact_urls("https://x/a.wasm", &base, entry),
+            ["https://x/a.wasm"]
+        );
+    }
 
     #[test]
     fn base_url_precedence_prefers_override() {
@@ -538,11 +1507,41 @@ mod tests {
     #[test]
     fn index_url_includes_abi_bucket() {
         assert_eq!(
-            index_url("https://example.com/treetags/"),
+            index_url("https://example.com/treetags/", PLUGIN_ABI_VERSION),
             format!("https://example.com/treetags/{PLUGIN_ABI_VERSION}/index.json")
         );
     }
 
+    #[test]
+    fn mirrors_split_on_commas() {
+        let listed: Vec<_> = mirrors("file:///mnt/plugins, https://x/y,").collect();
+        assert_eq!(listed, ["file:///mnt/plugins", "https://x/y"]);
+    }
+
+    #[test]
+    fn local_specs_are_paths_or_bundles() {
+        assert!(is_local_spec("./java"));
+        assert!(is_local_spec("dist/java"));
+        assert!(is_local_spec("java-0.2.0.tar"));
+        assert!(is_local_spec("java.zip"));
+        assert!(!is_local_spec("java"));
+        assert!(!is_local_spec("java@0.1.0"));
+    }
+
+    #[test]
+    fn parse_spec_splits_optional_version() {
+        assert_eq!(parse_spec("java"), ("java", None));
+        assert_eq!(parse_spec("java@0.1.0"), ("java", Some("0.1.0")));
+    }
+
+    #[test]
+    fn index_cache_is_keyed_by_base_url_and_abi() {
+        let official = index_cache_path("https://example.com/plugins", 4);
+        assert_eq!(official, index_cache_path("https://example.com/plugins", 4));
+        assert_ne!(official, index_cache_path("https://example.com/plugins", 3));
+        assert_ne!(official, index_cache_path("file:///srv/plugins", 4));
+    }
+
     #[test]
     fn status_label_reflects_version_relationship() {
         assert_eq!(status_label("0.1.0", "0.2.0"), "update: 0.1.0 -> 0.2.0");
@@ -550,4 +1549,93 @@ mod tests {
         assert_eq!(status_label("0.3.0", "0.2.0"), "installed (0.3.0, newer)");
         assert_eq!(status_label("weird", "0.2.0"), "installed (weird)");
     }
+
+    #[test]
+    fn search_matches_names_languages_extensions_and_patterns() {
+        let entry: IndexEntry = serde_json::from_value(serde_json::json!({
+            "name": "starlark",
+            "version": "0.1.0",
+            "language": "Starlark",
+            "aliases": ["bazel"],
+            "extensions": ["star", "bzl"],
+            "patterns": ["BUILD", "WORKSPACE"],
+            "wasm_url": "https://x/a.wasm",
+            "manifest_url": "https://x/a.toml",
+            "wasm_sha256": "00",
+            "wasm_size": 1,
+        }))
+        .unwrap();
+        for term in [
+            "lark",
+            "STARLARK",
+            "bazel",
+            "bzl",
+            "*.bzl",
+            ".star",
+            "workspace",
+        ] {
+            assert!(matches_term(&entry, term), "{term}");
+        }
+        for term in ["rust", "python", "*.py"] {
+            assert!(!matches_term(&entry, term), "{term}");
+        }
+    }
+
+    #[test]
+    fn older_buckets_only_fill_in_missing_plugins() {
+        let bucket = |abi: u32, names: &[&str]| PluginIndex {
+            abi_version: abi,
+            generated_at: None,
+            plugins: names
+                .iter()
+                .map(|name| {
+                    serde_json::from_value(serde_json::json!({
+                        "name": name,
+                        "version": format!("0.{abi}.0"),
+                        "extensions": [name],
+                        "wasm_url": "https://x/a.wasm",
+                        "manifest_url": "https://x/a.toml",
+                        "wasm_sha256": "00",
+                        "wasm_size": 1,
+                    }))
+                    .unwrap()
+                })
+                .collect(),
+        };
+        let mut index = bucket(PLUGIN_ABI_VERSION, &["lua"]);
+        merge_older_bucket(&mut index, bucket(3, &["lua", "java"]));
+
+        let names: Vec<_> = index.plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["java", "lua"]);
+        assert_eq!(index.plugins[0].abi_version, Some(3));
+        assert_eq!(entry_abi(&index.plugins[0]), 3);
+        assert_eq!(entry_abi(&index.plugins[1]), PLUGIN_ABI_VERSION);
+        assert_eq!(
+            index_row(&index.plugins[0], &HashMap::new())[1],
+            "0.3.0 (ABI 3)"
+        );
+    }
+
+    #[test]
+    fn shadowed_languages_share_an_extension() {
+        let native = [
+            NativeLanguage {
+                name: "java".into(),
+                extensions: vec!["java".into()],
+            },
+            NativeLanguage {
+                name: "c".into(),
+                extensions: vec!["c".into(), "h".into()],
+            },
+        ];
+        let shadowed = shadowed_languages(&["H".into(), "hpp".into()], &native);
+        assert_eq!(
+            shadowed,
+            [ShadowedLanguage {
+                language: "c".into(),
+                extensions: vec!["h".into()],
+            }]
+        );
+        assert!(shadowed_languages(&["kt".into()], &native).is_empty());
+    }
 }
@@ -0,0 +1,287 @@
+//! Declarative plugins: a tree-sitter grammar compiled to `.wasm` plus a
+//! `tags.scm` query (and optional `locals.scm`), executed by the host through
+//! tree-sitter's WASM language store rather than as a WASM component.
+//!
+//! Unlike component plugins nothing runs plugin-authored code outside the
+//! grammar itself, so there is no WIT request: kind filtering and extension
+//! fields are handled here from the manifest's `[[kinds]]` and `[kind_map]`.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tree_sitter_tags::TagsContext;
+
+use super::manifest::{ManifestKind, PluginManifest};
+use crate::config::Config;
+use crate::parser::TagKindConfig;
+use crate::tag::{ExtensionFields, Tag};
+
+/// The grammar and query files of a declarative plugin, resolved from its
+/// manifest directory.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "declarative-plugins"), allow(dead_code))]
+pub(crate) struct DeclarativeSpec {
+    /// Grammar name; the `.wasm` must export `tree_sitter_<grammar>`.
+    pub grammar: String,
+    pub tags_path: PathBuf,
+    pub locals_path: Option<PathBuf>,
+    /// Capture syntax type (`function` for `@definition.function`) to kind letter.
+    pub kind_map: BTreeMap<String, String>,
+}
+
+impl DeclarativeSpec {
+    /// Returns `None` for component plugins.
+    pub fn from_manifest(manifest: &PluginManifest, dir: &Path) -> Option<Self> {
+        if !manifest.is_declarative() {
+            return None;
+        }
+        Some(Self {
+            grammar: manifest.grammar.clone()?,
+            tags_path: dir.join(&manifest.tags_query),
+            locals_path: manifest.locals_query.as_ref().map(|q| dir.join(q)),
+            kind_map: manifest.kind_map.clone(),
+        })
+    }
+
+    /// The first query file referenced by the manifest that does not exist.
+    pub fn missing_file(&self) -> Option<&Path> {
+        std::iter::once(self.tags_path.as_path())
+            .chain(self.locals_path.as_deref())
+            .find(|p| !p.exists())
+    }
+}
+
+/// Syntax type name to the kind letter emitted for it, or `None` when the kind
+/// is disabled through `--kinds-<lang>`.
+pub(crate) type KindFilter = HashMap<String, Option<Cow<'static, str>>>;
+
+/// A declarative plugin's compiled tags query. Shared across worker threads;
+/// each thread parses with its own WASM store.
+pub(crate) struct DeclarativeGrammar {
+    config: tree_sitter_tags::TagsConfiguration,
+    /// Keeps the store the language was loaded into alive.
+    #[cfg(feature = "declarative-plugins")]
+    _store: tree_sitter::WasmStore,
+}
+
+impl DeclarativeGrammar {
+    /// Generates tags for one file, keeping the kinds `kinds` enables. `ctx`
+    /// must have a WASM store attached via `GrammarRuntime::attach_store`.
+    pub fn generate(
+        &self,
+        ctx: &mut TagsContext,
+        code: &[u8],
+        file_path: &str,
+        config: &Config,
+        kinds: &KindFilter,
+    ) -> Vec<Tag> {
+        let (raw_tags, _) = match ctx.generate_tags(&self.config, code, None) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Error generating tags for file: {e}");
+                return Vec::new();
+            }
+        };
+        let file_name: Arc<str> = Arc::from(file_path);
+        let mut tags = Vec::new();
+        for raw in raw_tags {
+            let raw = match raw {
+                Ok(raw) if raw.is_definition => raw,
+                Ok(_) => continue,
+                Err(e) => {
+                    eprintln!("Error generating tags for file: {e}");
+                    continue;
+                }
+            };
+            let syntax_type = self.config.syntax_type_name(raw.syntax_type_id);
+            let kind = match kinds.get(syntax_type) {
+                Some(Some(kind)) => kind.clone(),
+                Some(None) => continue,
+                None => Cow::Owned(syntax_type.to_string()),
+            };
+            let start_row = raw.span.start.row;
+            let end_row = start_row
+                + code[raw.line_range.start.min(raw.range.end)..raw.range.end]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count();
+
+            let mut tag = match Tag::from_ts_tag(raw, code, file_name.clone()) {
+                Ok(tag) => tag,
+                Err(msg) => {
+                    eprintln!("{msg}");
+                    continue;
+                }
+            };
+            let mut fields = ExtensionFields::new();
+            if config.fields_config.is_field_enabled("line") {
+                fields.insert("line", (start_row + 1).to_string());
+            }
+            if config.fields_config.is_field_enabled("end") {
+                fields.insert("end", (end_row + 1).to_string());
+            }
+            tag.kind = Some(kind);
+            tag.extension_fields = (!fields.is_empty()).then_some(fields);
+            tags.push(tag);
+        }
+        tags
+    }
+}
+
+/// Resolves the kind letter emitted for each syntax type in `kind_map`.
+/// When the manifest declares `[[kinds]]`, letters disabled by `kinds_str`
+/// (the `--kinds-<lang>` value) map to `None`.
+pub(crate) fn resolve_kinds(
+    kind_map: &BTreeMap<String, String>,
+    manifest_kinds: &[ManifestKind],
+    kinds_str: &str,
+) -> KindFilter {
+    let aliases: Vec<[&str; 2]> = manifest_kinds
+        .iter()
+        .map(|k| [k.letter.as_str(), k.name.as_str()])
+        .collect();
+    let mapping = |default: bool| -> Vec<(&[&str], &str)> {
+        manifest_kinds
+            .iter()
+            .zip(&aliases)
+            .filter(|(k, _)| k.default == default)
+            .map(|(k, a)| (&a[..], k.letter.as_str()))
+            .collect()
+    };
+    let enabled = (!manifest_kinds.is_empty())
+        .then(|| TagKindConfig::from_string(kinds_str, &mapping(true), &mapping(false)));
+
+    kind_map
+        .iter()
+        .map(|(syntax_type, letter)| {
+            let on = enabled
+                .as_ref()
+                .is_none_or(|cfg| cfg.is_kind_enabled(letter));
+            let kind: Cow<'static, str> = Cow::Owned(letter.clone());
+            (syntax_type.clone(), on.then_some(kind))
+        })
+        .collect()
+}
+
+/// Owns the tree-sitter WASM engine shared by every declarative grammar.
+#[derive(Default)]
+pub(crate) struct GrammarRuntime {
+    #[cfg(feature = "declarative-plugins")]
+    engine: std::sync::OnceLock<tree_sitter::wasmtime::Engine>,
+}
+
+#[cfg(feature = "declarative-plugins")]
+impl GrammarRuntime {
+    fn engine(&self) -> &tree_sitter::wasmtime::Engine {
+        self.engine.get_or_init(Default::default)
+    }
+
+    /// Gives the thread's tags parser a WASM store so it can run grammars
+    /// loaded from `.wasm`. Called once per worker thread.
+    pub fn attach_store(&self, ctx: &mut TagsContext) -> anyhow::Result<()> {
+        let store = tree_sitter::WasmStore::new(self.engine())
+            .map_err(|e| anyhow::anyhow!("cannot create grammar store: {e}"))?;
+        ctx.parser
+            .set_wasm_store(store)
+            .map_err(|e| anyhow::anyhow!("cannot attach grammar store: {e}"))
+    }
+
+    /// Loads the grammar `.wasm` and compiles the plugin's queries.
+    pub fn load(
+        &self,
+        spec: &DeclarativeSpec,
+        wasm_path: &Path,
+    ) -> anyhow::Result<DeclarativeGrammar> {
+        let read = |path: &Path| {
+            std::fs::read(path).map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))
+        };
+        let wasm = read(wasm_path)?;
+        let tags_query = String::from_utf8(read(&spec.tags_path)?)?;
+        let locals_query = match &spec.locals_path {
+            Some(path) => String::from_utf8(read(path)?)?,
+            None => String::new(),
+        };
+
+        let mut store = tree_sitter::WasmStore::new(self.engine())
+            .map_err(|e| anyhow::anyhow!("cannot create grammar store: {e}"))?;
+        let language = store
+            .load_language(&spec.grammar, &wasm)
+            .map_err(|e| anyhow::anyhow!("load grammar {}: {e}", wasm_path.display()))?;
+        let config = tree_sitter_tags::TagsConfiguration::new(language, &tags_query, &locals_query)
+            .map_err(|e| anyhow::anyhow!("compile {}: {e}", spec.tags_path.display()))?;
+
+        Ok(DeclarativeGrammar {
+            config,
+            _store: store,
+        })
+    }
+}
+
+#[cfg(not(feature = "declarative-plugins"))]
+impl GrammarRuntime {
+    pub fn attach_store(&self, _ctx: &mut TagsContext) -> anyhow::Result<()> {
+        anyhow::bail!("treetags was built without the `declarative-plugins` feature")
+    }
+
+    pub fn load(
+        &self,
+        _spec: &DeclarativeSpec,
+        _wasm_path: &Path,
+    ) -> anyhow::Result<DeclarativeGrammar> {
+        anyhow::bail!("treetags was built without the `declarative-plugins` feature")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(letter: &str, name: &str, default: bool) -> ManifestKind {
+        ManifestKind {
+            letter: letter.to_string(),
+            name: name.to_string(),
+            default,
+        }
+    }
+
+    fn kind_map() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("function".to_string(), "f".to_string()),
+            ("class".to_string(), "c".to_string()),
+            ("constant".to_string(), "k".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_resolve_kinds_defaults() {
+        let kinds = [
+            kind("f", "function", true),
+            kind("c", "class", true),
+            kind("k", "constant", false),
+        ];
+        let resolved = resolve_kinds(&kind_map(), &kinds, "");
+        assert_eq!(resolved["function"].as_deref(), Some("f"));
+        assert_eq!(resolved["class"].as_deref(), Some("c"));
+        assert_eq!(resolved["constant"], None);
+    }
+
+    #[test]
+    fn test_resolve_kinds_modifiers() {
+        let kinds = [
+            kind("f", "function", true),
+            kind("c", "class", true),
+            kind("k", "constant", false),
+        ];
+        let resolved = resolve_kinds(&kind_map(), &kinds, "+k-c");
+        assert_eq!(resolved["function"].as_deref(), Some("f"));
+        assert_eq!(resolved["class"], None);
+        assert_eq!(resolved["constant"].as_deref(), Some("k"));
+    }
+
+    #[test]
+    fn test_resolve_kinds_without_declared_kinds() {
+        let resolved = resolve_kinds(&kind_map(), &[], "f");
+        assert!(resolved.values().all(Option::is_some));
+    }
+}
@@ -0,0 +1,321 @@
+//! `treetags plugin test`: golden tests for plugins, laid out like this
+//! repository's `tests/test_cases`.
+//!
+//! Every directory under `<plugin-dir>/test_cases` with an `input/args.txt` is
+//! a test case. `args.txt` holds the treetags arguments (shell-quoted, one or
+//! more lines, `#` comments allowed) and `input/` the source files they name;
+//! `expected/` holds any of `stdout.txt`, `stderr.txt` and `exit_code.txt`
+//! (missing files are not checked, a missing exit code means 0). treetags runs
+//! in `input/` with the plugin passed as `--plugin-dir` and with an empty
+//! plugins directory and user config, so installed plugins cannot interfere.
+//! `--bless` writes the actual output as the new expectations.
+
+use anyhow::{anyhow, bail, Context, Result};
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::manifest::PluginManifest;
+
+/// Directory of test cases inside a plugin project.
+pub const TEST_CASES_DIR: &str = "test_cases";
+
+/// Placeholder in `args.txt` replaced with the directory holding the plugin,
+/// as in this repository's own golden tests.
+const PLUGINS_DIR_PLACEHOLDER: &str = "{TREETAGS_TEST_PLUGINS_DIR}";
+
+struct TestCase {
+    name: String,
+    input_dir: PathBuf,
+    expected_dir: PathBuf,
+}
+
+/// What one run of treetags produced.
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+/// Runs the test cases of the plugin project in `dir` (those whose name
+/// contains `filter`, if given) against the plugin in `built`, or the one
+/// found by [`plugin_under_test`]. Fails if any case fails.
+pub fn run(dir: &Path, built: Option<&Path>, filter: Option<&str>, bless: bool) -> Result<()> {
+    let plugin = match built {
+        Some(built) => built.to_path_buf(),
+        None => plugin_under_test(dir)?,
+    };
+    let cases: Vec<TestCase> = discover(&dir.join(TEST_CASES_DIR))?
+        .into_iter()
+        .filter(|case| filter.is_none_or(|f| case.name.contains(f)))
+        .collect();
+    if cases.is_empty() {
+        bail!(
+            "no test cases found under {}\n\
+             Each case is a directory with input/args.txt and an expected/ directory.",
+            dir.join(TEST_CASES_DIR).display()
+        );
+    }
+
+    let exe = std::env::current_exe().context("locating the treetags executable")?;
+    let isolation = tempfile::tempdir().context("creating a temporary directory")?;
+    let empty_config = isolation.path().join("config.toml");
+    std::fs::write(&empty_config, "user_grammars = []\n")?;
+
+    println!(
+        "running {} test case(s) against {}",
+        cases.len(),
+        plugin.display()
+    );
+    let mut failures = Vec::new();
+    for case in &cases {
+        let args = parse_args(
+            &std::fs::read_to_string(case.input_dir.join("args.txt"))
+                .with_context(|| format!("reading {}", case.input_dir.display()))?,
+            plugin.parent().unwrap_or(&plugin),
+        )
+        .with_context(|| format!("test case `{}`", case.name))?;
+        let output = Command::new(&exe)
+            .current_dir(&case.input_dir)
+            .arg("--plugins-dir")
+            .arg(isolation.path())
+            .arg("--user-languages-config")
+            .arg(&empty_config)
+            .arg("--plugin-dir")
+            .arg(&plugin)
+            .args(&args)
+            .output()
+            .with_context(|| format!("running {}", exe.display()))?;
+        let outcome = Outcome {
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            exit_code: output.status.code().unwrap_or(-1),
+        };
+
+        if bless {
+            write_expected(case, &outcome)?;
+            println!("test {} ... blessed", case.name);
+            continue;
+        }
+        let mismatches = compare(case, &outcome)?;
+        if mismatches.is_empty() {
+            println!("test {} ... ok", case.name);
+        } else {
+            println!("test {} ... FAILED", case.name);
+            failures.push((case.name.as_str(), mismatches));
+        }
+    }
+
+    if bless {
+        println!("\nblessed {} test case(s)", cases.len());
+        return Ok(());
+    }
+    for (name, mismatches) in &failures {
+        println!("\n---- {name} ----");
+        for mismatch in mismatches {
+            println!("{mismatch}");
+        }
+    }
+    let passed = cases.len() - failures.len();
+    if failures.is_empty() {
+        println!("\ntest result: ok. {passed} passed; 0 failed");
+        Ok(())
+    } else {
+        println!(
+            "\ntest result: FAILED. {passed} passed; {} failed",
+            failures.len()
+        );
+        bail!(
+            "{} test case(s) failed; rerun with --bless to accept the new output",
+            failures.len()
+        )
+    }
+}
+
+/// The directory to load the plugin from: `dir` itself when its `.wasm` is
+/// there (a declarative plugin or a built copy), else the `dist/<name>/`
+/// written by `treetags-build-plugin`.
+fn plugin_under_test(dir: &Path) -> Result<PathBuf> {
+    let manifest_path = dir.join("plugin.toml");
+    let text = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("reading {}", manifest_path.display()))?;
+    let manifest: PluginManifest =
+        toml::from_str(&text).with_context(|| format!("parsing {}", manifest_path.display()))?;
+    if manifest.wasm_path(dir).exists() {
+        return Ok(dir.to_path_buf());
+    }
+    let dist = dir.join("dist").join(&manifest.name);
+    if dist.join("plugin.toml").exists() {
+        return Ok(dist);
+    }
+    Err(anyhow!(
+        "no built plugin found for `{}` (looked in {})\n\
+         Build it with `treetags-build-plugin {}` or point at it with --built.",
+        manifest.name,
+        dist.display(),
+        dir.display()
+    ))
+}
+
+/// Test cases under `root`, sorted by name (their path below `root`).
+fn discover(root: &Path) -> Result<Vec<TestCase>> {
+    let mut cases = Vec::new();
+    if !root.is_dir() {
+        return Ok(cases);
+    }
+    for entry in walkdir::WalkDir::new(root).min_depth(1).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("reading {}", root.display()))?;
+        let dir = entry.path();
+        if !entry.file_type().is_dir() || !dir.join("input/args.txt").is_file() {
+            continue;
+        }
+        let name = dir
+            .strip_prefix(root)
+            .unwrap_or(dir)
+            .to_string_lossy()
+            .replace('\\', "/");
+        cases.push(TestCase {
+            name,
+            input_dir: dir.join("input"),
+            expected_dir: dir.join("expected"),
+        });
+    }
+    Ok(cases)
+}
+
+/// Splits `args.txt` into arguments, substituting the plugins-dir placeholder.
+fn parse_args(content: &str, plugins_dir: &Path) -> Result<Vec<String>> {
+    let content = content.replace(PLUGINS_DIR_PLACEHOLDER, &plugins_dir.to_string_lossy());
+    let mut args = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        args.extend(shlex::split(line).ok_or_else(|| anyhow!("cannot parse arguments `{line}`"))?);
+    }
+    Ok(args)
+}
+
+/// Readable descriptions of how `outcome` differs from the expectations.
+fn compare(case: &TestCase, outcome: &Outcome) -> Result<Vec<String>> {
+    if !case.expected_dir.is_dir() {
+        return Ok(vec![
+            "no expected/ directory; run with --bless to record the output".to_string(),
+        ]);
+    }
+    let mut mismatches = Vec::new();
+    let expected_code = match read_expected(case, "exit_code.txt")? {
+        Some(code) => code
+            .trim()
+            .parse::<i32>()
+            .with_context(|| format!("invalid exit_code.txt in `{}`", case.name))?,
+        None => 0,
+    };
+    if outcome.exit_code != expected_code {
+        mismatches.push(format!(
+            "exit code: expected {expected_code}, got {}",
+            outcome.exit_code
+        ));
+    }
+    for (file, actual) in [
+        ("stdout.txt", &outcome.stdout),
+        ("stderr.txt", &outcome.stderr),
+    ] {
+        let Some(expected) = read_expected(case, file)? else {
+            continue;
+        };
+        // Both end in a newline so the diff only shows real differences.
+        let (expected, actual) = (normalize(&expected) + "\n", normalize(actual) + "\n");
+        if expected != actual {
+            let diff = TextDiff::from_lines(&expected, &actual)
+                .unified_diff()
+                .context_radius(3)
+                .header(&format!("expected/{file}"), "actual")
+                .to_string();
+            mismatches.push(format!("{file} differs:\n{diff}"));
+        }
+    }
+    Ok(mismatches)
+}
+
+fn read_expected(case: &TestCase, file: &str) -> Result<Option<String>> {
+    let path = case.expected_dir.join(file);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+fn write_expected(case: &TestCase, outcome: &Outcome) -> Result<()> {
+    std::fs::create_dir_all(&case.expected_dir)
+        .with_context(|| format!("creating {}", case.expected_dir.display()))?;
+    for (file, contents) in [
+        ("stdout.txt", outcome.stdout.clone()),
+        ("stderr.txt", outcome.stderr.clone()),
+        ("exit_code.txt", outcome.exit_code.to_string()),
+    ] {
+        let path = case.expected_dir.join(file);
+        std::fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Ignores trailing whitespace on each line and at the end, like the
+/// repository's own golden tests.
+fn normalize(output: &str) -> String {
+    output
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_are_split_and_placeholder_substituted() {
+        let args = parse_args(
+            "# comment\n--sort yes -f -\n\n--plugin-dir '{TREETAGS_TEST_PLUGINS_DIR}/x y'\n",
+            Path::new("/p"),
+        )
+        .unwrap();
+        assert_eq!(args, ["--sort", "yes", "-f", "-", "--plugin-dir", "/p/x y"]);
+    }
+
+    #[test]
+    fn compare_reports_diffs_and_bless_accepts_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let case_dir = dir.path().join("basic");
+        std::fs::create_dir_all(case_dir.join("input")).unwrap();
+        std::fs::write(case_dir.join("input/args.txt"), "-f -\n").unwrap();
+        std::fs::create_dir_all(case_dir.join("expected")).unwrap();
+        std::fs::write(case_dir.join("expected/stdout.txt"), "a\nb\n").unwrap();
+
+        let cases = discover(dir.path()).unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].name, "basic");
+
+        let outcome = Outcome {
+            stdout: "a\nc  \n".into(),
+            stderr: "ignored without stderr.txt".into(),
+            exit_code: 0,
+        };
+        let mismatches = compare(&cases[0], &outcome).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].contains("-b\n+c\n"), "{}", mismatches[0]);
+
+        write_expected(&cases[0], &outcome).unwrap();
+        assert!(compare(&cases[0], &outcome).unwrap().is_empty());
+        let failing = Outcome {
+            exit_code: 1,
+            ..outcome
+        };
+        assert_eq!(compare(&cases[0], &failing).unwrap().len(), 1);
+    }
+}
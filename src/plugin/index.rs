@@ -1,8 +1,9 @@
 //! Schema for the plugin distribution index published to GitHub Pages.
 //!
 //! Each ABI version gets its own `index.json` (one [`IndexEntry`] per plugin,
-//! latest compatible version only) plus a shared `abis.json` enumerating which
-//! ABI buckets exist. These types are the single source of truth shared by the
+//! describing its latest version and carrying every earlier version published
+//! to the bucket) plus a shared `abis.json` enumerating which ABI buckets
+//! exist. These types are the single source of truth shared by the
 //! `treetags-build-site` generator and the client that installs plugins, so
 //! the published schema can never drift from what the CLI expects.
 
@@ -46,25 +47,59 @@ pub struct IndexEntry {
     pub interpreters: Vec<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kinds: Option<Vec<IndexKind>>,
-    /// Absolute URL of the `.wasm` component (a GitHub Release asset).
+    /// URL of the `.wasm` component (a GitHub Release asset). A relative URL
+    /// is resolved against the ABI bucket of the mirror serving the index.
     pub wasm_url: String,
-    /// Absolute URL of the distribution `plugin.toml`.
+    /// URL of the distribution `plugin.toml`, resolved like `wasm_url`.
     pub manifest_url: String,
     /// Lowercase hex SHA-256 of the `.wasm`, verified by the client on download.
     pub wasm_sha256: String,
     pub wasm_size: u64,
-    /// Reserved for a future detached signature over the `.wasm` (e.g. if the
-    /// project opens to third-party plugins). Absent today: integrity relies on
-    /// a trusted local build plus HTTPS + `wasm_sha256`. Kept optional so it can
-    /// be populated later without a breaking schema change.
+    /// Hex ed25519 signature over the `.wasm`, set when the index is built
+    /// with `--signing-key`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub wasm_signature: Option<String>,
+    /// Hex ed25519 signature over the distribution `plugin.toml`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_signature: Option<String>,
+    /// Extra files installed next to the `.wasm`, such as a declarative
+    /// plugin's query files. Empty for component plugins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<IndexFile>,
+    /// Earlier versions published to this ABI bucket, newest first, so a
+    /// specific version can be installed with `treetags plugin install
+    /// <name>@<version>`. Their own `previous_versions` are always empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_versions: Vec<IndexEntry>,
+    /// Set by the client on entries taken from an older ABI bucket, for
+    /// plugins not yet rebuilt for its own ABI. Never set in published indexes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi_version: Option<u32>,
+    /// Set by the client to the mirror whose index listed this entry, for
+    /// resolving relative artifact URLs. Never serialized.
+    #[serde(skip)]
+    pub mirror: Option<String>,
+}
+
+/// An extra file shipped with a plugin, verified like the `.wasm`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexFile {
+    /// File name inside the installed plugin directory (e.g. `tags.scm`).
+    pub name: String,
+    /// Resolved like [`IndexEntry::wasm_url`].
+    pub url: String,
+    /// Lowercase hex SHA-256 of the file.
+    pub sha256: String,
+    /// Hex ed25519 signature over the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 impl IndexEntry {
     /// Build an entry from a parsed manifest plus the hosting metadata computed
-    /// for its `.wasm`. Asset URLs follow the `<base>/<name>.{wasm,toml}`
-    /// convention used by the publish workflow's per-ABI release.
+    /// for its `.wasm`. Asset URLs follow the `<base>/<name>-<version>.{wasm,toml}`
+    /// convention used by the publish workflow's per-ABI release, so every
+    /// published version stays downloadable.
     pub fn from_manifest(
         manifest: &PluginManifest,
         asset_base_url: &str,
@@ -84,13 +119,73 @@ impl IndexEntry {
                 .kinds
                 .as_ref()
                 .map(|ks| ks.iter().map(IndexKind::from).collect()),
-            wasm_url: format!("{base}/{}.wasm", manifest.name),
-            manifest_url: format!("{base}/{}.toml", manifest.name),
+            wasm_url: format!("{base}/{}-{}.wasm", manifest.name, manifest.version),
+            manifest_url: format!("{base}/{}-{}.toml", manifest.name, manifest.version),
             wasm_sha256,
             wasm_size,
             wasm_signature: None,
+            manifest_signature: None,
+            files: Vec::new(),
+            previous_versions: Vec::new(),
+            abi_version: None,
+            mirror: None,
         }
     }
+
+    /// Adds the extra files shipped next to the `.wasm`, given as
+    /// `(file name, sha256)` pairs and hosted as
+    /// `<base>/<name>-<version>.<file name>`.
+    pub fn with_files(mut self, asset_base_url: &str, files: Vec<(String, String)>) -> Self {
+        let base = asset_base_url.trim_end_matches('/');
+        self.files = files
+            .into_iter()
+            .map(|(file, sha256)| IndexFile {
+                url: format!("{base}/{}-{}.{file}", self.name, self.version),
+                name: file,
+                sha256,
+                signature: None,
+            })
+            .collect();
+        self
+    }
+
+    /// Carries over the versions of `existing` (this plugin's entry in the
+    /// previously published index) as `previous_versions`. A republished
+    /// version replaces its earlier build.
+    pub fn with_history(mut self, existing: Option<&IndexEntry>) -> Self {
+        let mut previous: Vec<IndexEntry> = existing
+            .into_iter()
+            .flat_map(IndexEntry::versions)
+            .filter(|old| old.version != self.version)
+            .map(|old| IndexEntry {
+                previous_versions: Vec::new(),
+                ..old.clone()
+            })
+            .collect();
+        previous.sort_by(|a, b| cmp_version_strings(&b.version, &a.version));
+        previous.dedup_by(|a, b| a.version == b.version);
+        self.previous_versions = previous;
+        self
+    }
+
+    /// This entry followed by its previous versions, newest first.
+    pub fn versions(&self) -> impl Iterator<Item = &IndexEntry> {
+        std::iter::once(self).chain(&self.previous_versions)
+    }
+
+    /// The published entry for `version`, if any.
+    pub fn find_version(&self, version: &str) -> Option<&IndexEntry> {
+        self.versions().find(|v| v.version == version)
+    }
+}
+
+/// Orders versions as semver, falling back to a plain string comparison for
+/// versions that do not parse.
+fn cmp_version_strings(a: &str, b: &str) -> std::cmp::Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
 }
 
 /// The `index.json` for one ABI bucket.
@@ -202,11 +297,11 @@ mod tests {
         // Trailing slash on the base is trimmed exactly once.
         assert_eq!(
             entry.wasm_url,
-            "https://example.com/releases/download/plugin-store-v3/java.wasm"
+            "https://example.com/releases/download/plugin-store-v3/java-0.2.0.wasm"
         );
         assert_eq!(
             entry.manifest_url,
-            "https://example.com/releases/download/plugin-store-v3/java.toml"
+            "https://example.com/releases/download/plugin-store-v3/java-0.2.0.toml"
         );
         assert_eq!(entry.wasm_sha256, "deadbeef");
         assert_eq!(entry.wasm_size, 42);
@@ -214,6 +309,49 @@ mod tests {
         assert_eq!(kinds.len(), 2);
         assert!(kinds[0].default, "kinds default to true when omitted");
         assert!(!kinds[1].default);
+        assert!(entry.files.is_empty());
+    }
+
+    #[test]
+    fn entry_with_files_builds_urls() {
+        let entry = IndexEntry::from_manifest(&manifest(), "https://x/y/", "ab".into(), 1)
+            .with_files("https://x/y/", vec![("tags.scm".into(), "cd".into())]);
+        assert_eq!(
+            entry.files,
+            vec![IndexFile {
+                name: "tags.scm".into(),
+                url: "https://x/y/java-0.2.0.tags.scm".into(),
+                sha256: "cd".into(),
+                signature: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn history_collects_earlier_versions_newest_first() {
+        let build = |version: &str, sha: &str| {
+            let mut m = manifest();
+            m.version = version.to_string();
+            IndexEntry::from_manifest(&m, "https://x/y", sha.into(), 1)
+        };
+        let v1 = build("0.1.0", "a");
+        let v2 = build("0.2.0", "b").with_history(Some(&v1));
+        // 0.2.0 is rebuilt and republished, then 0.10.0 is released.
+        let v2_rebuilt = build("0.2.0", "c").with_history(Some(&v2));
+        let v10 = build("0.10.0", "d").with_history(Some(&v2_rebuilt));
+
+        let versions: Vec<_> = v10.versions().map(|v| v.version.as_str()).collect();
+        assert_eq!(versions, ["0.10.0", "0.2.0", "0.1.0"]);
+        assert_eq!(v10.find_version("0.2.0").unwrap().wasm_sha256, "c");
+        assert!(v10
+            .previous_versions
+            .iter()
+            .all(|v| v.previous_versions.is_empty()));
+        assert!(v10.find_version("0.3.0").is_none());
+        assert!(build("0.1.0", "a")
+            .with_history(None)
+            .previous_versions
+            .is_empty());
     }
 
     #[test]
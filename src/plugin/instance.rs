@@ -1,14 +1,30 @@
 use std::path::Path;
-use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime::component::{InstancePre, ResourceTable};
 use wasmtime::Store;
 use wasmtime_wasi::{DirPerms, FilePerms};
 
+// `full-plugin-world` is a superset of the other plugin worlds, so one set of
+// bindings serves them all; the `batch` and `detect` exports are looked up
+// separately and are optional.
 wasmtime::component::bindgen!({
-    world: "plugin-world",
+    world: "full-plugin-world",
     path: "wit",
 });
 
-pub use exports::treetags::plugin::plugin::{Request, Tag};
+pub use batch::BatchFile;
+use exports::treetags::plugin::{batch, detect, plugin};
+pub use plugin::{Request, Tag};
+
+/// Bindings for plugins built against ABI 3 (`wit/v3`), which predates the
+/// request's `project-path` and the `batch` interface.
+mod v3 {
+    wasmtime::component::bindgen!({
+        world: "plugin-world",
+        path: "wit/v3",
+    });
+}
+
+use v3::exports::treetags::plugin::plugin as plugin_v3;
 
 pub struct PluginState {
     ctx: wasmtime_wasi::WasiCtx,
@@ -24,35 +40,173 @@ impl wasmtime_wasi::WasiView for PluginState {
     }
 }
 
+/// Export indices resolved once per compiled component, for the world of the
+/// ABI version its manifest declares.
+pub enum ExportIndices {
+    Current {
+        plugin: plugin::GuestIndices,
+        /// `None` when the component does not export the optional `batch` interface.
+        batch: Option<batch::GuestIndices>,
+        /// `None` when the component does not export the optional `detect` interface.
+        detect: Option<detect::GuestIndices>,
+    },
+    V3(plugin_v3::GuestIndices),
+}
+
+impl ExportIndices {
+    pub fn new(pre: &InstancePre<PluginState>, abi_version: u32) -> anyhow::Result<Self> {
+        match abi_version {
+            super::PLUGIN_ABI_VERSION => Ok(Self::Current {
+                plugin: plugin::GuestIndices::new(pre)?,
+                batch: batch::GuestIndices::new(pre).ok(),
+                detect: detect::GuestIndices::new(pre).ok(),
+            }),
+            3 => Ok(Self::V3(plugin_v3::GuestIndices::new(pre)?)),
+            abi => anyhow::bail!("unsupported plugin ABI version {abi}"),
+        }
+    }
+
+    /// Whether the component exports the optional `detect` interface.
+    pub fn has_detect(&self) -> bool {
+        matches!(
+            self,
+            Self::Current {
+                detect: Some(_),
+                ..
+            }
+        )
+    }
+}
+
+enum Exports {
+    Current {
+        plugin: plugin::Guest,
+        batch: Option<batch::Guest>,
+        detect: Option<detect::Guest>,
+    },
+    V3(plugin_v3::Guest),
+}
+
 pub struct WasmInstance {
     store: Store<PluginState>,
-    plugin: PluginWorld,
+    exports: Exports,
 }
 
 impl WasmInstance {
-    pub fn from_component(
+    pub fn instantiate(
         mut store: Store<PluginState>,
-        component: &Component,
-        linker: &Linker<PluginState>,
+        pre: &InstancePre<PluginState>,
+        indices: &ExportIndices,
     ) -> anyhow::Result<Self> {
-        let plugin = PluginWorld::instantiate(&mut store, component, linker)?;
-        Ok(Self { store, plugin })
+        let instance = pre.instantiate(&mut store)?;
+        let exports = match indices {
+            ExportIndices::Current {
+                plugin,
+                batch,
+                detect,
+            } => Exports::Current {
+                plugin: plugin.load(&mut store, &instance)?,
+                batch: match batch {
+                    Some(batch) => Some(batch.load(&mut store, &instance)?),
+                    None => None,
+                },
+                detect: match detect {
+                    Some(detect) => Some(detect.load(&mut store, &instance)?),
+                    None => None,
+                },
+            },
+            ExportIndices::V3(plugin) => Exports::V3(plugin.load(&mut store, &instance)?),
+        };
+        Ok(Self { store, exports })
     }
 
-    /// Calls the plugin's `generate` export.
+    /// Calls the plugin's `generate` export. ABI 3 plugins get the request
+    /// without its `project_path`.
     pub fn generate(
         &mut self,
         req: &Request,
         source: &[u8],
     ) -> anyhow::Result<Result<Vec<Tag>, String>> {
-        self.plugin
-            .treetags_plugin_plugin()
-            .call_generate(&mut self.store, req, source)
-            .map_err(|e| anyhow::anyhow!("{e}"))
+        let result = match &self.exports {
+            Exports::Current { plugin, .. } => plugin.call_generate(&mut self.store, req, source),
+            Exports::V3(plugin) => {
+                let req = plugin_v3::Request {
+                    file_path: req.file_path.clone(),
+                    kinds: req.kinds.clone(),
+                    extras: req.extras.clone(),
+                    fields: req.fields.clone(),
+                    cache_file: req.cache_file.clone(),
+                };
+                plugin
+                    .call_generate(&mut self.store, &req, source)
+                    .map(|result| result.map(|tags| tags.into_iter().map(tag_from_v3).collect()))
+            }
+        };
+        result.map_err(|e| anyhow::anyhow!("{e}"))
+    }
+
+    /// Whether the plugin exports the optional `batch` interface.
+    pub fn supports_batch(&self) -> bool {
+        matches!(self.exports, Exports::Current { batch: Some(_), .. })
+    }
+
+    /// Calls the plugin's `generate-batch` export. Returns `None` when the
+    /// plugin does not export the `batch` interface.
+    #[allow(clippy::type_complexity)]
+    pub fn generate_batch(
+        &mut self,
+        files: &[BatchFile],
+    ) -> Option<anyhow::Result<Vec<Result<Vec<Tag>, String>>>> {
+        let Exports::Current {
+            batch: Some(batch), ..
+        } = &self.exports
+        else {
+            return None;
+        };
+        Some(
+            batch
+                .call_generate_batch(&mut self.store, files)
+                .map_err(|e| anyhow::anyhow!("{e}")),
+        )
+    }
+
+    /// Calls the plugin's `score` export on a file's content prefix. Returns
+    /// `None` when the plugin does not export the `detect` interface.
+    pub fn score(&mut self, prefix: &[u8]) -> Option<anyhow::Result<u32>> {
+        let Exports::Current {
+            detect: Some(detect),
+            ..
+        } = &self.exports
+        else {
+            return None;
+        };
+        Some(
+            detect
+                .call_score(&mut self.store, prefix)
+                .map_err(|e| anyhow::anyhow!("{e}")),
+        )
     }
 }
 
-pub fn new_store(engine: &wasmtime::Engine, cache_dir: Option<&Path>) -> Store<PluginState> {
+fn tag_from_v3(tag: plugin_v3::Tag) -> Tag {
+    Tag {
+        name: tag.name,
+        line: tag.line,
+        kind: tag.kind,
+        end_line: tag.end_line,
+        extension_fields: tag.extension_fields,
+    }
+}
+
+/// Guest path at which the project root is preopened for plugins granted
+/// project read access.
+pub const PROJECT_GUEST_DIR: &str = "/project";
+
+pub fn new_store(
+    engine: &wasmtime::Engine,
+    cache_dir: Option<&Path>,
+    project_root: Option<&Path>,
+) -> Store<PluginState> {
     let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
     builder.inherit_stderr();
     if let Some(dir) = cache_dir {
@@ -62,6 +216,16 @@ pub fn new_store(engine: &wasmtime::Engine, cache_dir: Option<&Path>) -> Store<P
             eprintln!("treetags: cannot preopen cache dir {}: {e}", dir.display());
         }
     }
+    if let Some(root) = project_root {
+        if let Err(e) =
+            builder.preopened_dir(root, PROJECT_GUEST_DIR, DirPerms::READ, FilePerms::READ)
+        {
+            eprintln!(
+                "treetags: cannot preopen project dir {}: {e}",
+                root.display()
+            );
+        }
+    }
     let state = PluginState {
         ctx: builder.build(),
         table: ResourceTable::new(),
@@ -0,0 +1,145 @@
+//! `treetags-plugins.lock`: the plugin versions a project is pinned to.
+//!
+//! The lockfile lives in the project root and is meant to be committed.
+//! `treetags plugin install`/`update`/`uninstall` keep it current once it
+//! exists (or when `install --lock` creates it), and `treetags plugin sync`
+//! installs exactly the locked versions so every developer generates the same
+//! tags.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::index::IndexEntry;
+
+/// File name of the lockfile, looked up in the current directory.
+pub const LOCKFILE_NAME: &str = "treetags-plugins.lock";
+
+const HEADER: &str = "# Generated by `treetags plugin install`/`update`; \
+                      install these versions with `treetags plugin sync`.\n\n";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Lockfile {
+    /// Base URL of the plugin index the locked plugins were installed from.
+    pub index_url: String,
+    /// Locked plugins, sorted by name.
+    #[serde(default, rename = "plugin")]
+    pub plugins: Vec<LockedPlugin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LockedPlugin {
+    pub name: String,
+    pub version: String,
+    /// Lowercase hex SHA-256 of the installed `.wasm`.
+    pub wasm_sha256: String,
+}
+
+impl From<&IndexEntry> for LockedPlugin {
+    fn from(entry: &IndexEntry) -> Self {
+        LockedPlugin {
+            name: entry.name.clone(),
+            version: entry.version.clone(),
+            wasm_sha256: entry.wasm_sha256.clone(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Reads the lockfile at `path`, or `None` if there is none.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        toml::from_str(&text)
+            .map(Some)
+            .with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let body = toml::to_string(self).context("serializing lockfile")?;
+        std::fs::write(path, format!("{HEADER}{body}"))
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Records `locked` as the locked version of its plugin.
+    pub fn lock(&mut self, locked: LockedPlugin) {
+        match self.plugins.binary_search_by(|p| p.name.cmp(&locked.name)) {
+            Ok(i) => self.plugins[i] = locked,
+            Err(i) => self.plugins.insert(i, locked),
+        }
+    }
+
+    /// Drops the plugin named `name`. Returns whether it was locked.
+    pub fn unlock(&mut self, name: &str) -> bool {
+        match self.plugins.binary_search_by(|p| p.name.as_str().cmp(name)) {
+            Ok(i) => {
+                self.plugins.remove(i);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, version: &str) -> IndexEntry {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version,
+            "extensions": [name],
+            "wasm_url": "https://x/a.wasm",
+            "manifest_url": "https://x/a.toml",
+            "wasm_sha256": format!("{name}-{version}"),
+            "wasm_size": 1,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn lock_keeps_plugins_sorted_and_replaces_versions() {
+        let mut lock = Lockfile::default();
+        lock.lock(LockedPlugin::from(&entry("kotlin", "0.1.0")));
+        lock.lock(LockedPlugin::from(&entry("java", "0.1.0")));
+        lock.lock(LockedPlugin::from(&entry("kotlin", "0.2.0")));
+        let names: Vec<_> = lock.plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["java", "kotlin"]);
+        assert_eq!(lock.plugins[1].version, "0.2.0");
+        assert_eq!(lock.plugins[1].wasm_sha256, "kotlin-0.2.0");
+    }
+
+    #[test]
+    fn unlock_drops_only_the_named_plugin() {
+        let mut lock = Lockfile::default();
+        lock.lock(LockedPlugin::from(&entry("java", "0.1.0")));
+        lock.lock(LockedPlugin::from(&entry("kotlin", "0.1.0")));
+        assert!(lock.unlock("java"));
+        assert!(!lock.unlock("java"));
+        let names: Vec<_> = lock.plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["kotlin"]);
+    }
+
+    #[test]
+    fn lockfile_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+        assert_eq!(Lockfile::load(&path).unwrap(), None);
+
+        let mut lock = Lockfile {
+            index_url: "https://example.com/plugins".into(),
+            plugins: Vec::new(),
+        };
+        lock.lock(LockedPlugin::from(&entry("java", "0.2.0")));
+        lock.save(&path).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# Generated"));
+        assert!(text.contains("[[plugin]]"));
+        assert_eq!(Lockfile::load(&path).unwrap(), Some(lock));
+    }
+}
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 /// A single tag kind entry from a plugin's `plugin.toml` manifest.
@@ -22,6 +23,9 @@ pub struct PluginManifest {
     pub version: String,
     /// ABI version the plugin targets. Must match the host's `PLUGIN_ABI_VERSION`.
     pub abi_version: u32,
+    /// File extensions the plugin generates tags for. Empty for
+    /// post-processors.
+    #[serde(default)]
     pub extensions: Vec<String>,
     /// Language name used to look up `--kinds-{lang}` config and to match
     /// `--language-force`.
@@ -39,8 +43,24 @@ pub struct PluginManifest {
     #[serde(default)]
     pub interpreters: Vec<String>,
     /// Path to the .wasm component file, relative to the directory containing this manifest.
+    /// For declarative plugins this is the tree-sitter grammar compiled to WASM.
     #[serde(default = "default_wasm_file")]
     pub wasm_file: String,
+    /// Makes this a declarative plugin: the tree-sitter grammar name, whose
+    /// `tree_sitter_<grammar>` function `wasm_file` exports. Tags then come from
+    /// `tags_query` instead of a component's `generate` export.
+    #[serde(default)]
+    pub grammar: Option<String>,
+    /// Tags query of a declarative plugin, relative to this manifest.
+    #[serde(default = "default_tags_query")]
+    pub tags_query: String,
+    /// Optional locals query of a declarative plugin, relative to this manifest.
+    #[serde(default)]
+    pub locals_query: Option<String>,
+    /// Maps the syntax type of a declarative plugin's `@definition.<type>`
+    /// captures to the kind letter emitted for it.
+    #[serde(default)]
+    pub kind_map: BTreeMap<String, String>,
     /// Optional list of tag kinds the plugin can generate, for `--list-kinds` output.
     pub kinds: Option<Vec<ManifestKind>>,
     /// Marks a dev/test-only plugin: it still loads and routes when explicitly
@@ -49,14 +69,39 @@ pub struct PluginManifest {
     /// install it. Defaults to false.
     #[serde(default)]
     pub internal: bool,
+    /// Makes this a tag post-processor exporting the `post-process` interface
+    /// instead of a language plugin. It is only run when listed under
+    /// `[[post_processors]]` in the config file. Defaults to false.
+    #[serde(default)]
+    pub post_processor: bool,
 }
 
 fn default_wasm_file() -> String {
     "plugin.wasm".to_string()
 }
 
+fn default_tags_query() -> String {
+    "tags.scm".to_string()
+}
+
 impl PluginManifest {
     pub fn wasm_path(&self, manifest_dir: &Path) -> PathBuf {
         manifest_dir.join(&self.wasm_file)
     }
+
+    /// Whether this is a declarative (grammar + query) plugin.
+    pub fn is_declarative(&self) -> bool {
+        self.grammar.is_some()
+    }
+
+    /// Query files shipped alongside the `.wasm` by a declarative plugin,
+    /// relative to the manifest. Empty for component plugins.
+    pub fn query_files(&self) -> Vec<&str> {
+        if !self.is_declarative() {
+            return Vec::new();
+        }
+        std::iter::once(self.tags_query.as_str())
+            .chain(self.locals_query.as_deref())
+            .collect()
+    }
 }
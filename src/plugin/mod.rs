@@ -1,19 +1,40 @@
+pub mod bundle;
+pub mod cache;
 pub mod client;
-// Consumed by the treetags-build-site bin and the plugin-install client; a few
-// items (AbisFile/merged) are only used by the bin, so they read as dead from
-// the main treetags binary's copy of this module tree.
-#[allow(dead_code)]
+pub(crate) mod declarative;
+pub mod golden;
 pub mod index;
 pub(crate) mod instance;
+pub mod lockfile;
 pub mod manifest;
+pub mod post_process;
 pub mod registry;
 mod shared;
+pub mod signing;
 
 pub use registry::print_plugin_list;
-#[allow(unused_imports)]
 pub use registry::PluginRegistry;
 
+/// Whether `path` is a hidden (dot) directory, such as the `.rollback/` copies
+/// of replaced installs, which plugin discovery skips.
+pub(crate) fn is_hidden(path: &std::path::Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
 /// ABI version this build of treetags accepts from WASM plugins.
 /// Bump this whenever the WIT interface (wit/treetags-plugin.wit) changes
 /// in a backwards-incompatible way, and update the constant in plugins/common.
-pub const PLUGIN_ABI_VERSION: u32 = 3;
+/// Keep the previous WIT under `wit/v<N>` and add it to
+/// [`SUPPORTED_ABI_VERSIONS`] so existing plugins keep loading.
+pub const PLUGIN_ABI_VERSION: u32 = 4;
+
+/// ABI versions whose plugins this build loads, newest first. Plugins built
+/// for an older ABI keep working until they are rebuilt; the host binds each
+/// one's WIT world (`wit/v<N>`) in [`instance`].
+pub const SUPPORTED_ABI_VERSIONS: &[u32] = &[PLUGIN_ABI_VERSION, 3];
+
+/// Whether plugins targeting `abi_version` can be loaded.
+pub fn supports_abi(abi_version: u32) -> bool {
+    SUPPORTED_ABI_VERSIONS.contains(&abi_version)
+}
@@ -0,0 +1,317 @@
+//! Tag post-processing plugins: components exporting the `post-process`
+//! interface (`post-processor-world` in wit/treetags-plugin.wit) that rewrite
+//! the tags generated for a file, whatever its language.
+//!
+//! They run as an ordered pipeline configured by `[[post_processors]]` in the
+//! config file, each step receiving the tags returned by the previous one. A
+//! step that cannot be loaded is skipped for the whole run, and one that fails
+//! on a file leaves that file's tags as they were. Post-processors get no
+//! filesystem access; a step configured with `source = true` receives the
+//! file's contents instead.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use wasmtime::component::{Component, InstancePre, Linker};
+use wasmtime::{Engine, Store};
+
+use super::instance::{new_store, PluginState};
+use super::manifest::PluginManifest;
+use crate::config::Config;
+use crate::tag::{ExtensionFields, Tag};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "post-processor-world",
+        path: "wit",
+    });
+}
+
+use bindings::exports::treetags::plugin::post_process::{FileTags, Guest, GuestIndices, TagEntry};
+
+/// The configured post-processors, compiled once and shared by all workers.
+pub struct PostProcessPipeline {
+    engine: Engine,
+    steps: Vec<Step>,
+}
+
+struct Step {
+    name: String,
+    args: String,
+    source: bool,
+    pre: InstancePre<PluginState>,
+    indices: GuestIndices,
+}
+
+impl PostProcessPipeline {
+    /// Loads the `[[post_processors]]` of `config`, looking plugins up by name
+    /// in the same directories as language plugins.
+    pub fn load(config: &Config) -> Self {
+        let engine = Engine::default();
+        let mut steps = Vec::new();
+        for step in &config.post_processors {
+            let loaded = find_plugin(&step.plugin, config)
+                .and_then(|(manifest, dir)| compile(&engine, &manifest, &dir));
+            match loaded {
+                Ok((name, pre, indices)) => steps.push(Step {
+                    name,
+                    args: step.args.clone(),
+                    source: step.source,
+                    pre,
+                    indices,
+                }),
+                Err(e) => eprintln!("treetags: skipping post-processor '{}': {e:#}", step.plugin),
+            }
+        }
+        Self { engine, steps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Creates a worker's runner, which instantiates each step on first use.
+    pub fn runner(self: &Arc<Self>) -> PipelineRunner {
+        PipelineRunner {
+            pipeline: Arc::clone(self),
+            instances: self.steps.iter().map(|_| None).collect(),
+        }
+    }
+}
+
+/// A worker thread's instances of the pipeline's steps.
+pub struct PipelineRunner {
+    pipeline: Arc<PostProcessPipeline>,
+    instances: Vec<Option<(Store<PluginState>, Guest)>>,
+}
+
+impl PipelineRunner {
+    /// Whether there are no steps to run.
+    pub fn is_empty(&self) -> bool {
+        self.pipeline.is_empty()
+    }
+
+    /// Runs `tags`, generated for `file_path` in `language`, through every
+    /// step of the pipeline.
+    pub fn run(
+        &mut self,
+        mut tags: Vec<Tag>,
+        file_path: &str,
+        language: &str,
+        source: &[u8],
+    ) -> Vec<Tag> {
+        for (step, slot) in self.pipeline.steps.iter().zip(&mut self.instances) {
+            if slot.is_none() {
+                let store = new_store(&self.pipeline.engine, None, None);
+                match instantiate(store, step) {
+                    Ok(instance) => *slot = Some(instance),
+                    Err(e) => {
+                        eprintln!("treetags: post-processor '{}' init error: {e}", step.name);
+                        continue;
+                    }
+                }
+            }
+            let (store, guest) = slot.as_mut().expect("instantiated above");
+
+            let originals = original_addresses(&tags);
+            let input = FileTags {
+                file_path: file_path.to_string(),
+                language: language.to_string(),
+                args: step.args.clone(),
+                tags: tags.iter().map(to_entry).collect(),
+                source: step.source.then(|| source.to_vec()),
+            };
+            match guest.call_process(&mut *store, &input) {
+                Ok(Ok(entries)) => {
+                    let file_name: Arc<str> = Arc::from(file_path);
+                    tags = entries
+                        .into_iter()
+                        .map(|entry| from_entry(entry, &file_name, &originals))
+                        .collect();
+                }
+                Ok(Err(msg)) => {
+                    eprintln!(
+                        "treetags: post-processor '{}' error for {file_path}: {msg}",
+                        step.name
+                    );
+                }
+                Err(e) => {
+                    eprintln!(
+                        "treetags: post-processor '{}' call error for {file_path}: {e}",
+                        step.name
+                    );
+                    // A trapped instance cannot be reused; start afresh next time.
+                    *slot = None;
+                }
+            }
+        }
+        tags
+    }
+}
+
+/// Finds the post-processor named `plugin`, or the one in the directory it
+/// names when it contains a `/`.
+fn find_plugin(plugin: &str, config: &Config) -> Result<(PluginManifest, PathBuf)> {
+    let candidates = if plugin.contains('/') {
+        vec![Path::new(plugin).join("plugin.toml")]
+    } else {
+        super::registry::manifest_paths(&config.plugin_dirs, Some(&config.plugins_dir))
+    };
+    let mut found = None;
+    for path in candidates {
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let Ok(manifest) = toml::from_str::<PluginManifest>(&text) else {
+            continue;
+        };
+        if plugin.contains('/') || manifest.name == plugin {
+            let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+            // Later manifests take precedence, as for language plugins.
+            found = Some((manifest, dir));
+        }
+    }
+    let (manifest, dir) = found.ok_or_else(|| anyhow!("no such plugin"))?;
+    if !manifest.post_processor {
+        bail!(
+            "plugin `{}` is not a post-processor (set `post_processor = true` in its plugin.toml)",
+            manifest.name
+        );
+    }
+    if manifest.abi_version != super::PLUGIN_ABI_VERSION {
+        bail!(
+            "post-processor `{}` targets ABI {} but post-processors need ABI {}",
+            manifest.name,
+            manifest.abi_version,
+            super::PLUGIN_ABI_VERSION
+        );
+    }
+    Ok((manifest, dir))
+}
+
+fn compile(
+    engine: &Engine,
+    manifest: &PluginManifest,
+    dir: &Path,
+) -> Result<(String, InstancePre<PluginState>, GuestIndices)> {
+    let wasm_path = manifest.wasm_path(dir);
+    let component = Component::from_file(engine, &wasm_path)
+        .map_err(|e| anyhow!("load component {}: {e}", wasm_path.display()))?;
+    let mut linker: Linker<PluginState> = Linker::new(engine);
+    wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+    let pre = linker.instantiate_pre(&component)?;
+    let indices = GuestIndices::new(&pre)
+        .map_err(|e| anyhow!("the component does not export `post-process`: {e}"))?;
+    Ok((manifest.name.clone(), pre, indices))
+}
+
+fn instantiate(mut store: Store<PluginState>, step: &Step) -> Result<(Store<PluginState>, Guest)> {
+    let instance = step.pre.instantiate(&mut store)?;
+    let guest = step.indices.load(&mut store, &instance)?;
+    Ok((store, guest))
+}
+
+/// The Ex command of a tag's address, without the `;"` (and tab) that
+/// separates it from the fields in the tags file.
+fn ex_command(address: &str) -> &str {
+    address
+        .trim_end_matches('\t')
+        .strip_suffix(";\"")
+        .unwrap_or(address.trim_end_matches('\t'))
+}
+
+/// Maps each Ex command handed to a step back to the exact address it came
+/// from, so tags passed through unchanged are written exactly as before.
+fn original_addresses(tags: &[Tag]) -> HashMap<String, String> {
+    tags.iter()
+        .map(|tag| (ex_command(&tag.address).to_string(), tag.address.clone()))
+        .collect()
+}
+
+fn to_entry(tag: &Tag) -> TagEntry {
+    TagEntry {
+        name: tag.name.clone(),
+        address: ex_command(&tag.address).to_string(),
+        kind: tag.kind.as_ref().map(|kind| kind.to_string()),
+        extension_fields: tag
+            .extension_fields
+            .iter()
+            .flat_map(|fields| fields.iter())
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    }
+}
+
+fn from_entry(entry: TagEntry, file_name: &Arc<str>, originals: &HashMap<String, String>) -> Tag {
+    let address = originals
+        .get(&entry.address)
+        .cloned()
+        .unwrap_or_else(|| format!("{};\"", entry.address));
+    let mut fields = ExtensionFields::new();
+    for (key, value) in entry.extension_fields {
+        fields.insert(key, value);
+    }
+    Tag {
+        name: entry.name,
+        file_name: Arc::clone(file_name),
+        address,
+        kind: entry.kind.map(Into::into),
+        extension_fields: (!fields.is_empty()).then_some(fields),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_round_trip_through_entries() {
+        let mut fields = ExtensionFields::new();
+        fields.insert("line", "3");
+        fields.insert("class", "Foo");
+        let tags = vec![
+            Tag {
+                name: "bar".into(),
+                file_name: Arc::from("a.rs"),
+                address: "/^fn bar() {$/;\"\t".into(),
+                kind: Some("f".into()),
+                extension_fields: Some(fields),
+            },
+            Tag {
+                name: "baz".into(),
+                file_name: Arc::from("a.rs"),
+                address: "/^const BAZ: u8 = 1;$/;\"".into(),
+                kind: None,
+                extension_fields: None,
+            },
+        ];
+        let originals = original_addresses(&tags);
+        let entries: Vec<TagEntry> = tags.iter().map(to_entry).collect();
+        assert_eq!(entries[0].address, "/^fn bar() {$/");
+        assert_eq!(
+            entries[0].extension_fields,
+            [("line".into(), "3".into()), ("class".into(), "Foo".into())]
+        );
+
+        let file_name = Arc::from("a.rs");
+        let back: Vec<Tag> = entries
+            .into_iter()
+            .map(|entry| from_entry(entry, &file_name, &originals))
+            .collect();
+        assert_eq!(back, tags);
+
+        let added = from_entry(
+            TagEntry {
+                name: "new".into(),
+                address: "/^new$/".into(),
+                kind: Some("v".into()),
+                extension_fields: Vec::new(),
+            },
+            &file_name,
+            &originals,
+        );
+        assert_eq!(added.address, "/^new$/;\"");
+    }
+}
@@ -1,13 +1,16 @@
-use super::instance::{Request, Tag as PluginTag, WasmInstance};
+use super::declarative::{resolve_kinds, DeclarativeGrammar, DeclarativeSpec, GrammarRuntime};
+use super::instance::{BatchFile, Request, Tag as PluginTag, WasmInstance};
 use super::manifest::PluginManifest;
 use super::shared::SharedPlugin;
 use crate::config::Config;
+use crate::language_parser::SourceFile;
 use crate::split_by_newlines::split_by_newlines;
 use crate::tag::{ExtensionFields, Tag};
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, OnceLock};
+use tree_sitter_tags::TagsContext;
 use wasmtime::Engine;
 
 struct PluginEntry {
@@ -21,6 +24,10 @@ struct PluginEntry {
     /// true for Dev/test-only plugin: routes normally but is hidden from
     /// `list_plugins` and plugin build CI.
     internal: bool,
+    /// Set for declarative plugins, whose `wasm_path` is a tree-sitter grammar.
+    declarative: Option<Arc<DeclarativeSpec>>,
+    /// ABI version from the manifest, selecting the WIT world to bind.
+    abi_version: u32,
 }
 
 struct ExtPlugin {
@@ -29,6 +36,9 @@ struct ExtPlugin {
     name: String,
     /// The plugin's declared kind letters
     kind_letters: HashSet<&'static str>,
+    kinds: Vec<super::manifest::ManifestKind>,
+    declarative: Option<Arc<DeclarativeSpec>>,
+    abi_version: u32,
 }
 
 /// Language name and file extensions for a detected plugin.
@@ -37,84 +47,136 @@ pub struct PluginInfo {
     pub extensions: Vec<String>,
 }
 
-/// Shared-across-threads plugin registry. On the first file processed for a given
-/// extension, its `.wasm` is JIT-compiled into a `SharedPlugin` stored in `compiled`.
+/// Shared-across-threads plugin registry. On the first file processed by a given
+/// plugin, its `.wasm` is JIT-compiled into a `SharedPlugin` stored in `compiled`.
 /// Worker threads each lazily create their own per-thread `WasmInstance` from the
 /// shared compiled plugin via `SharedPlugin::create_instance()`.
 pub struct PluginRegistry {
-    entries: HashMap<String, PluginEntry>,
-    ext_plugins: HashMap<String, ExtPlugin>,
+    /// Extension → the plugins claiming it, highest priority first.
+    entries: HashMap<String, Vec<PluginEntry>>,
+    /// Plugin name → plugin.
+    plugins: HashMap<String, ExtPlugin>,
     compiled: HashMap<PathBuf, OnceLock<Option<SharedPlugin>>>,
+    /// One instance per plugin exporting `detect`, shared by all threads for
+    /// scoring ambiguous files. Created on first use; `None` when the plugin
+    /// does not export `detect` or fails to load.
+    detectors: HashMap<String, OnceLock<Option<Mutex<WasmInstance>>>>,
     engine: Engine,
+    /// Declarative plugin grammars, loaded lazily like `compiled`.
+    grammars: HashMap<PathBuf, OnceLock<Option<DeclarativeGrammar>>>,
+    grammar_runtime: GrammarRuntime,
     /// Plugins opted in for cache file access.
     cache_enabled_plugins: HashSet<String>,
     /// Per-project cache root: ~/.cache/treetags/<hash-of-cwd>/.
     /// None when no plugins have cache access enabled.
     project_cache_root: Option<PathBuf>,
+    /// Plugins opted in for read-only access to the project root.
+    project_read_plugins: HashSet<String>,
+    /// The project root (cwd), preopened read-only for `project_read_plugins`.
+    project_root: PathBuf,
 }
 
 impl PluginRegistry {
     /// Scans `dirs` for `plugin.toml` manifests. WASM binaries are JIT-compiled lazily
     /// on first use, at most once per unique `.wasm` file.
-    /// `cache_plugins` is the list of plugin names granted cache file access.
+    /// `cache_plugins` is the list of plugin names granted cache file access and
+    /// `project_read_plugins` those granted read-only access to the project root.
     pub fn scan(
         dirs: &[PathBuf],
         recursive_dir: Option<&PathBuf>,
         cache_plugins: &[String],
+        project_read_plugins: &[String],
     ) -> Self {
         let entries = scan_to_entries(dirs, recursive_dir);
 
-        let mut ext_plugins: HashMap<String, ExtPlugin> = HashMap::new();
+        let mut plugins: HashMap<String, ExtPlugin> = HashMap::new();
         let mut compiled: HashMap<PathBuf, OnceLock<Option<SharedPlugin>>> = HashMap::new();
+        let mut grammars: HashMap<PathBuf, OnceLock<Option<DeclarativeGrammar>>> = HashMap::new();
 
-        for (ext, entry) in &entries {
-            compiled
-                .entry(entry.wasm_path.clone())
-                .or_insert_with(OnceLock::new);
+        for entry in entries.values().flatten() {
+            if plugins.contains_key(&entry.name) {
+                continue;
+            }
+            if entry.declarative.is_some() {
+                grammars.entry(entry.wasm_path.clone()).or_default();
+            } else {
+                compiled.entry(entry.wasm_path.clone()).or_default();
+            }
             let kind_letters = entry
                 .kinds
                 .iter()
                 .map(|k| &*Box::leak(k.letter.clone().into_boxed_str()))
                 .collect();
-            ext_plugins.insert(
-                ext.clone(),
+            plugins.insert(
+                entry.name.clone(),
                 ExtPlugin {
                     wasm_path: entry.wasm_path.clone(),
                     language: entry.language.clone(),
                     name: entry.name.clone(),
                     kind_letters,
+                    kinds: entry.kinds.clone(),
+                    declarative: entry.declarative.clone(),
+                    abi_version: entry.abi_version,
                 },
             );
         }
 
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
         let cache_enabled_plugins: HashSet<String> = cache_plugins.iter().cloned().collect();
         let project_cache_root = if cache_enabled_plugins.is_empty() {
             None
         } else {
-            let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-            let project_hash = format!("{:016x}", fnv1a_64(cwd.as_os_str().as_encoded_bytes()));
-            Some(crate::config::paths::get_cache_dir().join(project_hash))
+            Some(crate::config::paths::get_cache_dir().join(super::cache::project_hash(&cwd)))
         };
 
+        // ABI 3 requests have no `project_path`, so the grant cannot reach them.
+        let outdated: BTreeSet<&str> = plugins
+            .values()
+            .filter(|e| e.declarative.is_none() && e.abi_version < super::PLUGIN_ABI_VERSION)
+            .filter(|e| project_read_plugins.contains(&e.name))
+            .map(|e| e.name.as_str())
+            .collect();
+        for name in outdated {
+            eprintln!(
+                "treetags: plugin '{name}' targets an ABI without project paths; \
+                 rebuild it to use --plugin-project-read"
+            );
+        }
+
+        let detectors = plugins
+            .values()
+            .filter(|ep| ep.declarative.is_none())
+            .map(|ep| (ep.name.clone(), OnceLock::new()))
+            .collect();
+
         Self {
             entries,
-            ext_plugins,
+            plugins,
             compiled,
+            detectors,
             engine: Engine::default(),
+            grammars,
+            grammar_runtime: GrammarRuntime::default(),
             cache_enabled_plugins,
             project_cache_root,
+            project_read_plugins: project_read_plugins.iter().cloned().collect(),
+            project_root: cwd,
         }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.ext_plugins.is_empty()
+        self.plugins.is_empty()
     }
 
     /// Returns info about all detected plugins, grouped by plugin identity (wasm path).
     /// Each entry has the display language name (falls back to plugin name) and sorted extensions.
     pub fn list_plugins(&self) -> Vec<PluginInfo> {
         let mut by_wasm: HashMap<&PathBuf, (String, Vec<String>)> = HashMap::new();
-        for (ext, entry) in &self.entries {
+        for (ext, entry) in self
+            .entries
+            .iter()
+            .flat_map(|(ext, claimants)| claimants.iter().map(move |entry| (ext, entry)))
+        {
             // Dev/test-only plugins route normally but are hidden from listings.
             if entry.internal {
                 continue;
@@ -139,38 +201,172 @@ impl PluginRegistry {
         result
     }
 
-    /// Attempts to generate tags for `extension` using a plugin.
+    /// Attempts to generate tags using the plugin named `plugin`.
     ///
     /// `local_instances` is the calling thread's per-thread instance cache.
-    /// On the first call for a given extension, the plugin is JIT-compiled (once, shared
+    /// On the first call for a given plugin, it is JIT-compiled (once, shared
     /// across threads) and a new `WasmInstance` is created for this thread.
     /// Returns `None` if no plugin is registered or if the plugin fails.
     pub fn try_generate(
         &self,
         local_instances: &mut HashMap<String, WasmInstance>,
-        extension: &str,
+        plugin: &str,
         source: &[u8],
         file_path: &str,
         absolute_path: &Path,
         config: &Config,
     ) -> Option<Vec<Tag>> {
-        let ep = self.ext_plugins.get(extension)?;
+        let (ep, instance) = self.instance_for(local_instances, plugin)?;
+        let req = self.request_for(ep, file_path, absolute_path, config);
+        let result = instance.generate(&req, source);
+        self.convert_result(ep, result, source, file_path)
+    }
 
-        let plugin_cache_dir: Option<PathBuf> = if self.cache_enabled_plugins.contains(&ep.name) {
-            self.project_cache_root
-                .as_ref()
-                .map(|root| root.join(&ep.name))
-        } else {
-            None
+    /// Generates tags for several files claimed by the plugin named `plugin`.
+    ///
+    /// Plugins exporting the optional `batch` interface receive all `files` in a
+    /// single `generate-batch` call; other plugins get one `generate` call per
+    /// file. A file whose generation fails contributes no tags.
+    pub fn try_generate_batch(
+        &self,
+        local_instances: &mut HashMap<String, WasmInstance>,
+        plugin: &str,
+        files: &[SourceFile<'_>],
+        config: &Config,
+    ) -> Vec<Tag> {
+        let Some((ep, instance)) = self.instance_for(local_instances, plugin) else {
+            return Vec::new();
         };
 
-        // Lazily JIT-compile the plugin the first time a file with this extension is seen.
-        // On failure the OnceLock stores None permanently — no retry, error printed once.
-        let shared = self
-            .compiled
+        if !instance.supports_batch() {
+            return files
+                .iter()
+                .filter_map(|f| {
+                    let req = self.request_for(ep, f.path, f.absolute_path, config);
+                    let result = instance.generate(&req, f.code);
+                    self.convert_result(ep, result, f.code, f.path)
+                })
+                .flatten()
+                .collect();
+        }
+
+        let batch: Vec<BatchFile> = files
+            .iter()
+            .map(|f| BatchFile {
+                req: self.request_for(ep, f.path, f.absolute_path, config),
+                source: f.code.to_vec(),
+            })
+            .collect();
+        match instance.generate_batch(&batch) {
+            Some(Ok(results)) if results.len() == files.len() => files
+                .iter()
+                .zip(results)
+                .filter_map(|(f, result)| self.convert_result(ep, Ok(result), f.code, f.path))
+                .flatten()
+                .collect(),
+            Some(Ok(results)) => {
+                eprintln!(
+                    "treetags: plugin error for {plugin}: batch returned {} results for {} files",
+                    results.len(),
+                    files.len()
+                );
+                Vec::new()
+            }
+            Some(Err(e)) => {
+                eprintln!("treetags: plugin call error for {plugin}: {e}");
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Generates tags with the declarative plugin named `plugin`, loading its
+    /// grammar and compiling its queries on first use (once, shared across
+    /// threads). Its kind filter is resolved from `config` on every call.
+    /// `ctx` is the calling thread's tags context, which must have a grammar
+    /// store attached via `attach_grammar_store`.
+    /// Returns `None` if no declarative plugin is registered or it fails to load.
+    pub fn try_generate_declarative(
+        &self,
+        ctx: &mut TagsContext,
+        plugin: &str,
+        source: &[u8],
+        file_path: &str,
+        config: &Config,
+    ) -> Option<Vec<Tag>> {
+        let ep = self.plugins.get(plugin)?;
+        let spec = ep.declarative.as_ref()?;
+        let grammar = self
+            .grammars
             .get(&ep.wasm_path)?
             .get_or_init(|| {
-                SharedPlugin::from_file(&self.engine, &ep.wasm_path)
+                self.grammar_runtime
+                    .load(spec, &ep.wasm_path)
+                    .map_err(|e| eprintln!("treetags: plugin load error for {}: {e}", ep.name))
+                    .ok()
+            })
+            .as_ref()?;
+        // Plugins sharing a grammar share its compiled query, so the kind
+        // filter comes from this plugin's manifest and the current config.
+        let kinds_str = ep
+            .language
+            .as_deref()
+            .map(|lang| config.get_kinds(lang))
+            .unwrap_or("");
+        let kinds = resolve_kinds(&spec.kind_map, &ep.kinds, kinds_str);
+        Some(grammar.generate(ctx, source, file_path, config, &kinds))
+    }
+
+    /// Attaches a tree-sitter WASM store to a worker thread's tags context so
+    /// it can run declarative plugin grammars. Returns false on failure.
+    pub fn attach_grammar_store(&self, ctx: &mut TagsContext) -> bool {
+        self.grammar_runtime
+            .attach_store(ctx)
+            .map_err(|e| eprintln!("treetags: {e}"))
+            .is_ok()
+    }
+
+    /// Scores `prefix`, the head of a file claimed by several languages, with
+    /// the `detect` export of the plugin named `plugin`. Returns `None` when the
+    /// plugin does not export `detect` or the call fails.
+    ///
+    /// Scoring runs on one instance per plugin shared by all threads, as it
+    /// only happens for ambiguous files.
+    pub fn score(&self, plugin: &str, prefix: &[u8]) -> Option<u32> {
+        let ep = self.plugins.get(plugin)?;
+        let detector = self
+            .detectors
+            .get(plugin)?
+            .get_or_init(|| {
+                let shared = self.shared(ep)?;
+                if !shared.exports.has_detect() {
+                    return None;
+                }
+                // Scoring needs no filesystem access, whatever the plugin was granted.
+                shared
+                    .create_instance(None, None)
+                    .map_err(|e| eprintln!("treetags: plugin init error for {plugin}: {e}"))
+                    .ok()
+                    .map(Mutex::new)
+            })
+            .as_ref()?;
+        let mut instance = detector.lock().unwrap_or_else(|e| e.into_inner());
+        match instance.score(prefix)? {
+            Ok(score) => Some(score),
+            Err(e) => {
+                eprintln!("treetags: plugin call error for {plugin}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Returns the compiled component of `ep`, JIT-compiling it on first use.
+    /// On failure the OnceLock stores None permanently — no retry, error printed once.
+    fn shared(&self, ep: &ExtPlugin) -> Option<&SharedPlugin> {
+        self.compiled
+            .get(&ep.wasm_path)?
+            .get_or_init(|| {
+                SharedPlugin::from_file(&self.engine, &ep.wasm_path, ep.abi_version)
                     .map_err(|e| {
                         eprintln!(
                             "treetags: plugin load error for {}: {e}",
@@ -179,46 +375,109 @@ impl PluginRegistry {
                     })
                     .ok()
             })
-            .as_ref()?;
+            .as_ref()
+    }
+
+    /// Looks up the plugin named `plugin` and returns it together with the
+    /// calling thread's instance, creating the instance on first use.
+    fn instance_for<'a>(
+        &'a self,
+        local_instances: &'a mut HashMap<String, WasmInstance>,
+        plugin: &str,
+    ) -> Option<(&'a ExtPlugin, &'a mut WasmInstance)> {
+        let ep = self.plugins.get(plugin)?;
+
+        // Lazily JIT-compile the plugin the first time a file it claims is seen.
+        let shared = self.shared(ep)?;
 
         // Lazy-create a per-thread WasmInstance from the now-compiled shared plugin.
-        // The cache dir is preopened as "." in the plugin's WASI sandbox.
-        let instance = match local_instances.entry(extension.to_string()) {
+        // The cache dir is preopened as "." and the project root (when granted)
+        // as `/project` in the plugin's WASI sandbox.
+        let instance = match local_instances.entry(plugin.to_string()) {
             Entry::Occupied(e) => e.into_mut(),
             Entry::Vacant(e) => {
                 let inst = shared
-                    .create_instance(plugin_cache_dir.as_deref())
-                    .map_err(|e| eprintln!("treetags: plugin init error for .{extension}: {e}"))
+                    .create_instance(
+                        self.plugin_cache_dir(ep).as_deref(),
+                        self.project_root_for(ep),
+                    )
+                    .map_err(|e| eprintln!("treetags: plugin init error for {plugin}: {e}"))
                     .ok()?;
                 e.insert(inst)
             }
         };
+        Some((ep, instance))
+    }
+
+    /// Records the project in its cache directory and trims every project's
+    /// caches to `max_size` bytes. Does nothing when no plugin has cache access.
+    pub fn prepare_cache(&self, max_size: Option<u64>) {
+        if let Some(dir) = &self.project_cache_root {
+            super::cache::prepare(dir, &self.project_root, max_size);
+        }
+    }
 
-        let language = ep.language.as_deref();
-        let kinds = language
+    fn plugin_cache_dir(&self, ep: &ExtPlugin) -> Option<PathBuf> {
+        if self.cache_enabled_plugins.contains(&ep.name) {
+            self.project_cache_root
+                .as_ref()
+                .map(|root| root.join(&ep.name))
+        } else {
+            None
+        }
+    }
+
+    fn project_root_for(&self, ep: &ExtPlugin) -> Option<&Path> {
+        self.project_read_plugins
+            .contains(&ep.name)
+            .then_some(self.project_root.as_path())
+    }
+
+    fn request_for(
+        &self,
+        ep: &ExtPlugin,
+        file_path: &str,
+        absolute_path: &Path,
+        config: &Config,
+    ) -> Request {
+        let kinds = ep
+            .language
+            .as_deref()
             .map(|lang| config.get_kinds(lang))
             .unwrap_or("")
             .to_string();
 
-        let cache_file = plugin_cache_dir
-            .as_ref()
-            .map(|_| cache_filename(absolute_path));
+        let cache_file = self
+            .cache_enabled_plugins
+            .contains(&ep.name)
+            .then(|| cache_filename(absolute_path));
 
-        let req = Request {
+        Request {
             file_path: file_path.to_string(),
             kinds,
             extras: config.extras.clone(),
             fields: config.fields.clone(),
             cache_file,
-        };
+            project_path: self
+                .project_root_for(ep)
+                .and_then(|root| project_relative_path(root, absolute_path)),
+        }
+    }
 
-        match instance.generate(&req, source) {
+    fn convert_result(
+        &self,
+        ep: &ExtPlugin,
+        result: anyhow::Result<Result<Vec<PluginTag>, String>>,
+        source: &[u8],
+        file_path: &str,
+    ) -> Option<Vec<Tag>> {
+        match result {
             Err(e) => {
-                eprintln!("treetags: plugin call error for .{extension}: {e}");
+                eprintln!("treetags: plugin call error for {}: {e}", ep.name);
                 None
             }
             Ok(Err(msg)) => {
-                eprintln!("treetags: plugin error for .{extension}: {msg}");
+                eprintln!("treetags: plugin error for {}: {msg}", ep.name);
                 None
             }
             Ok(Ok(plugin_tags)) => {
@@ -234,29 +493,30 @@ impl PluginRegistry {
     }
 }
 
-/// FNV-1a 64-bit hash — deterministic across runs, no new dependencies.
-fn fnv1a_64(bytes: &[u8]) -> u64 {
-    const BASIS: u64 = 14695981039346656037;
-    const PRIME: u64 = 1099511628211;
-    let mut h = BASIS;
-    for &b in bytes {
-        h ^= b as u64;
-        h = h.wrapping_mul(PRIME);
-    }
-    h
-}
-
 /// Returns the cache filename for a source file: a 16-hex-digit FNV-1a hash + ".cache".
 fn cache_filename(abs_path: &Path) -> String {
     format!(
         "{:016x}.cache",
-        fnv1a_64(abs_path.as_os_str().as_encoded_bytes())
+        super::cache::fnv1a_64(abs_path.as_os_str().as_encoded_bytes())
     )
 }
 
+/// Returns `abs_path` relative to the project root with `/` separators, or
+/// `None` when the file lives outside the project.
+fn project_relative_path(root: &Path, abs_path: &Path) -> Option<String> {
+    let rel = abs_path.strip_prefix(root).ok()?;
+    let parts: Vec<_> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Some(parts.join("/"))
+}
+
 /// Per-extension plugin metadata, returned without loading any WASM.
 pub struct PluginExtInfo {
     pub ext: String,
+    /// Name of the plugin, which routes files to it.
+    pub plugin: String,
     pub lang: Option<String>,
     pub aliases: Vec<String>,
     pub patterns: Vec<String>,
@@ -265,21 +525,27 @@ pub struct PluginExtInfo {
     /// Dev/test-only plugin: routes normally but is hidden from listings
     /// (`--list-plugins`, `--list-languages`).
     pub internal: bool,
+    /// Declarative (grammar + query) plugin rather than a component.
+    pub declarative: bool,
 }
 
 /// Scans plugin manifests (no WASM loading) and returns per-extension plugin info.
 /// Used by `LanguageParserRegistry` to build `WasmLanguageParser` stubs.
+/// Plugins sharing an extension are listed highest priority first.
 pub fn scan_ext_infos(dirs: &[PathBuf], plugins_dir: Option<&PathBuf>) -> Vec<PluginExtInfo> {
     scan_to_entries(dirs, plugins_dir)
         .into_iter()
+        .flat_map(|(ext, claimants)| claimants.into_iter().map(move |entry| (ext.clone(), entry)))
         .map(|(ext, entry)| PluginExtInfo {
             ext,
+            plugin: entry.name,
             lang: entry.language,
             aliases: entry.aliases,
             patterns: entry.patterns,
             interpreters: entry.interpreters,
             kinds: entry.kinds,
             internal: entry.internal,
+            declarative: entry.declarative.is_some(),
         })
         .collect()
 }
@@ -287,7 +553,7 @@ pub fn scan_ext_infos(dirs: &[PathBuf], plugins_dir: Option<&PathBuf>) -> Vec<Pl
 /// Prints a formatted table of all detected plugins to stdout.
 pub fn print_plugin_list(dirs: &[PathBuf], plugins_dir: &PathBuf) {
     println!("Plugin directory: {}", plugins_dir.display());
-    let registry = PluginRegistry::scan(dirs, Some(plugins_dir), &[]);
+    let registry = PluginRegistry::scan(dirs, Some(plugins_dir), &[], &[]);
     let plugins = registry.list_plugins();
     if plugins.is_empty() {
         println!("No plugins detected.");
@@ -317,22 +583,32 @@ pub fn scan_language_names(
 ) -> std::collections::HashSet<String> {
     scan_to_entries(dirs, plugins_dir)
         .into_values()
+        .flatten()
         .filter_map(|e| e.language)
         .collect()
 }
 
-/// Scans dirs for plugin.toml manifests and builds the extension→entry map.
+/// Scans dirs for plugin.toml manifests and builds the extension→claimants map.
 /// No WASM is loaded. This is the shared foundation for both `PluginRegistry::scan`
 /// and `scan_language_names`.
 fn scan_to_entries(
     dirs: &[PathBuf],
     recursive_dir: Option<&PathBuf>,
-) -> HashMap<String, PluginEntry> {
-    let mut entries: HashMap<String, PluginEntry> = HashMap::new();
+) -> HashMap<String, Vec<PluginEntry>> {
+    let mut entries: HashMap<String, Vec<PluginEntry>> = HashMap::new();
+    for manifest_path in manifest_paths(dirs, recursive_dir) {
+        load_manifest(&manifest_path, &mut entries);
+    }
+    entries
+}
 
-    // Scan the recursive default dir first so explicit --plugin-dir entries take precedence.
+/// Every `plugin.toml` found under `recursive_dir` and in `dirs`, in
+/// precedence order: a later manifest overrides an earlier one, so explicit
+/// `--plugin-dir` entries come last.
+pub(crate) fn manifest_paths(dirs: &[PathBuf], recursive_dir: Option<&PathBuf>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
     if let Some(r_dir) = recursive_dir {
-        scan_recursive(r_dir, &mut entries);
+        scan_recursive(r_dir, &mut paths);
     }
 
     for dir in dirs {
@@ -343,36 +619,37 @@ fn scan_to_entries(
                 for entry in read_dir.flatten() {
                     let sub = entry.path().join("plugin.toml");
                     if sub.exists() {
-                        load_manifest(&sub, &mut entries);
+                        paths.push(sub);
                     }
                 }
             }
         } else {
-            load_manifest(&manifest_path, &mut entries);
+            paths.push(manifest_path);
         }
     }
-
-    entries
+    paths
 }
 
-fn scan_recursive(dir: &Path, entries: &mut HashMap<String, PluginEntry>) {
+fn scan_recursive(dir: &Path, paths: &mut Vec<PathBuf>) {
     if let Ok(read_dir) = std::fs::read_dir(dir) {
         for entry in read_dir.flatten() {
             let path = entry.path();
             if path.is_dir() {
-                scan_recursive(&path, entries);
+                if !super::is_hidden(&path) {
+                    scan_recursive(&path, paths);
+                }
             } else if path
                 .file_name()
                 .map(|n| n == "plugin.toml")
                 .unwrap_or(false)
             {
-                load_manifest(&path, entries);
+                paths.push(path);
             }
         }
     }
 }
 
-fn load_manifest(manifest_path: &Path, entries: &mut HashMap<String, PluginEntry>) {
+fn load_manifest(manifest_path: &Path, entries: &mut HashMap<String, Vec<PluginEntry>>) {
     let dir = match manifest_path.parent() {
         Some(d) => d.to_path_buf(),
         None => return,
@@ -391,13 +668,17 @@ fn load_manifest(manifest_path: &Path, entries: &mut HashMap<String, PluginEntry
             return;
         }
     };
-    if manifest.abi_version != super::PLUGIN_ABI_VERSION {
+    // Post-processors handle no extensions; they run from the config file.
+    if manifest.post_processor {
+        return;
+    }
+    if !super::supports_abi(manifest.abi_version) {
         eprintln!(
             "treetags: plugin '{}' targets ABI version {}, \
-             but treetags supports ABI version {}",
+             but treetags supports ABI versions {:?}",
             manifest.name,
             manifest.abi_version,
-            super::PLUGIN_ABI_VERSION
+            super::SUPPORTED_ABI_VERSIONS
         );
         return;
     }
@@ -410,6 +691,15 @@ fn load_manifest(manifest_path: &Path, entries: &mut HashMap<String, PluginEntry
         );
         return;
     }
+    let declarative = DeclarativeSpec::from_manifest(&manifest, &dir).map(Arc::new);
+    if let Some(missing) = declarative.as_ref().and_then(|spec| spec.missing_file()) {
+        eprintln!(
+            "treetags: plugin {} query file '{}' not found",
+            manifest.name,
+            missing.display()
+        );
+        return;
+    }
     let language = manifest.language.clone();
     let aliases = manifest.aliases.clone();
     let patterns = manifest.patterns.clone();
@@ -417,9 +707,16 @@ fn load_manifest(manifest_path: &Path, entries: &mut HashMap<String, PluginEntry
     let name = manifest.name.clone();
     let kinds = manifest.kinds.clone().unwrap_or_default();
     let internal = manifest.internal;
+    let abi_version = manifest.abi_version;
+    // A later manifest for the same plugin replaces the earlier one, and takes
+    // precedence over other plugins claiming the same extensions.
+    for claimants in entries.values_mut() {
+        claimants.retain(|e| e.name != name);
+    }
+    entries.retain(|_, claimants| !claimants.is_empty());
     for ext in &manifest.extensions {
-        entries.insert(
-            ext.clone(),
+        entries.entry(ext.clone()).or_default().insert(
+            0,
             PluginEntry {
                 wasm_path: wasm_path.clone(),
                 language: language.clone(),
@@ -429,6 +726,8 @@ fn load_manifest(manifest_path: &Path, entries: &mut HashMap<String, PluginEntry
                 name: name.clone(),
                 kinds: kinds.clone(),
                 internal,
+                declarative: declarative.clone(),
+                abi_version,
             },
         );
     }
@@ -499,7 +798,7 @@ mod tests {
             r#"
 name = "plugin-a"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 extensions = ["a"]
 "#,
@@ -512,7 +811,7 @@ extensions = ["a"]
             r#"
 name = "plugin-b"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 extensions = ["b"]
 "#,
@@ -520,12 +819,49 @@ extensions = ["b"]
         .unwrap();
         fs::write(plugin_b.join("plugin.wasm"), "").unwrap();
 
-        let registry = PluginRegistry::scan(&[], Some(&dir.path().to_path_buf()), &[]);
+        let registry = PluginRegistry::scan(&[], Some(&dir.path().to_path_buf()), &[], &[]);
         assert!(registry.entries.contains_key("a"));
         assert!(registry.entries.contains_key("b"));
         assert_eq!(registry.entries.len(), 2);
     }
 
+    #[test]
+    fn test_plugins_share_extensions() {
+        let dir = tempdir().unwrap();
+        for (name, exts) in [("objc", r#"["m", "h"]"#), ("matlab", r#"["m"]"#)] {
+            let p = dir.path().join(name);
+            fs::create_dir_all(&p).unwrap();
+            fs::write(
+                p.join("plugin.toml"),
+                format!(
+                    "name = \"{name}\"\nversion = \"0.1.0\"\nabi_version = 4\nextensions = {exts}\n"
+                ),
+            )
+            .unwrap();
+            fs::write(p.join("plugin.wasm"), "").unwrap();
+        }
+        let dirs = [dir.path().join("objc"), dir.path().join("matlab")];
+
+        let registry = PluginRegistry::scan(&dirs, None, &[], &[]);
+        // The later manifest comes first among the plugins claiming `.m`.
+        let claimants: Vec<&str> = registry.entries["m"]
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(claimants, ["matlab", "objc"]);
+        assert_eq!(registry.entries["h"].len(), 1);
+        assert_eq!(registry.plugins.len(), 2);
+
+        // Rescanning a plugin replaces its earlier manifest.
+        let dirs = [dirs[0].clone(), dirs[1].clone(), dirs[0].clone()];
+        let registry = PluginRegistry::scan(&dirs, None, &[], &[]);
+        let claimants: Vec<&str> = registry.entries["m"]
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(claimants, ["objc", "matlab"]);
+    }
+
     #[test]
     fn test_list_plugins() {
         let dir = tempdir().unwrap();
@@ -537,7 +873,7 @@ extensions = ["b"]
             r#"
 name = "java-plugin"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 language = "java"
 extensions = ["java", "class"]
@@ -546,7 +882,7 @@ extensions = ["java", "class"]
         .unwrap();
         fs::write(plugin_java.join("plugin.wasm"), "").unwrap();
 
-        let registry = PluginRegistry::scan(&[], Some(&dir.path().to_path_buf()), &[]);
+        let registry = PluginRegistry::scan(&[], Some(&dir.path().to_path_buf()), &[], &[]);
         let plugins = registry.list_plugins();
         assert_eq!(plugins.len(), 1);
         assert_eq!(plugins[0].language, "java");
@@ -563,7 +899,7 @@ extensions = ["java", "class"]
             r#"
 name = "mylang-plugin"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 language = "mylang"
 aliases = ["ml2", "mylanguage"]
@@ -592,7 +928,7 @@ extensions = ["ml2"]
             r#"
 name = "mylang-plugin"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 language = "mylang"
 extensions = ["ml2"]
@@ -623,7 +959,7 @@ interpreters = ["mylang", "ml2run"]
             r#"
 name = "noalias"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 extensions = ["xyz"]
 "#,
@@ -648,7 +984,7 @@ extensions = ["xyz"]
             r#"
 name = "my-plugin"
 version = "0.1.0"
-abi_version = 3
+abi_version = 4
 wasm_file = "plugin.wasm"
 extensions = ["xyz"]
 "#,
@@ -656,9 +992,63 @@ extensions = ["xyz"]
         .unwrap();
         fs::write(dir.path().join("plugin.wasm"), "").unwrap();
 
-        let registry = PluginRegistry::scan(&[dir.path().to_path_buf()], None, &[]);
+        let registry = PluginRegistry::scan(&[dir.path().to_path_buf()], None, &[], &[]);
         let plugins = registry.list_plugins();
         assert_eq!(plugins.len(), 1);
         assert_eq!(plugins[0].language, "my-plugin");
     }
+
+    #[test]
+    fn test_declarative_manifest_parsed() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("plugin.toml"),
+            r#"
+name = "zig"
+version = "0.1.0"
+abi_version = 4
+language = "zig"
+extensions = ["zig"]
+grammar = "zig"
+locals_query = "locals.scm"
+
+[kind_map]
+function = "f"
+"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("plugin.wasm"), "").unwrap();
+        fs::write(dir.path().join("tags.scm"), "").unwrap();
+
+        // locals.scm is referenced but missing, so the plugin is skipped.
+        assert!(scan_ext_infos(&[dir.path().to_path_buf()], None).is_empty());
+
+        fs::write(dir.path().join("locals.scm"), "").unwrap();
+        let infos = scan_ext_infos(&[dir.path().to_path_buf()], None);
+        assert_eq!(infos.len(), 1);
+        assert!(infos[0].declarative);
+
+        let registry = PluginRegistry::scan(&[dir.path().to_path_buf()], None, &[], &[]);
+        let spec = registry.plugins["zig"].declarative.as_ref().unwrap();
+        assert_eq!(spec.grammar, "zig");
+        assert_eq!(spec.tags_path, dir.path().join("tags.scm"));
+        assert_eq!(spec.kind_map["function"], "f");
+        assert!(registry
+            .grammars
+            .contains_key(&dir.path().join("plugin.wasm")));
+        assert!(registry.compiled.is_empty());
+    }
+
+    #[test]
+    fn test_project_relative_path() {
+        let root = Path::new("/work/project");
+        assert_eq!(
+            project_relative_path(root, Path::new("/work/project/src/a/B.java")).as_deref(),
+            Some("src/a/B.java")
+        );
+        assert_eq!(
+            project_relative_path(root, Path::new("/elsewhere/B.java")),
+            None
+        );
+    }
 }
@@ -1,40 +1,50 @@
 use std::path::Path;
-use wasmtime::component::{Component, Linker};
+use wasmtime::component::{Component, InstancePre, Linker};
 use wasmtime::Engine;
 
-use super::instance::{new_store, PluginState, WasmInstance};
+use super::instance::{new_store, ExportIndices, PluginState, WasmInstance};
 
 /// The compiled component for one plugin, loaded exactly once.
 /// All worker threads share one `SharedPlugin` and each creates a
 /// per-thread `WasmInstance` from it via `create_instance()`.
 pub struct SharedPlugin {
     pub engine: Engine,
-    pub component: Component,
-    pub linker: Linker<PluginState>,
+    /// The component pre-linked against WASI, so per-thread instantiation
+    /// skips import resolution.
+    pub pre: InstancePre<PluginState>,
+    pub exports: ExportIndices,
 }
 
 impl SharedPlugin {
-    /// Loads and JIT-compiles the `.wasm` component file (done once).
-    pub fn from_file(engine: &Engine, path: &Path) -> anyhow::Result<Self> {
+    /// Loads and JIT-compiles the `.wasm` component file (done once), binding
+    /// the exports of the world for `abi_version`.
+    pub fn from_file(engine: &Engine, path: &Path, abi_version: u32) -> anyhow::Result<Self> {
         let engine = engine.clone();
         let component = Component::from_file(&engine, path)
             .map_err(|e| anyhow::anyhow!("load component {}: {e}", path.display()))?;
 
         let mut linker: Linker<PluginState> = Linker::new(&engine);
         wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+        let pre = linker.instantiate_pre(&component)?;
+        let exports = ExportIndices::new(&pre, abi_version)?;
 
         Ok(Self {
             engine,
-            component,
-            linker,
+            pre,
+            exports,
         })
     }
 
     /// Creates a new per-thread execution context (own linear memory, own call stack).
     /// The compiled component code is shared — no re-JIT per thread.
-    /// Pass `cache_dir` to preopen a directory for the plugin's cache files.
-    pub fn create_instance(&self, cache_dir: Option<&Path>) -> anyhow::Result<WasmInstance> {
-        let store = new_store(&self.engine, cache_dir);
-        WasmInstance::from_component(store, &self.component, &self.linker)
+    /// Pass `cache_dir` to preopen a directory for the plugin's cache files and
+    /// `project_root` to preopen the project read-only.
+    pub fn create_instance(
+        &self,
+        cache_dir: Option<&Path>,
+        project_root: Option<&Path>,
+    ) -> anyhow::Result<WasmInstance> {
+        let store = new_store(&self.engine, cache_dir, project_root);
+        WasmInstance::instantiate(store, &self.pre, &self.exports)
     }
 }
@@ -0,0 +1,215 @@
+//! Ed25519 detached signatures over the plugin index and its artifacts.
+//!
+//! `treetags-build-site --signing-key` signs the exact bytes of every
+//! `index.json` it writes (published next to it as `index.json.sig`) and of
+//! each `.wasm`, manifest and extra file (stored in the index entry). The
+//! client refuses to install or update unless the index and every downloaded
+//! artifact verify against one of the trusted public keys, or the user passes
+//! `--allow-unsigned-plugins`. With no trusted key at all (none compiled in
+//! and none configured) nothing verifies, so installs are refused too.
+//!
+//! Keys and signatures are lowercase hex: a signing key file holds the 32-byte
+//! secret seed, a public key is 32 bytes and a signature 64 bytes.
+
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::path::Path;
+
+/// Public keys trusted for the official plugin index, in hex. Always trusted in
+/// addition to keys from `--plugin-trusted-key` and [`TRUSTED_KEYS_ENV`].
+/// Empty until the official index is signed, so until then installs need a
+/// configured key or `--allow-unsigned-plugins`.
+pub const TRUSTED_KEYS: &[&str] = &[];
+
+/// Comma-separated hex public keys trusted in addition to [`TRUSTED_KEYS`].
+pub const TRUSTED_KEYS_ENV: &str = "TREETAGS_PLUGIN_TRUSTED_KEYS";
+
+/// Decides whether downloaded plugin data may be installed.
+pub struct TrustPolicy {
+    keys: Vec<VerifyingKey>,
+    allow_unsigned: bool,
+}
+
+impl TrustPolicy {
+    /// Trusts the compiled-in keys, those in [`TRUSTED_KEYS_ENV`] and
+    /// `extra_keys` (from `--plugin-trusted-key`).
+    pub fn new(extra_keys: &[String], allow_unsigned: bool) -> Result<Self> {
+        let env_keys = std::env::var(TRUSTED_KEYS_ENV).unwrap_or_default();
+        let configured: Vec<&str> = env_keys
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .chain(extra_keys.iter().map(String::as_str))
+            .collect();
+        Self::with_keys(TRUSTED_KEYS, &configured, allow_unsigned)
+    }
+
+    /// Trusts `builtin` (standing in for [`TRUSTED_KEYS`]) and `configured`.
+    pub(crate) fn with_keys(
+        builtin: &[&str],
+        configured: &[&str],
+        allow_unsigned: bool,
+    ) -> Result<Self> {
+        let keys = builtin
+            .iter()
+            .chain(configured)
+            .copied()
+            .map(parse_public_key)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            keys,
+            allow_unsigned,
+        })
+    }
+
+    /// Checks `signature` (hex, as published) over `data` against the trusted
+    /// keys. A missing or bad signature is an error unless unsigned installs
+    /// were allowed, in which case it is only reported.
+    pub fn verify(&self, what: &str, data: &[u8], signature: Option<&str>) -> Result<()> {
+        let problem = match signature {
+            None => format!("{what} is not signed"),
+            Some(sig) => match parse_signature(sig) {
+                Err(e) => format!("{what} has a malformed signature: {e}"),
+                Ok(_) if self.keys.is_empty() => {
+                    format!("{what} is signed but no trusted keys are configured")
+                }
+                Ok(sig) if self.keys.iter().any(|k| k.verify(data, &sig).is_ok()) => {
+                    return Ok(());
+                }
+                Ok(_) => format!("{what} signature does not match any trusted key"),
+            },
+        };
+        if self.allow_unsigned {
+            eprintln!("warning: {problem}; installing anyway (--allow-unsigned-plugins)");
+            return Ok(());
+        }
+        bail!(
+            "{problem}\n\
+             Trust the publisher's key with `--plugin-trusted-key` or `${TRUSTED_KEYS_ENV}`, \
+             or pass `--allow-unsigned-plugins` to skip verification."
+        )
+    }
+}
+
+/// Signs `data`, returning the hex signature stored in the index.
+pub fn sign_hex(key: &SigningKey, data: &[u8]) -> String {
+    to_hex(&key.sign(data).to_bytes())
+}
+
+/// Hex public key matching `key`, for `--plugin-trusted-key`.
+pub fn public_key_hex(key: &SigningKey) -> String {
+    to_hex(key.verifying_key().as_bytes())
+}
+
+/// Generates a fresh signing key from the OS random source.
+pub fn generate_signing_key() -> Result<SigningKey> {
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed).map_err(|e| anyhow!("cannot gather randomness: {e}"))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Reads a signing key file holding the hex secret seed.
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read signing key {}", path.display()))?;
+    let seed = from_hex::<32>(text.trim())
+        .with_context(|| format!("invalid signing key in {}", path.display()))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Writes `key` as a signing key file readable only by the owner.
+pub fn write_signing_key(path: &Path, key: &SigningKey) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("cannot create {}", path.display()))?;
+    std::io::Write::write_all(
+        &mut file,
+        format!("{}\n", to_hex(&key.to_bytes())).as_bytes(),
+    )
+    .with_context(|| format!("cannot write {}", path.display()))
+}
+
+fn parse_public_key(hex: &str) -> Result<VerifyingKey> {
+    let bytes = from_hex::<32>(hex).with_context(|| format!("invalid trusted key `{hex}`"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("invalid trusted key `{hex}`: {e}"))
+}
+
+fn parse_signature(hex: &str) -> Result<Signature> {
+    Ok(Signature::from_bytes(&from_hex::<64>(hex.trim())?))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex<const N: usize>(hex: &str) -> Result<[u8; N]> {
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        bail!("expected {} hex digits, got {}", N * 2, hex.len());
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| anyhow!("`{hex}` is not hex"))?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn policy(allow_unsigned: bool) -> TrustPolicy {
+        TrustPolicy::with_keys(&[&public_key_hex(&key())], &[], allow_unsigned).unwrap()
+    }
+
+    #[test]
+    fn signature_roundtrip_verifies() {
+        let sig = sign_hex(&key(), b"index");
+        assert!(policy(false).verify("index", b"index", Some(&sig)).is_ok());
+    }
+
+    #[test]
+    fn tampered_data_is_rejected() {
+        let sig = sign_hex(&key(), b"index");
+        assert!(policy(false).verify("index", b"indeX", Some(&sig)).is_err());
+    }
+
+    #[test]
+    fn untrusted_key_is_rejected() {
+        let other = SigningKey::from_bytes(&[9u8; 32]);
+        let sig = sign_hex(&other, b"index");
+        assert!(policy(false).verify("index", b"index", Some(&sig)).is_err());
+    }
+
+    #[test]
+    fn unsigned_requires_override() {
+        assert!(policy(false).verify("index", b"index", None).is_err());
+        assert!(policy(true).verify("index", b"index", None).is_ok());
+    }
+
+    #[test]
+    fn no_trusted_keys_rejects_unsigned_and_tampered() {
+        let policy = TrustPolicy::with_keys(&[], &[], false).unwrap();
+        assert!(policy.verify("index", b"index", None).is_err());
+        let sig = sign_hex(&key(), b"index");
+        assert!(policy.verify("index", b"indeX", Some(&sig)).is_err());
+    }
+
+    #[test]
+    fn public_key_hex_parses_back() {
+        let parsed = parse_public_key(&public_key_hex(&key())).unwrap();
+        assert_eq!(parsed, key().verifying_key());
+        assert!(parse_public_key("abcd").is_err());
+    }
+}
@@ -0,0 +1,540 @@
+//! Export of tags as a [SCIP](https://github.com/sourcegraph/scip) index for
+//! code intelligence platforms.
+//!
+//! Every definition becomes a global symbol named after its language, scope
+//! chain and name, e.g. `treetags . rust . shapes/Circle#area().`, with a
+//! definition occurrence at the tag's name and an enclosing range from its
+//! `end` field. Reference tags (a `roles` field without `def`) become
+//! reference occurrences of the definition with the same name. The comment
+//! block above a definition, or a Python docstring below it, is kept as its
+//! documentation.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use prost::Message;
+
+use crate::tag_record::{Scope, TagRecord};
+
+/// The subset of `scip.proto` written by treetags.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Index {
+        #[prost(message, optional, tag = "1")]
+        pub metadata: Option<Metadata>,
+        #[prost(message, repeated, tag = "2")]
+        pub documents: Vec<Document>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Metadata {
+        /// `ProtocolVersion`; 0 is the only version.
+        #[prost(int32, tag = "1")]
+        pub version: i32,
+        #[prost(message, optional, tag = "2")]
+        pub tool_info: Option<ToolInfo>,
+        #[prost(string, tag = "3")]
+        pub project_root: String,
+        /// `TextEncoding`: 1 is UTF-8.
+        #[prost(int32, tag = "4")]
+        pub text_document_encoding: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ToolInfo {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+        #[prost(string, repeated, tag = "3")]
+        pub arguments: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Document {
+        #[prost(string, tag = "1")]
+        pub relative_path: String,
+        #[prost(message, repeated, tag = "2")]
+        pub occurrences: Vec<Occurrence>,
+        #[prost(message, repeated, tag = "3")]
+        pub symbols: Vec<SymbolInformation>,
+        #[prost(string, tag = "4")]
+        pub language: String,
+        /// `PositionEncoding`: 1 is UTF-8 code unit offsets.
+        #[prost(int32, tag = "6")]
+        pub position_encoding: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Occurrence {
+        /// `[line, start, end]` or `[start line, start, end line, end]`.
+        #[prost(int32, repeated, tag = "1")]
+        pub range: Vec<i32>,
+        #[prost(string, tag = "2")]
+        pub symbol: String,
+        /// Bit set of `SymbolRole`.
+        #[prost(int32, tag = "3")]
+        pub symbol_roles: i32,
+        #[prost(int32, repeated, tag = "7")]
+        pub enclosing_range: Vec<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolInformation {
+        #[prost(string, tag = "1")]
+        pub symbol: String,
+        #[prost(string, repeated, tag = "3")]
+        pub documentation: Vec<String>,
+        /// `SymbolInformation.Kind`.
+        #[prost(int32, tag = "5")]
+        pub kind: i32,
+        #[prost(string, tag = "6")]
+        pub display_name: String,
+        #[prost(string, tag = "8")]
+        pub enclosing_symbol: String,
+    }
+}
+
+/// `SymbolRole.Definition`.
+const ROLE_DEFINITION: i32 = 0x1;
+/// `TextEncoding.UTF8` and `PositionEncoding.UTF8CodeUnitOffsetFromLineStart`.
+const UTF8: i32 = 1;
+
+/// How a kind of tag is written: its SCIP descriptor suffix and
+/// `SymbolInformation.Kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Descriptor {
+    Namespace(i32),
+    Type(i32),
+    Method(i32),
+    Term(i32),
+}
+
+impl Descriptor {
+    fn of(kind: &str) -> Self {
+        let kind = kind.to_ascii_lowercase();
+        let has = |names: &[&str]| names.iter().any(|name| kind.contains(name));
+        // SymbolInformation.Kind values.
+        if has(&["namespace"]) {
+            Descriptor::Namespace(30)
+        } else if has(&["package"]) {
+            Descriptor::Namespace(35)
+        } else if has(&["module", "header"]) || kind == "n" {
+            Descriptor::Namespace(29)
+        } else if has(&["method"]) {
+            Descriptor::Method(26)
+        } else if has(&["constructor"]) {
+            Descriptor::Method(9)
+        } else if has(&["function", "prototype", "subroutine", "generator"]) || kind == "f" {
+            Descriptor::Method(17)
+        } else if has(&["macro"]) {
+            Descriptor::Term(25)
+        } else if has(&["enumerator", "enumarator", "variant"]) || kind == "e" {
+            Descriptor::Term(12)
+        } else if has(&["enum"]) || kind == "g" {
+            Descriptor::Type(11)
+        } else if has(&["trait"]) {
+            Descriptor::Type(53)
+        } else if has(&["interface", "protocol"]) || kind == "i" {
+            Descriptor::Type(21)
+        } else if has(&["union"]) {
+            Descriptor::Type(59)
+        } else if has(&["struct", "record"]) || kind == "s" {
+            Descriptor::Type(49)
+        } else if has(&["field", "member"]) || kind == "m" {
+            Descriptor::Term(15)
+        } else if has(&["class", "impl", "object"]) || kind == "c" {
+            Descriptor::Type(7)
+        } else if has(&["typedef", "alias"]) {
+            Descriptor::Type(55)
+        } else if has(&["type"]) || kind == "t" {
+            Descriptor::Type(54)
+        } else if has(&["propert"]) {
+            Descriptor::Term(41)
+        } else if has(&["constant", "define"]) {
+            Descriptor::Term(8)
+        } else {
+            Descriptor::Term(61)
+        }
+    }
+
+    fn kind(self) -> i32 {
+        match self {
+            Descriptor::Namespace(kind)
+            | Descriptor::Type(kind)
+            | Descriptor::Method(kind)
+            | Descriptor::Term(kind) => kind,
+        }
+    }
+
+    fn write(self, name: &str, out: &mut String) {
+        escape_into(name, out);
+        out.push_str(match self {
+            Descriptor::Namespace(_) => "/",
+            Descriptor::Type(_) => "#",
+            Descriptor::Method(_) => "().",
+            Descriptor::Term(_) => ".",
+        });
+    }
+}
+
+/// Writes a descriptor name, backtick-quoting it unless it is a simple
+/// identifier.
+fn escape_into(name: &str, out: &mut String) {
+    let simple = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '$'));
+    if simple {
+        out.push_str(name);
+    } else {
+        out.push('`');
+        out.push_str(&name.replace('`', "``"));
+        out.push('`');
+    }
+}
+
+/// `treetags . <language> . ` — the scheme and package part of every symbol.
+fn symbol_prefix(language: Option<&str>) -> String {
+    let language = language.map(str::to_ascii_lowercase);
+    match language.as_deref() {
+        Some(language) if !language.is_empty() && !language.contains(' ') => {
+            format!("treetags . {language} . ")
+        }
+        _ => "treetags . . . ".to_string(),
+    }
+}
+
+/// Descriptors of the scopes enclosing a tag, outermost first.
+fn scope_descriptors(scope: &Scope, out: &mut String) {
+    let descriptor = match Descriptor::of(&scope.kind) {
+        namespace @ Descriptor::Namespace(_) => namespace,
+        _ => Descriptor::Type(0),
+    };
+    for part in scope
+        .name
+        .split(['.', ':', '/', '\\'])
+        .filter(|part| !part.is_empty())
+    {
+        descriptor.write(part, out);
+    }
+}
+
+fn is_reference(record: &TagRecord) -> bool {
+    record
+        .field("roles")
+        .is_some_and(|roles| !roles.split(',').any(|role| role == "def"))
+}
+
+/// The symbol of a definition tag, and that of its enclosing scope.
+fn symbol(record: &TagRecord) -> (String, Option<String>) {
+    let prefix = symbol_prefix(record.language.as_deref());
+    let mut enclosing = prefix.clone();
+    if let Some(scope) = &record.scope {
+        scope_descriptors(scope, &mut enclosing);
+    }
+    let mut symbol = enclosing.clone();
+    let kind = record.kind_name.as_deref().or(record.kind.as_deref());
+    Descriptor::of(kind.unwrap_or("")).write(&record.name, &mut symbol);
+    let enclosing = (enclosing.len() > prefix.len()).then_some(enclosing);
+    (symbol, enclosing)
+}
+
+/// Text of a comment line with its marker removed, or `None` when the line is
+/// not a comment.
+fn comment_text(line: &str) -> Option<&str> {
+    const MARKERS: &[&str] = &[
+        "///", "//!", "//", "/**", "/*", "*/", "*", "#", "--", ";;", ";",
+    ];
+    let marker = MARKERS.iter().find(|marker| line.starts_with(**marker))?;
+    let text = &line[marker.len()..];
+    if *marker == "#" && !(text.is_empty() || text.starts_with([' ', '#'])) {
+        // `#include`, `#define`, `#!`, ...
+        return None;
+    }
+    Some(text.strip_suffix("*/").unwrap_or(text).trim())
+}
+
+/// The comment block right above line `row`, skipping attributes and
+/// decorators, or else a docstring on the line below it.
+fn documentation(lines: &[&str], row: usize) -> Option<String> {
+    let mut docs = Vec::new();
+    for line in lines[..row.min(lines.len())].iter().rev() {
+        let line = line.trim();
+        if line.starts_with("#[") || line.starts_with('@') {
+            if docs.is_empty() {
+                continue;
+            }
+            break;
+        }
+        match comment_text(line) {
+            Some(text) => docs.push(text),
+            None => break,
+        }
+    }
+    docs.reverse();
+    let docs: Vec<&str> = docs
+        .into_iter()
+        .skip_while(|line| line.is_empty())
+        .collect();
+    if !docs.is_empty() {
+        return Some(docs.join("\n").trim_end().to_string());
+    }
+    docstring(&lines[(row + 1).min(lines.len())..])
+}
+
+fn docstring(lines: &[&str]) -> Option<String> {
+    let first = lines.first()?.trim();
+    let quote = ["\"\"\"", "'''"]
+        .into_iter()
+        .find(|quote| first.starts_with(quote))?;
+    let first = &first[quote.len()..];
+    if let Some(end) = first.find(quote) {
+        return Some(first[..end].trim().to_string());
+    }
+    let mut docs = vec![first.trim()];
+    for line in &lines[1..] {
+        let line = line.trim();
+        if let Some(end) = line.find(quote) {
+            docs.push(&line[..end]);
+            break;
+        }
+        docs.push(line);
+    }
+    Some(docs.join("\n").trim().to_string())
+}
+
+/// `[line, start, end]` of the tag's name on its line, in bytes.
+fn name_range(record: &TagRecord, lines: &[&str]) -> Vec<i32> {
+    let row = record.line.unwrap_or(1).saturating_sub(1) as usize;
+    let line = lines.get(row).copied().unwrap_or("");
+    let start = line.find(&record.name).unwrap_or(0);
+    let end = if line.is_empty() {
+        0
+    } else {
+        start + record.name.len()
+    };
+    vec![row as i32, start as i32, end as i32]
+}
+
+/// `[start line, 0, end line, end]` spanning the definition, when it has an
+/// `end` past its first line.
+fn enclosing_range(record: &TagRecord, lines: &[&str]) -> Vec<i32> {
+    match (record.line, record.end_line) {
+        (Some(line), Some(end)) if end > line => {
+            let end_row = end as usize - 1;
+            let end_col = lines.get(end_row).map_or(0, |line| line.len());
+            vec![line as i32 - 1, 0, end_row as i32, end_col as i32]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Builds the SCIP index of `records`, reading each tagged file under
+/// `project_root` for name positions and documentation.
+fn build_index(records: &[TagRecord], project_root: &Path) -> proto::Index {
+    let mut by_file: BTreeMap<&str, Vec<&TagRecord>> = BTreeMap::new();
+    for record in records {
+        by_file.entry(&record.path).or_default().push(record);
+    }
+    let mut definitions: HashMap<&str, String> = HashMap::new();
+    for record in records.iter().filter(|r| !is_reference(r)) {
+        definitions
+            .entry(&record.name)
+            .or_insert_with(|| symbol(record).0);
+    }
+
+    let documents = by_file
+        .into_iter()
+        .map(|(path, records)| {
+            let text = fs::read_to_string(project_root.join(path)).unwrap_or_default();
+            let lines: Vec<&str> = text.lines().collect();
+            let mut document = proto::Document {
+                relative_path: path.to_string(),
+                language: records
+                    .iter()
+                    .find_map(|r| r.language.clone())
+                    .unwrap_or_default(),
+                position_encoding: UTF8,
+                ..proto::Document::default()
+            };
+            let mut described = HashSet::new();
+            for record in records {
+                if is_reference(record) {
+                    let symbol = definitions
+                        .get(record.name.as_str())
+                        .cloned()
+                        .unwrap_or_else(|| symbol(record).0);
+                    document.occurrences.push(proto::Occurrence {
+                        range: name_range(record, &lines),
+                        symbol,
+                        ..proto::Occurrence::default()
+                    });
+                    continue;
+                }
+                let (symbol, enclosing_symbol) = symbol(record);
+                document.occurrences.push(proto::Occurrence {
+                    range: name_range(record, &lines),
+                    symbol: symbol.clone(),
+                    symbol_roles: ROLE_DEFINITION,
+                    enclosing_range: enclosing_range(record, &lines),
+                });
+                if described.insert(symbol.clone()) {
+                    let row = record.line.unwrap_or(1).saturating_sub(1) as usize;
+                    let kind = record.kind_name.as_deref().or(record.kind.as_deref());
+                    document.symbols.push(proto::SymbolInformation {
+                        symbol,
+                        documentation: documentation(&lines, row).into_iter().collect(),
+                        kind: Descriptor::of(kind.unwrap_or("")).kind(),
+                        display_name: record.name.clone(),
+                        enclosing_symbol: enclosing_symbol.unwrap_or_default(),
+                    });
+                }
+            }
+            document
+        })
+        .collect();
+
+    let root = fs::canonicalize(project_root).unwrap_or_else(|_| project_root.to_path_buf());
+    proto::Index {
+        metadata: Some(proto::Metadata {
+            version: 0,
+            tool_info: Some(proto::ToolInfo {
+                name: "treetags".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                arguments: std::env::args().skip(1).collect(),
+            }),
+            project_root: format!("file://{}", root.display()),
+            text_document_encoding: UTF8,
+        }),
+        documents,
+    }
+}
+
+/// Encodes the SCIP index of `records`, whose paths are relative to
+/// `project_root`.
+pub fn encode_index(records: &[TagRecord], project_root: &Path) -> Vec<u8> {
+    build_index(records, project_root).encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn record(name: &str, kind: &str, line: u32, end: Option<u32>) -> TagRecord {
+        let mut record = TagRecord::new(name.to_string(), "shapes.rs");
+        record.kind_name = Some(kind.to_string());
+        record.language = Some("Rust".to_string());
+        record.line = Some(line);
+        record.end_line = end;
+        record
+    }
+
+    #[test]
+    fn test_symbols_from_language_scope_and_name() {
+        let mut area = record("area", "method", 3, Some(5));
+        area.scope = Some(Scope::new("implementation", "shapes::Circle"));
+        assert_eq!(
+            symbol(&area),
+            (
+                "treetags . rust . shapes#Circle#area().".to_string(),
+                Some("treetags . rust . shapes#Circle#".to_string())
+            )
+        );
+
+        let mut inner = record("inner", "module", 1, None);
+        inner.scope = Some(Scope::new("module", "outer"));
+        assert_eq!(symbol(&inner).0, "treetags . rust . outer/inner/");
+
+        let mut operator = record("operator+=", "function", 1, None);
+        operator.language = None;
+        assert_eq!(symbol(&operator).0, "treetags . . . `operator+=`().");
+        assert_eq!(
+            symbol(&record("MAX", "constant", 1, None)).0,
+            "treetags . rust . MAX."
+        );
+    }
+
+    #[test]
+    fn test_documentation() {
+        let source = [
+            "use std::fmt;",
+            "",
+            "/// A circle.",
+            "///",
+            "/// Centered on the origin.",
+            "#[derive(Debug)]",
+            "struct Circle;",
+            "def area(self):",
+            "    \"\"\"Area of the",
+            "    circle.\"\"\"",
+            "#define UNDOCUMENTED 1",
+            "fn undocumented() {}",
+        ];
+        assert_eq!(
+            documentation(&source, 6).as_deref(),
+            Some("A circle.\n\nCentered on the origin.")
+        );
+        assert_eq!(
+            documentation(&source, 7).as_deref(),
+            Some("Area of the\ncircle.")
+        );
+        assert_eq!(documentation(&source, 11), None);
+        assert_eq!(documentation(&source, 0), None);
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = fs::File::create(dir.path().join("shapes.rs")).unwrap();
+        file.write_all(
+            b"// Unit shapes.\nmod shapes {\n    fn area() {}\n}\nfn main() { area(); }\n",
+        )
+        .unwrap();
+
+        let mut area = record("area", "function", 3, Some(3));
+        area.scope = Some(Scope::new("module", "shapes"));
+        let mut call = record("area", "function", 5, None);
+        call.fields.push(("roles".to_string(), "call".to_string()));
+        let records = vec![record("shapes", "module", 2, Some(4)), area, call];
+
+        let bytes = encode_index(&records, dir.path());
+        let index = proto::Index::decode(bytes.as_slice()).unwrap();
+        let metadata = index.metadata.unwrap();
+        assert_eq!(metadata.tool_info.unwrap().name, "treetags");
+        assert!(metadata.project_root.starts_with("file:///"));
+
+        let document = &index.documents[0];
+        assert_eq!(document.relative_path, "shapes.rs");
+        assert_eq!(document.language, "Rust");
+        let occurrences: Vec<_> = document
+            .occurrences
+            .iter()
+            .map(|o| (o.symbol.as_str(), o.range.clone(), o.symbol_roles))
+            .collect();
+        assert_eq!(
+            occurrences,
+            [
+                ("treetags . rust . shapes/", vec![1, 4, 10], ROLE_DEFINITION),
+                (
+                    "treetags . rust . shapes/area().",
+                    vec![2, 7, 11],
+                    ROLE_DEFINITION
+                ),
+                ("treetags . rust . shapes/area().", vec![4, 12, 16], 0),
+            ]
+        );
+        assert_eq!(document.occurrences[0].enclosing_range, [1, 0, 3, 1]);
+
+        let shapes = &document.symbols[0];
+        assert_eq!(shapes.documentation, ["Unit shapes."]);
+        assert_eq!(shapes.kind, 29);
+        assert_eq!(
+            document.symbols[1].enclosing_symbol,
+            "treetags . rust . shapes/"
+        );
+        assert_eq!(document.symbols.len(), 2);
+    }
+}
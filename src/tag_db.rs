@@ -0,0 +1,428 @@
+//! `--output-format=sqlite`: tags kept in a SQLite database for dashboards and
+//! ad-hoc SQL, updated one file at a time.
+//!
+//! The schema (`PRAGMA user_version` 1) is normalized into:
+//!
+//! - `files(id, path, language, mtime, hash)`: one row per tagged file. `path`
+//!   is written as in a tags file next to the database, `mtime` is in
+//!   nanoseconds since the epoch and `hash` is the SHA-256 of the content.
+//! - `symbols(id, file_id, name, kind, kind_name, line, column, end_line,
+//!   scope_kind, scope_name, pattern, pattern_truncated)`: one row per tag.
+//! - `fields(symbol_id, key, value)`: the remaining extension fields.
+//! - `meta(key, value)`: the options the tags were generated with.
+//!
+//! Deleting a file row deletes its symbols and their fields.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, Context};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::plugin::index::sha256_hex;
+use crate::tag_lookup::{TagMatch, TagQuery};
+use crate::tag_record::{Scope, TagRecord};
+
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    language TEXT,
+    mtime INTEGER,
+    hash TEXT
+);
+CREATE TABLE symbols (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    kind TEXT,
+    kind_name TEXT,
+    line INTEGER,
+    column INTEGER,
+    end_line INTEGER,
+    scope_kind TEXT,
+    scope_name TEXT,
+    pattern TEXT,
+    pattern_truncated INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX symbols_name ON symbols(name);
+CREATE INDEX symbols_file ON symbols(file_id);
+CREATE TABLE fields (
+    symbol_id INTEGER NOT NULL REFERENCES symbols(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE INDEX fields_symbol ON fields(symbol_id);
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// First bytes of every SQLite database file.
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// Whether the file at `path` is a SQLite database rather than a tags file.
+pub fn is_database(path: &Path) -> bool {
+    let mut magic = [0; 16];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| &magic == SQLITE_MAGIC)
+}
+
+/// What a file looked like when it was tagged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileState {
+    /// Modification time in nanoseconds since the epoch, when known.
+    pub mtime: Option<i64>,
+    /// Lowercase hex SHA-256 of the content.
+    pub hash: String,
+}
+
+/// A tagged file to store with [`TagDatabase::update`].
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    /// Path as the tags name it.
+    pub path: String,
+    pub language: Option<String>,
+    pub state: FileState,
+}
+
+pub struct TagDatabase {
+    conn: Connection,
+}
+
+impl TagDatabase {
+    /// Opens the database at `path`, creating it when missing.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("cannot open database {}", path.display()))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        match version {
+            0 => {
+                conn.execute_batch(SCHEMA)?;
+                conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            }
+            SCHEMA_VERSION => {}
+            _ => bail!(
+                "{} has schema version {version}, newer than this treetags supports",
+                path.display()
+            ),
+        }
+        Ok(Self { conn })
+    }
+
+    /// Records the options tags are generated with. When they differ from the
+    /// stored ones every file is treated as changed, so the next update
+    /// retags all of them.
+    pub fn set_options(&self, options: &str) -> anyhow::Result<()> {
+        let stored: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'options'", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        if stored.as_deref() != Some(options) {
+            self.conn.execute("UPDATE files SET hash = NULL", [])?;
+            self.conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('options', ?1)",
+                [options],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Compares `file` with what was stored for `path`. Returns `None` when its
+    /// tags are current, otherwise the state to store after retagging it.
+    ///
+    /// An unchanged modification time is trusted; when only the modification
+    /// time changed the stored one is refreshed and the tags kept.
+    pub fn check_file(&self, path: &str, file: &Path) -> anyhow::Result<Option<FileState>> {
+        let mtime = fs::metadata(file)
+            .with_context(|| format!("cannot read {}", file.display()))?
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .and_then(|since| i64::try_from(since.as_nanos()).ok());
+        let stored: Option<(Option<i64>, Option<String>)> = self
+            .conn
+            .query_row(
+                "SELECT mtime, hash FROM files WHERE path = ?1",
+                [path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let stored_hash = match stored {
+            Some((stored_mtime, Some(hash))) => {
+                if mtime.is_some() && stored_mtime == mtime {
+                    return Ok(None);
+                }
+                Some(hash)
+            }
+            _ => None,
+        };
+
+        let content = fs::read(file).with_context(|| format!("cannot read {}", file.display()))?;
+        let hash = sha256_hex(&content);
+        if stored_hash.as_deref() == Some(hash.as_str()) {
+            self.conn.execute(
+                "UPDATE files SET mtime = ?1 WHERE path = ?2",
+                params![mtime, path],
+            )?;
+            return Ok(None);
+        }
+        Ok(Some(FileState { mtime, hash }))
+    }
+
+    /// Replaces the tags of `files` with `records`, in one transaction.
+    /// Stored files missing from `files` are dropped unless `keep` returns
+    /// true for their path. Records of files not in `files` are ignored.
+    pub fn update(
+        &mut self,
+        files: &[FileEntry],
+        records: &[TagRecord],
+        keep: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<()> {
+        let tx = self.conn.transaction()?;
+        let stored: Vec<String> = {
+            let mut stmt = tx.prepare("SELECT path FROM files")?;
+            let paths = stmt.query_map([], |row| row.get(0))?;
+            paths.collect::<Result<_, _>>()?
+        };
+        let updated: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        for path in stored {
+            if updated.contains(path.as_str()) || !keep(&path) {
+                tx.execute("DELETE FROM files WHERE path = ?1", [&path])?;
+            }
+        }
+
+        let mut file_ids = HashMap::new();
+        {
+            let mut insert_file = tx.prepare(
+                "INSERT INTO files (path, language, mtime, hash) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            let mut insert_symbol = tx.prepare(
+                "INSERT INTO symbols (file_id, name, kind, kind_name, line, column, end_line,
+                     scope_kind, scope_name, pattern, pattern_truncated)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            let mut insert_field =
+                tx.prepare("INSERT INTO fields (symbol_id, key, value) VALUES (?1, ?2, ?3)")?;
+
+            for file in files {
+                insert_file.execute(params![
+                    file.path,
+                    file.language,
+                    file.state.mtime,
+                    file.state.hash
+                ])?;
+                file_ids.insert(file.path.as_str(), tx.last_insert_rowid());
+            }
+            for record in records {
+                let Some(file_id) = file_ids.get(record.path.as_str()) else {
+                    continue;
+                };
+                let scope = record.scope.as_ref();
+                insert_symbol.execute(params![
+                    file_id,
+                    record.name,
+                    record.kind,
+                    record.kind_name,
+                    record.line,
+                    record.column,
+                    record.end_line,
+                    scope.map(|scope| &scope.kind),
+                    scope.map(|scope| &scope.name),
+                    record.pattern,
+                    record.pattern_truncated,
+                ])?;
+                let symbol_id = tx.last_insert_rowid();
+                for (key, value) in &record.fields {
+                    insert_field.execute(params![symbol_id, key, value])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Looks up `query` like [`crate::tag_lookup::lookup`] does in a tags
+    /// file. Matches are ordered by name, path and line, and their `line` is
+    /// the tags file line they would be written as.
+    pub fn lookup(&self, query: &TagQuery) -> anyhow::Result<Vec<TagMatch>> {
+        let name = if query.prefix {
+            "substr(s.name, 1, length(?1)) = ?1"
+        } else {
+            "s.name = ?1"
+        };
+        let collate = if query.ignore_case {
+            " COLLATE NOCASE"
+        } else {
+            ""
+        };
+        let sql = format!(
+            "SELECT s.id, s.name, f.path, f.language, s.kind, s.kind_name, s.line, s.column,
+                 s.end_line, s.scope_kind, s.scope_name, s.pattern, s.pattern_truncated
+             FROM symbols s JOIN files f ON f.id = s.file_id
+             WHERE {name}{collate}
+             ORDER BY s.name, f.path, s.line"
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut fields_stmt = self
+            .conn
+            .prepare("SELECT key, value FROM fields WHERE symbol_id = ?1 ORDER BY rowid")?;
+        let mut rows = stmt.query([&query.name])?;
+        let mut matches = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut record = TagRecord::new(row.get::<_, String>(1)?, row.get::<_, String>(2)?);
+            record.language = row.get(3)?;
+            record.kind = row.get(4)?;
+            record.kind_name = row.get(5)?;
+            record.line = row.get(6)?;
+            record.column = row.get(7)?;
+            record.end_line = row.get(8)?;
+            if let (Some(kind), Some(name)) = (
+                row.get::<_, Option<String>>(9)?,
+                row.get::<_, Option<String>>(10)?,
+            ) {
+                record.scope = Some(Scope::new(kind, name));
+            }
+            record.pattern = row.get(11)?;
+            record.pattern_truncated = row.get(12)?;
+            record.fields = fields_stmt
+                .query_map([row.get::<_, i64>(0)?], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<_, _>>()?;
+
+            if query.filter.matches(&record) {
+                // A tags file does not carry the language of each tag.
+                let line = TagRecord {
+                    language: None,
+                    ..record.clone()
+                }
+                .to_ctags_line();
+                matches.push(TagMatch { line, record });
+            }
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, path: &str, line: u32) -> TagRecord {
+        let mut record = TagRecord::new(name, path);
+        record.line = Some(line);
+        record.kind = Some("f".to_string());
+        record.kind_name = Some("function".to_string());
+        record
+    }
+
+    fn entry(path: &str, hash: &str) -> FileEntry {
+        FileEntry {
+            path: path.to_string(),
+            language: Some("Rust".to_string()),
+            state: FileState {
+                mtime: None,
+                hash: hash.to_string(),
+            },
+        }
+    }
+
+    fn names(db: &TagDatabase, query: &TagQuery) -> Vec<String> {
+        db.lookup(query)
+            .unwrap()
+            .iter()
+            .map(|m| format!("{}@{}", m.record.name, m.record.path))
+            .collect()
+    }
+
+    #[test]
+    fn test_update_replaces_files_and_round_trips_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tags.db");
+        let mut db = TagDatabase::open(&path).unwrap();
+        assert!(is_database(&path));
+
+        let mut area = record("area", "a.rs", 3);
+        area.scope = Some(Scope::new("struct", "Circle"));
+        area.column = Some(8);
+        area.pattern = Some("    fn area(&self) -> f64 {".to_string());
+        area.fields = vec![("signature".to_string(), "(&self)".to_string())];
+        db.update(
+            &[entry("a.rs", "1"), entry("b.rs", "2")],
+            &[
+                area.clone(),
+                record("area", "b.rs", 1),
+                record("main", "b.rs", 5),
+            ],
+            |_| false,
+        )
+        .unwrap();
+
+        let query = TagQuery {
+            name: "area".to_string(),
+            ..TagQuery::default()
+        };
+        let found = db.lookup(&query).unwrap();
+        assert_eq!(found.len(), 2);
+        let mut expected = area;
+        expected.language = Some("Rust".to_string());
+        assert_eq!(found[0].record, expected);
+        assert!(found[0]
+            .line
+            .starts_with("area\ta.rs\t/^    fn area(&self) -> f64 {$/;\""));
+        assert!(!found[0].line.contains("language:"));
+
+        // Retagging b.rs keeps a.rs; a full update drops files it omits.
+        db.update(&[entry("b.rs", "3")], &[record("Area", "b.rs", 2)], |_| {
+            true
+        })
+        .unwrap();
+        let query = TagQuery {
+            name: "AR".to_string(),
+            prefix: true,
+            ignore_case: true,
+            ..TagQuery::default()
+        };
+        assert_eq!(names(&db, &query), ["Area@b.rs", "area@a.rs"]);
+        db.update(&[], &[], |path| path == "b.rs").unwrap();
+        assert_eq!(names(&db, &query), ["Area@b.rs"]);
+    }
+
+    #[test]
+    fn test_check_file_and_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.rs");
+        fs::write(&source, "fn area() {}\n").unwrap();
+        let mut db = TagDatabase::open(&dir.path().join("tags.db")).unwrap();
+        db.set_options("fields=").unwrap();
+
+        let state = db.check_file("a.rs", &source).unwrap().expect("new file");
+        assert_eq!(state.hash, sha256_hex(b"fn area() {}\n"));
+        let file = FileEntry {
+            path: "a.rs".to_string(),
+            language: None,
+            state,
+        };
+        db.update(&[file], &[], |_| false).unwrap();
+        assert_eq!(db.check_file("a.rs", &source).unwrap(), None);
+
+        // Same content under a new modification time is still current.
+        db.conn.execute("UPDATE files SET mtime = 0", []).unwrap();
+        assert_eq!(db.check_file("a.rs", &source).unwrap(), None);
+
+        db.set_options("fields=+S").unwrap();
+        assert!(db.check_file("a.rs", &source).unwrap().is_some());
+    }
+}
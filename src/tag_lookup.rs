@@ -0,0 +1,385 @@
+//! readtags-style lookup of tags by name in a tags file.
+//!
+//! Sorted tags files (`!_TAG_FILE_SORTED` 1, or 2 for case-folded order) are
+//! binary searched by seeking, so only the lines around the matches are read.
+//! Unsorted files, and case-insensitive lookups in a case-sensitively sorted
+//! file, fall back to a linear scan.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::tag_record::TagRecord;
+
+/// Order of the tags in a file, from its `!_TAG_FILE_SORTED` pseudo-tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Unsorted,
+    Sorted,
+    /// Sorted ignoring ASCII case.
+    FoldCase,
+}
+
+/// What to look up.
+#[derive(Debug, Clone, Default)]
+pub struct TagQuery {
+    pub name: String,
+    /// Match every tag whose name starts with `name`.
+    pub prefix: bool,
+    /// Compare names ignoring ASCII case.
+    pub ignore_case: bool,
+    pub filter: TagFilter,
+}
+
+/// Conditions a matching tag must also meet. Empty lists match everything.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    /// Kind letters or names, any of which matches.
+    pub kinds: Vec<String>,
+    /// Language names, any of which matches (ignoring case). Tags whose
+    /// language is unknown never match.
+    pub languages: Vec<String>,
+    /// `NAME` or `KIND:NAME` of the enclosing scope.
+    pub scope: Option<String>,
+    /// Extension fields as `KEY` (present) or `KEY=VALUE`, all of which must match.
+    pub fields: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn matches(&self, record: &TagRecord) -> bool {
+        let kind_ok = self.kinds.is_empty()
+            || self.kinds.iter().any(|kind| {
+                record.kind.as_deref() == Some(kind) || record.kind_name.as_deref() == Some(kind)
+            });
+        let language_ok = self.languages.is_empty()
+            || record.language.as_deref().is_some_and(|language| {
+                self.languages
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(language))
+            });
+        let scope_ok = match &self.scope {
+            None => true,
+            Some(wanted) => {
+                record
+                    .scope
+                    .as_ref()
+                    .is_some_and(|scope| match wanted.split_once(':') {
+                        Some((kind, name)) if !kind.is_empty() && !name.starts_with(':') => {
+                            scope.kind == kind && scope.name == name
+                        }
+                        _ => scope.name == *wanted,
+                    })
+            }
+        };
+        let fields_ok = self.fields.iter().all(|expr| match expr.split_once('=') {
+            Some((key, value)) => field(record, key).is_some_and(|field| field == value),
+            None => field(record, expr).is_some(),
+        });
+        kind_ok && language_ok && scope_ok && fields_ok
+    }
+}
+
+/// An extension field of `record`, including the ones it keeps separately.
+fn field<'a>(record: &'a TagRecord, key: &str) -> Option<Cow<'a, str>> {
+    let number = |n: Option<u32>| n.map(|n| Cow::Owned(n.to_string()));
+    match key {
+        "kind" => record.kind.as_deref().map(Cow::Borrowed),
+        "language" => record.language.as_deref().map(Cow::Borrowed),
+        "line" => number(record.line),
+        "end" => number(record.end_line),
+        _ => record.field(key).map(Cow::Borrowed),
+    }
+    .or_else(|| {
+        let scope = record.scope.as_ref()?;
+        (scope.kind == key).then_some(Cow::Borrowed(scope.name.as_str()))
+    })
+}
+
+/// A tag found by [`lookup`]: the line as written in the tags file and its
+/// parsed form.
+#[derive(Debug, Clone)]
+pub struct TagMatch {
+    pub line: String,
+    pub record: TagRecord,
+}
+
+/// Looks up `query` in the tags file at `path`.
+pub fn lookup(path: &Path, query: &TagQuery) -> anyhow::Result<Vec<TagMatch>> {
+    lookup_with(path, query, |_| {})
+}
+
+/// Like [`lookup`], calling `describe` on every tag whose name matches before
+/// the filter is applied, e.g. to fill in the language with
+/// [`TagRecord::set_language`].
+pub fn lookup_with(
+    path: &Path,
+    query: &TagQuery,
+    mut describe: impl FnMut(&mut TagRecord),
+) -> anyhow::Result<Vec<TagMatch>> {
+    let file =
+        File::open(path).map_err(|e| anyhow::anyhow!("cannot open {}: {e}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let (order, data_start) = read_header(&mut reader)?;
+    let binary = match order {
+        SortOrder::Sorted => !query.ignore_case,
+        SortOrder::FoldCase => true,
+        SortOrder::Unsorted => false,
+    };
+    let fold = query.ignore_case || order == SortOrder::FoldCase;
+    let key = query.name.as_bytes();
+
+    let start = if binary {
+        lower_bound(&mut reader, data_start, key, query.prefix, fold)?
+    } else {
+        data_start
+    };
+    reader.seek(SeekFrom::Start(start))?;
+
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let line = trim_line(&buf);
+        match compare(tag_name(line), key, query.prefix, fold) {
+            Ordering::Equal => {}
+            Ordering::Greater if binary => break,
+            _ => continue,
+        }
+        let line = String::from_utf8_lossy(line).into_owned();
+        let Some(mut record) = TagRecord::from_ctags_line(&line) else {
+            continue;
+        };
+        describe(&mut record);
+        if query.filter.matches(&record) {
+            matches.push(TagMatch { line, record });
+        }
+    }
+    Ok(matches)
+}
+
+/// Reads the pseudo-tags at the top of the file. Returns the sort order and
+/// the offset of the first tag line.
+fn read_header(reader: &mut BufReader<File>) -> anyhow::Result<(SortOrder, u64)> {
+    let mut order = SortOrder::Unsorted;
+    let mut offset = 0;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 || !buf.starts_with(b"!_") {
+            break;
+        }
+        if let Some(rest) = buf.strip_prefix(b"!_TAG_FILE_SORTED\t") {
+            order = match rest.first() {
+                Some(b'1') => SortOrder::Sorted,
+                Some(b'2') => SortOrder::FoldCase,
+                _ => SortOrder::Unsorted,
+            };
+        }
+        offset += read as u64;
+    }
+    Ok((order, offset))
+}
+
+/// Offset of the first line whose name is not less than `key`.
+fn lower_bound(
+    reader: &mut BufReader<File>,
+    data_start: u64,
+    key: &[u8],
+    prefix: bool,
+    fold: bool,
+) -> anyhow::Result<u64> {
+    let len = reader.get_ref().metadata()?.len();
+    let (mut lo, mut hi) = (data_start, len);
+    let mut buf = Vec::new();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let less = match line_from(reader, mid, data_start, &mut buf)? {
+            Some(_) => compare(tag_name(trim_line(&buf)), key, prefix, fold) == Ordering::Less,
+            None => false,
+        };
+        if less {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(line_from(reader, lo, data_start, &mut buf)?.unwrap_or(len))
+}
+
+/// Reads the first line starting at or after `pos` into `buf`, returning its
+/// offset, or `None` past the last line.
+fn line_from(
+    reader: &mut BufReader<File>,
+    pos: u64,
+    data_start: u64,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<Option<u64>> {
+    let start = if pos > data_start {
+        // Finish the line holding the byte before `pos`; when that byte is a
+        // newline this reads just it and `pos` is already a line start.
+        reader.seek(SeekFrom::Start(pos - 1))?;
+        buf.clear();
+        pos - 1 + reader.read_until(b'\n', buf)? as u64
+    } else {
+        reader.seek(SeekFrom::Start(data_start))?;
+        data_start
+    };
+    buf.clear();
+    Ok((reader.read_until(b'\n', buf)? > 0).then_some(start))
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn tag_name(line: &[u8]) -> &[u8] {
+    line.split(|&b| b == b'\t').next().unwrap_or(line)
+}
+
+/// Orders a tag name against the key: for a prefix lookup only the first
+/// `key.len()` bytes of the name count. Case folding uses upper case, as
+/// ctags does when writing a case-folded file.
+fn compare(name: &[u8], key: &[u8], prefix: bool, fold: bool) -> Ordering {
+    let name = if prefix {
+        &name[..name.len().min(key.len())]
+    } else {
+        name
+    };
+    if fold {
+        name.iter()
+            .map(u8::to_ascii_uppercase)
+            .cmp(key.iter().map(u8::to_ascii_uppercase))
+    } else {
+        name.cmp(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TAGS: &str = "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted/\n\
+        Circle\tshapes.rs\t/^struct Circle {$/;\"\ts\tline:3\n\
+        area\tshapes.rs\t/^    fn area(&self) -> f64 {$/;\"\tf\tline:8\timplementation:Circle\n\
+        area\tsquare.rs\t/^    fn area(&self) -> f64 {$/;\"\tf\tline:4\timplementation:Square\n\
+        area_of\tshapes.rs\t/^fn area_of(s: &Shape) {$/;\"\tf\tline:20\n\
+        main\tmain.rs\t/^fn main() {$/;\"\tf\tline:1\n\
+        zone\tzone.rs\t/^const zone: u8 = 1;$/;\"\tc\n";
+
+    fn tags_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn names(path: &Path, query: &TagQuery) -> Vec<String> {
+        lookup(path, query)
+            .unwrap()
+            .into_iter()
+            .map(|m| format!("{}@{}", m.record.name, m.record.path))
+            .collect()
+    }
+
+    fn query(name: &str) -> TagQuery {
+        TagQuery {
+            name: name.to_string(),
+            ..TagQuery::default()
+        }
+    }
+
+    #[test]
+    fn test_exact_and_prefix_lookup() {
+        let file = tags_file(TAGS);
+        assert_eq!(
+            names(file.path(), &query("area")),
+            ["area@shapes.rs", "area@square.rs"]
+        );
+        let prefix = TagQuery {
+            prefix: true,
+            ..query("are")
+        };
+        assert_eq!(
+            names(file.path(), &prefix),
+            ["area@shapes.rs", "area@square.rs", "area_of@shapes.rs"]
+        );
+        for name in ["Circle", "zone", "main"] {
+            assert_eq!(names(file.path(), &query(name)).len(), 1, "{name}");
+        }
+        assert!(names(file.path(), &query("missing")).is_empty());
+        assert!(names(file.path(), &query("zzz")).is_empty());
+        assert!(names(file.path(), &query("A")).is_empty());
+    }
+
+    #[test]
+    fn test_ignore_case_and_unsorted_files() {
+        let file = tags_file(TAGS);
+        let ignore_case = TagQuery {
+            ignore_case: true,
+            ..query("circle")
+        };
+        assert_eq!(names(file.path(), &ignore_case), ["Circle@shapes.rs"]);
+
+        let unsorted = tags_file(&TAGS.replace("\t1\t/0", "\t0\t/0"));
+        assert_eq!(
+            names(unsorted.path(), &query("area")),
+            ["area@shapes.rs", "area@square.rs"]
+        );
+
+        let folded = "!_TAG_FILE_SORTED\t2\t/0=unsorted, 1=sorted, 2=foldcase/\n\
+            alpha\ta.rs\t1;\"\tf\n\
+            Beta\tb.rs\t2;\"\tf\n\
+            gamma\tc.rs\t3;\"\tf\n";
+        let folded = tags_file(folded);
+        assert_eq!(names(folded.path(), &query("beta")), ["Beta@b.rs"]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let file = tags_file(TAGS);
+        let scoped = TagQuery {
+            filter: TagFilter {
+                scope: Some("implementation:Square".to_string()),
+                ..TagFilter::default()
+            },
+            ..query("area")
+        };
+        assert_eq!(names(file.path(), &scoped), ["area@square.rs"]);
+
+        let fields = TagQuery {
+            prefix: true,
+            filter: TagFilter {
+                kinds: vec!["f".to_string()],
+                fields: vec!["line=20".to_string()],
+                ..TagFilter::default()
+            },
+            ..query("a")
+        };
+        assert_eq!(names(file.path(), &fields), ["area_of@shapes.rs"]);
+
+        let language = TagQuery {
+            filter: TagFilter {
+                languages: vec!["rust".to_string()],
+                ..TagFilter::default()
+            },
+            ..query("main")
+        };
+        assert!(names(file.path(), &language).is_empty());
+        let described = lookup_with(file.path(), &language, |record| {
+            record.language = Some("Rust".to_string())
+        })
+        .unwrap();
+        assert_eq!(described.len(), 1);
+        assert_eq!(
+            described[0].line,
+            "main\tmain.rs\t/^fn main() {$/;\"\tf\tline:1"
+        );
+    }
+}
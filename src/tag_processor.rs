@@ -1,12 +1,16 @@
 use crate::config::Config;
-use crate::language_parser::{LangId, LanguageParserRegistry, NameResolution};
+use crate::file_finder::FileFinder;
+use crate::language_parser::{LangId, LanguageParserRegistry, NameResolution, SourceFile};
 use crate::parser::Parser;
+use crate::plugin::post_process::{PipelineRunner, PostProcessPipeline};
 use crate::tag::Tag;
+use rayon::iter::Either;
 use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// Bytes read from the head of a file to inspect its `#!` shebang line.
 const SHEBANG_PREFIX_BYTES: u64 = 256;
@@ -26,7 +30,7 @@ const MODELINE_WINDOW_BYTES: u64 = 4096;
 ///
 /// A resolved language, plus any file content already read while resolving it,
 /// so the caller can avoid re-reading the file.
-pub(crate) struct Selection {
+pub struct Selection {
     pub lang: LangId,
     /// The full file content when it was read during resolution (ambiguous
     /// names), else `None`.
@@ -38,7 +42,7 @@ pub(crate) struct Selection {
 /// `path` is used for file IO; `rel_path` (the path relative to the launch
 /// directory) is what name resolution matches against, so relative-path regexes
 /// see the directory components.
-pub(crate) fn select_language(
+pub fn select_language(
     registry: &LanguageParserRegistry,
     config: &Config,
     path: &Path,
@@ -102,6 +106,42 @@ pub(crate) fn select_language(
     }
 }
 
+/// [`select_language`] for a source held in memory rather than read from
+/// `rel_path`, which need not exist. The shebang fallback runs under `-G` or
+/// when a file at `rel_path` exists and is executable.
+pub(crate) fn select_language_for_source(
+    registry: &LanguageParserRegistry,
+    config: &Config,
+    rel_path: &Path,
+    code: &[u8],
+) -> Option<LangId> {
+    match registry.resolve_by_name(rel_path) {
+        NameResolution::Unique(id) => Some(id),
+        NameResolution::Ambiguous(ids) => {
+            let cap = (SELECTOR_PREFIX_BYTES as usize).min(code.len());
+            Some(registry.disambiguate(&ids, &code[..cap]).unwrap_or(ids[0]))
+        }
+        NameResolution::None => {
+            if config.guess_language_eagerly || is_executable(rel_path) {
+                let cap = (SHEBANG_PREFIX_BYTES as usize).min(code.len());
+                if let Some(id) = registry.resolve_by_shebang(&code[..cap]) {
+                    return Some(id);
+                }
+            }
+            if !config.guess_language_eagerly {
+                return None;
+            }
+            let window = MODELINE_WINDOW_BYTES as usize;
+            let head = &code[..window.min(code.len())];
+            let tail = match code.len().checked_sub(window) {
+                Some(start) if start > 0 => &code[start..],
+                _ => &[][..],
+            };
+            registry.resolve_by_modeline(head, tail)
+        }
+    }
+}
+
 /// Reads up to `max` bytes from the start of `path`.
 fn read_prefix(path: &Path, max: u64) -> std::io::Result<Vec<u8>> {
     let mut buf = Vec::new();
@@ -141,10 +181,153 @@ fn is_executable(_path: &Path) -> bool {
     false
 }
 
+/// Generates the tags of `paths`, files or directories searched as configured
+/// by `config`, the way the `treetags` command does but without writing a tags
+/// file. Tag file names are relative to the current directory. Paths that
+/// cannot be read are reported on stderr and skipped.
+pub fn generate<P: AsRef<str>>(paths: &[P], config: &Config) -> impl Iterator<Item = Tag> {
+    let paths: Vec<String> = paths.iter().map(|p| p.as_ref().to_string()).collect();
+    let files = match FileFinder::from_patterns(config.exclude.clone(), config.recurse) {
+        Ok(finder) => {
+            let result = finder.get_files_from_paths(&paths);
+            result.print_errors();
+            result.files
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            Vec::new()
+        }
+    };
+
+    let processor = TagProcessor::new("-".to_string(), config.workers, config.clone());
+    let mut tags = processor.process_files(files);
+    if config.sort {
+        tags.par_sort_unstable_by(|a, b| a.sort_cmp(b));
+    }
+    tags.into_iter()
+}
+
+/// Generates the tags of a source held in memory, such as an unsaved editor
+/// buffer, as if it were the file `path`: the language is selected from `path`
+/// and `source`, and the tags are attributed to `path`. `path` need not exist.
+pub fn generate_source(path: &str, source: &[u8], config: &Config) -> impl Iterator<Item = Tag> {
+    let processor = TagProcessor::new("-".to_string(), config.workers, config.clone());
+    processor.process_source(path, source).into_iter()
+}
+
+/// Tags one source at a time, keeping the language registry, a parser with
+/// its plugin instances and the post-processors loaded between calls. For
+/// long-running callers such as `--interactive` and `treetags lsp`.
+pub struct SourceTagger {
+    registry: LanguageParserRegistry,
+    parser: Parser,
+    post_process: PipelineRunner,
+}
+
+impl SourceTagger {
+    /// Loads the languages, plugins and post-processors of `config`.
+    pub fn new(config: &Config) -> Self {
+        let registry = LanguageParserRegistry::new(config);
+        let parser = registry.create_parser();
+        let post_process = Arc::new(PostProcessPipeline::load(config)).runner();
+        Self {
+            registry,
+            parser,
+            post_process,
+        }
+    }
+
+    pub fn registry(&self) -> &LanguageParserRegistry {
+        &self.registry
+    }
+
+    /// Tags `code` as the file `file_name`, relative to the current
+    /// directory, returning the language it was tagged as (look it up in
+    /// [`registry`](Self::registry)) with its tags, or `None` when no language
+    /// handles it. `config` may differ from the one the tagger was created
+    /// with in its fields, extras and kinds; languages and plugins stay those
+    /// loaded at creation.
+    pub fn tag(
+        &mut self,
+        file_name: &str,
+        code: &[u8],
+        config: &Config,
+    ) -> Option<(LangId, Vec<Tag>)> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        self.tag_in(file_name, code, config, &cwd)
+    }
+
+    fn tag_in(
+        &mut self,
+        file_name: &str,
+        code: &[u8],
+        config: &Config,
+        tag_file_dir: &Path,
+    ) -> Option<(LangId, Vec<Tag>)> {
+        tag_source(
+            &self.registry,
+            &mut self.parser,
+            &mut self.post_process,
+            file_name,
+            code,
+            config,
+            tag_file_dir,
+        )
+    }
+}
+
+/// Tags `code` as the file `file_name` with a parser of `registry`, naming it
+/// relative to `tag_file_dir`.
+fn tag_source(
+    registry: &LanguageParserRegistry,
+    parser: &mut Parser,
+    post_process: &mut PipelineRunner,
+    file_name: &str,
+    code: &[u8],
+    config: &Config,
+    tag_file_dir: &Path,
+) -> Option<(LangId, Vec<Tag>)> {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let file_path = cwd.join(file_name);
+    let file_path_relative = relative_path(&file_path, tag_file_dir, file_name);
+
+    let lang = select_language_for_source(registry, config, Path::new(file_name), code)?;
+    let lp = registry.parser(lang);
+    let mut tags = lp.generate_tags(parser, code, &file_path_relative, config, &file_path);
+    tags = post_process.run(tags, &file_path_relative, lp.language_name(), code);
+
+    if config.sort {
+        tags.sort_unstable_by(|a, b| a.sort_cmp(b));
+    }
+    Some((lang, tags))
+}
+
+/// Result of the first, per-file pass in `TagProcessor::process_files`.
+enum Processed {
+    Tags(Vec<Tag>),
+    /// The file's parser takes batches; tagged in the second pass.
+    Deferred(Deferred),
+}
+
+/// A file whose language was resolved in the first pass but whose tags are
+/// generated as part of a batch.
+struct Deferred {
+    lang: LangId,
+    file_path: PathBuf,
+    file_path_relative: String,
+    /// Content already read during language selection, if any.
+    content: Option<Vec<u8>>,
+}
+
 pub struct TagProcessor {
     tag_file_path: String,
     workers: usize,
     config: Config,
+    /// Built on first use and kept to describe the tags afterwards.
+    registry: OnceLock<LanguageParserRegistry>,
+    /// The language each tagged file was parsed as, keyed by its name in the
+    /// tags.
+    languages: Mutex<HashMap<String, LangId>>,
 }
 
 impl TagProcessor {
@@ -153,23 +336,43 @@ impl TagProcessor {
             tag_file_path,
             workers,
             config,
+            registry: OnceLock::new(),
+            languages: Mutex::new(HashMap::new()),
         }
     }
 
+    /// The languages files are tagged with, loaded on first use.
+    pub fn registry(&self) -> &LanguageParserRegistry {
+        self.registry
+            .get_or_init(|| LanguageParserRegistry::new(&self.config))
+    }
+
+    /// The language the file named `path` in the tags was tagged as, by
+    /// [`process_files`](Self::process_files) or
+    /// [`process_source`](Self::process_source).
+    pub fn language_of(&self, path: &str) -> Option<LangId> {
+        self.languages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(path)
+            .copied()
+    }
+
+    fn record_language(&self, path: &str, lang: LangId) {
+        self.languages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path.to_string(), lang);
+    }
+
     pub fn process_files(&self, file_names: Vec<String>) -> Vec<Tag> {
-        // Build registry once; share Arc across workers.
+        // Build registry once and share it across workers.
         // LanguageParserRegistry::new also JIT-compiles WASM plugins once.
-        let lang_registry = Arc::new(LanguageParserRegistry::new(&self.config));
+        let lang_registry = self.registry();
+        let post_processors = Arc::new(PostProcessPipeline::load(&self.config));
 
         let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let tag_file_dir = if self.tag_file_path == "-" {
-            cwd.clone()
-        } else {
-            Path::new(&self.tag_file_path)
-                .parent()
-                .unwrap_or(Path::new(""))
-                .to_path_buf()
-        };
+        let tag_file_dir = self.tag_file_dir(&cwd);
 
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(self.workers)
@@ -177,60 +380,206 @@ impl TagProcessor {
             .expect("failed to build rayon thread pool");
 
         pool.install(|| {
-            file_names
+            // Files for parsers that take batches (WASM plugins) are deferred
+            // out of the first pass and grouped per language below.
+            let (tags, deferred): (Vec<Vec<Tag>>, Vec<Deferred>) = file_names
                 .par_iter()
                 .map_init(
-                    || lang_registry.create_parser(),
-                    |parser, file_name| {
-                        Self::process_one(
-                            parser,
-                            file_name,
-                            &cwd,
-                            &tag_file_dir,
-                            &self.config,
-                            &lang_registry,
-                        )
+                    || (lang_registry.create_parser(), post_processors.runner()),
+                    |(parser, post_process), file_name| {
+                        self.process_one(parser, post_process, file_name, &cwd, &tag_file_dir)
                     },
                 )
-                .flatten_iter()
-                .collect()
+                .partition_map(|processed| match processed {
+                    Processed::Tags(tags) => Either::Left(tags),
+                    Processed::Deferred(file) => Either::Right(file),
+                });
+
+            let mut tags: Vec<Tag> = tags.into_iter().flatten().collect();
+            tags.par_extend(
+                self.group_batches(deferred)
+                    .into_par_iter()
+                    .map_init(
+                        || (lang_registry.create_parser(), post_processors.runner()),
+                        |(parser, post_process), (lang, batch)| {
+                            Self::process_batch(
+                                parser,
+                                post_process,
+                                lang,
+                                batch,
+                                &self.config,
+                                lang_registry,
+                            )
+                        },
+                    )
+                    .flatten_iter(),
+            );
+            tags
         })
     }
 
-    /// Parses a file and returns its tags
+    /// Tags `code` as the file `file_name` without reading it from disk. The
+    /// language is selected by the name and the content, and the tags name
+    /// `file_name` the same way they would name the file on disk.
+    pub fn process_source(&self, file_name: &str, code: &[u8]) -> Vec<Tag> {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let registry = self.registry();
+        let mut post_process = Arc::new(PostProcessPipeline::load(&self.config)).runner();
+        let tagged = tag_source(
+            registry,
+            &mut registry.create_parser(),
+            &mut post_process,
+            file_name,
+            code,
+            &self.config,
+            &self.tag_file_dir(&cwd),
+        );
+        let Some((lang, tags)) = tagged else {
+            return Vec::new();
+        };
+        if let Some(tag) = tags.first() {
+            self.record_language(&tag.file_name, lang);
+        }
+        tags
+    }
+
+    /// The directory tag file names are relative to.
+    fn tag_file_dir(&self, cwd: &Path) -> PathBuf {
+        if self.tag_file_path == "-" {
+            cwd.to_path_buf()
+        } else {
+            Path::new(&self.tag_file_path)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_path_buf()
+        }
+    }
+
+    /// Groups deferred files per language and splits each group into batches
+    /// of at most `--plugin-batch-size` files, made smaller when needed so a
+    /// single plugin's files still spread across all workers.
+    fn group_batches(&self, deferred: Vec<Deferred>) -> Vec<(LangId, Vec<Deferred>)> {
+        let mut by_lang: BTreeMap<LangId, Vec<Deferred>> = BTreeMap::new();
+        for file in deferred {
+            by_lang.entry(file.lang).or_default().push(file);
+        }
+
+        let mut batches = Vec::new();
+        for (lang, files) in by_lang {
+            let size = self
+                .config
+                .plugin_batch_size
+                .min(files.len().div_ceil(self.workers.max(1)))
+                .max(1);
+            let mut files = files.into_iter().peekable();
+            while files.peek().is_some() {
+                batches.push((lang, files.by_ref().take(size).collect()));
+            }
+        }
+        batches
+    }
+
+    /// Parses a file and returns its tags, or defers it when its language
+    /// parser takes batches.
     fn process_one(
+        &self,
         parser: &mut Parser,
+        post_process: &mut PipelineRunner,
         file_name: &str,
         cwd: &Path,
         tag_file_dir: &Path,
-        config: &Config,
-        registry: &LanguageParserRegistry,
-    ) -> Vec<Tag> {
+    ) -> Processed {
+        let (config, registry) = (&self.config, self.registry());
         let file_path = cwd.join(file_name);
-
-        let file_path_relative = match file_path.strip_prefix(tag_file_dir) {
-            Ok(path) => path.to_string_lossy().into_owned(),
-            Err(_) => file_name.to_string(),
-        };
+        let file_path_relative = relative_path(&file_path, tag_file_dir, file_name);
 
         let selection = match select_language(registry, config, &file_path, Path::new(file_name)) {
             Some(selection) => selection,
-            None => return Vec::new(),
+            None => return Processed::Tags(Vec::new()),
         };
+        self.record_language(&file_path_relative, selection.lang);
         let lp = registry.parser(selection.lang);
 
+        if lp.supports_batch() {
+            return Processed::Deferred(Deferred {
+                lang: selection.lang,
+                file_path,
+                file_path_relative,
+                content: selection.content,
+            });
+        }
+
         let code = match selection.content {
             Some(content) => content,
             None => match fs::read(&file_path) {
                 Ok(b) => b,
                 Err(e) => {
                     eprintln!("{}", e);
-                    return Vec::new();
+                    return Processed::Tags(Vec::new());
                 }
             },
         };
 
         let mut tags = lp.generate_tags(parser, &code, &file_path_relative, config, &file_path);
+        tags = post_process.run(tags, &file_path_relative, lp.language_name(), &code);
+
+        if config.sort {
+            tags.sort_unstable_by(|a, b| a.sort_cmp(b));
+        }
+
+        Processed::Tags(tags)
+    }
+
+    /// Reads a batch of deferred files and hands them to their language parser
+    /// in one call.
+    fn process_batch(
+        parser: &mut Parser,
+        post_process: &mut PipelineRunner,
+        lang: LangId,
+        batch: Vec<Deferred>,
+        config: &Config,
+        registry: &LanguageParserRegistry,
+    ) -> Vec<Tag> {
+        let loaded: Vec<(Deferred, Vec<u8>)> = batch
+            .into_iter()
+            .filter_map(|mut file| {
+                let code = match file.content.take() {
+                    Some(content) => content,
+                    None => match fs::read(&file.file_path) {
+                        Ok(b) => b,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            return None;
+                        }
+                    },
+                };
+                Some((file, code))
+            })
+            .collect();
+        let files: Vec<SourceFile> = loaded
+            .iter()
+            .map(|(file, code)| SourceFile {
+                code,
+                path: &file.file_path_relative,
+                absolute_path: &file.file_path,
+            })
+            .collect();
+
+        let lp = registry.parser(lang);
+        let mut tags = lp.generate_tags_batch(parser, &files, config);
+        if !post_process.is_empty() {
+            let mut by_file: HashMap<Arc<str>, Vec<Tag>> = HashMap::new();
+            for tag in tags {
+                by_file.entry(tag.file_name.clone()).or_default().push(tag);
+            }
+            tags = files
+                .iter()
+                .flat_map(|file| {
+                    let file_tags = by_file.remove(file.path).unwrap_or_default();
+                    post_process.run(file_tags, file.path, lp.language_name(), file.code)
+                })
+                .collect();
+        }
 
         if config.sort {
             tags.sort_unstable_by(|a, b| a.sort_cmp(b));
@@ -239,3 +588,12 @@ impl TagProcessor {
         tags
     }
 }
+
+/// The name `file_path` is written under in a tags file in `tag_file_dir`:
+/// relative to that directory, or `file_name` as given when outside it.
+pub fn relative_path(file_path: &Path, tag_file_dir: &Path, file_name: &str) -> String {
+    match file_path.strip_prefix(tag_file_dir) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => file_name.to_string(),
+    }
+}
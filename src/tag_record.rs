@@ -0,0 +1,407 @@
+//! A structured, serializable view of a tag for programs consuming treetags
+//! output.
+//!
+//! [`Tag`] keeps the tags-file representation: a pre-escaped search address
+//! and extension fields in output order. [`TagRecord`] splits that into typed
+//! parts — line numbers, the unescaped source line, the scope — and derives
+//! `Serialize`/`Deserialize`. [`TagRecord::from_ctags_line`] and
+//! [`TagRecord::to_ctags_line`] convert to and from the tags file format.
+//!
+//! # Stability
+//!
+//! `TagRecord` and [`Scope`] are part of the public API and follow semver:
+//! fields are not removed, renamed or retyped outside a major release, and
+//! their serialized form keeps its field names. New fields may be added in
+//! minor releases; they are optional and default when absent, so older
+//! serialized records still deserialize. Both types are `#[non_exhaustive]`;
+//! build them with [`TagRecord::new`] and [`Scope::new`].
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::parser::KindInfo;
+use crate::tag::{parse_tag_line, ExtensionFields, Tag};
+
+/// Extension fields that describe the tag itself rather than the scope it is
+/// nested in. Any other field names a scope, e.g. `class:Foo`.
+const UNSCOPED_FIELDS: &[&str] = &[
+    "access",
+    "column",
+    "end",
+    "file",
+    "inherits",
+    "kind",
+    "language",
+    "line",
+    "module",
+    "nameref",
+    "roles",
+    "signature",
+    "typeref",
+];
+
+/// The scope a tag is defined in, e.g. `class` `Outer.Inner`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Scope {
+    /// Kind of the enclosing definition, e.g. `class`, `module`, `impl`.
+    pub kind: String,
+    /// Name of the enclosing definition as written in the tags file,
+    /// including any parent scopes (`Outer.Inner`, `example::Shape`).
+    pub name: String,
+}
+
+impl Scope {
+    pub fn new(kind: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            name: name.into(),
+        }
+    }
+}
+
+/// One tag as structured data.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct TagRecord {
+    pub name: String,
+    /// Path of the file, as written in the tags file.
+    pub path: String,
+    /// 1-based line of the definition, when known: from the `line` field or a
+    /// line-number address.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// 1-based column of the name, from the `column` field. treetags' own
+    /// parsers do not write that field; it is set for tags read from tags
+    /// files or plugins that record it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+    /// 1-based last line of the definition, from the `end` field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u32>,
+    /// The source line the tag's search pattern matches, unescaped and without
+    /// the `/^` and `$/` anchors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Whether `pattern` is only the start of the line. Lines longer than 96
+    /// bytes are cut, and their pattern then has no `$` anchor.
+    #[serde(skip_serializing_if = "is_false")]
+    pub pattern_truncated: bool,
+    /// Kind letter, e.g. `f`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Long kind name, e.g. `function`, when the language's kinds are known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind_name: Option<String>,
+    /// Language name, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Scope>,
+    /// The remaining extension fields, in tags file order.
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "fields_map::serialize",
+        deserialize_with = "fields_map::deserialize"
+    )]
+    pub fields: Vec<(String, String)>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl TagRecord {
+    pub fn new(name: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Parses one line of a tags file. `None` for pseudo-tags (`!_TAG_…`) and
+    /// lines with fewer than three columns.
+    pub fn from_ctags_line(line: &str) -> Option<Self> {
+        if line.starts_with('!') {
+            return None;
+        }
+        parse_tag_line(line.trim_end_matches(['\r', '\n'])).map(|tag| Self::from(&tag))
+    }
+
+    /// Formats the record as a tags file line, without the trailing newline.
+    pub fn to_ctags_line(&self) -> String {
+        let mut out = Vec::new();
+        Tag::from(self).write_into(&mut out);
+        out.pop();
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// Sets the language and fills in `kind_name` from the language's kinds,
+    /// e.g. `registry.for_language(lang).kinds()`.
+    pub fn set_language(&mut self, language: &str, kinds: &[KindInfo]) {
+        self.language = Some(language.to_string());
+        self.kind_name = self.kind.as_deref().and_then(|letter| {
+            kinds
+                .iter()
+                .find(|k| k.letter == letter)
+                .map(|k| k.name.clone())
+        });
+    }
+
+    /// The value of the extension field `key` among the remaining fields.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl From<&Tag> for TagRecord {
+    fn from(tag: &Tag) -> Self {
+        let mut record = TagRecord::new(tag.name.clone(), &*tag.file_name);
+        record.kind = tag.kind.as_deref().map(str::to_string);
+
+        let address = tag.address.trim_end_matches('\t');
+        let address = address.strip_suffix(";\"").unwrap_or(address);
+        if let Some((pattern, truncated)) = unescape_pattern(address) {
+            record.pattern = Some(pattern);
+            record.pattern_truncated = truncated;
+        } else if let Ok(line) = address.parse() {
+            record.line = Some(line);
+        }
+
+        let Some(fields) = &tag.extension_fields else {
+            return record;
+        };
+        let module = fields.get("module");
+        for (key, value) in fields.iter() {
+            match key.as_ref() {
+                "line" => record.line = value.parse().ok().or(record.line),
+                "column" => record.column = value.parse().ok(),
+                "end" => record.end_line = value.parse().ok(),
+                "kind" => record.kind = Some(value.to_string()),
+                "language" => record.language = Some(value.to_string()),
+                "module" => {}
+                key if record.scope.is_none() && !UNSCOPED_FIELDS.contains(&key) => {
+                    // Matches `Tag::write_into`, which prefixes scopes with the module.
+                    let name = match module {
+                        Some(module) => format!("{module}::{value}"),
+                        None => value.to_string(),
+                    };
+                    record.scope = Some(Scope::new(key, name));
+                }
+                key => record.fields.push((key.to_string(), value.to_string())),
+            }
+        }
+        if record.scope.is_none() {
+            record.scope = module.map(|module| Scope::new("module", module));
+        }
+        record
+    }
+}
+
+impl From<&TagRecord> for Tag {
+    fn from(record: &TagRecord) -> Self {
+        let address = match (&record.pattern, record.line) {
+            (Some(pattern), _) => {
+                let mut address = String::with_capacity(pattern.len() + 8);
+                address.push_str("/^");
+                Tag::escape_address_into(pattern, &mut address);
+                address.push_str(if record.pattern_truncated {
+                    "/;\""
+                } else {
+                    "$/;\""
+                });
+                address
+            }
+            (None, Some(line)) => format!("{line};\""),
+            (None, None) => {
+                let mut address = String::from("/");
+                Tag::escape_address_into(&record.name, &mut address);
+                address.push_str("/;\"");
+                address
+            }
+        };
+
+        let mut fields = ExtensionFields::new();
+        if let (Some(line), Some(_)) = (record.line, &record.pattern) {
+            fields.insert("line", line.to_string());
+        }
+        if let Some(column) = record.column {
+            fields.insert("column", column.to_string());
+        }
+        if let Some(language) = &record.language {
+            fields.insert("language", language.clone());
+        }
+        for (key, value) in &record.fields {
+            fields.insert(key.clone(), value.clone());
+        }
+        if let Some(end) = record.end_line {
+            fields.insert("end", end.to_string());
+        }
+        if let Some(scope) = &record.scope {
+            fields.insert(scope.kind.clone(), scope.name.clone());
+        }
+
+        Tag {
+            name: record.name.clone(),
+            file_name: Arc::from(record.path.as_str()),
+            address,
+            kind: record.kind.clone().map(Cow::Owned),
+            extension_fields: (!fields.is_empty()).then_some(fields),
+        }
+    }
+}
+
+/// Splits a `/^…$/` search address into the unescaped line text and whether
+/// the `$` anchor is missing. `None` for other addresses.
+fn unescape_pattern(address: &str) -> Option<(String, bool)> {
+    let body = address.strip_prefix("/^")?.strip_suffix('/')?;
+    let mut text = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    let mut anchored = false;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => text.extend(chars.next()),
+            '$' if chars.as_str().is_empty() => anchored = true,
+            _ => text.push(ch),
+        }
+    }
+    Some((text, !anchored))
+}
+
+/// (De)serializes the field list as a map, keeping its order.
+mod fields_map {
+    use serde::de::{MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(
+        fields: &[(String, String)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for (key, value) in fields {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, String)>, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = Vec<(String, String)>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of extension fields")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::with_capacity(access.size_hint().unwrap_or(0));
+                while let Some(entry) = access.next_entry()? {
+                    fields.push(entry);
+                }
+                Ok(fields)
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_ctags_line() {
+        let line = "area\tshapes.rs\t/^    fn area(&self) -> f64 {$/;\"\tkind:f\tline:12\t\
+                    signature:(&self)\tend:14\ttrait:example::Shape";
+        let record = TagRecord::from_ctags_line(line).unwrap();
+
+        assert_eq!(record.name, "area");
+        assert_eq!(record.path, "shapes.rs");
+        assert_eq!(record.line, Some(12));
+        assert_eq!(record.end_line, Some(14));
+        assert_eq!(
+            record.pattern.as_deref(),
+            Some("    fn area(&self) -> f64 {")
+        );
+        assert!(!record.pattern_truncated);
+        assert_eq!(record.kind.as_deref(), Some("f"));
+        assert_eq!(record.scope, Some(Scope::new("trait", "example::Shape")));
+        assert_eq!(
+            record.fields,
+            vec![("signature".to_string(), "(&self)".to_string())]
+        );
+        assert_eq!(record.column, None);
+        assert!(TagRecord::from_ctags_line("!_TAG_FILE_SORTED\t1\t/0=unsorted/").is_none());
+
+        let record = TagRecord::from_ctags_line("main\tmain.c\t3;\"\tf\tcolumn:5").unwrap();
+        assert_eq!(record.column, Some(5));
+        assert!(record.fields.is_empty());
+    }
+
+    #[test]
+    fn test_module_scope_and_escapes() {
+        let line = "Bar\tsrc/lib.rs\t/^struct Bar { path: \\/\\^a\\$ }/;\"\ts\tmodule:example";
+        let record = TagRecord::from_ctags_line(line).unwrap();
+
+        assert_eq!(record.pattern.as_deref(), Some("struct Bar { path: /^a$ }"));
+        assert!(record.pattern_truncated);
+        assert_eq!(record.scope, Some(Scope::new("module", "example")));
+        assert!(record.fields.is_empty());
+    }
+
+    #[test]
+    fn test_ctags_line_round_trip() {
+        for line in [
+            "area\tshapes.rs\t/^    fn area(&self) -> f64 {$/;\"\tf\tline:12\tsignature:(&self)\tend:14\ttrait:Shape",
+            "Bar\tlib.rs\t/^struct Bar { path: \\/\\^a\\$ }/;\"\ts",
+            "main\tmain.c\t3;\"\tf",
+            "area\tshapes.rs\t/^    fn area(&self) -> f64 {$/;\"\tf\tline:12\tcolumn:8\tend:14",
+        ] {
+            let record = TagRecord::from_ctags_line(line).unwrap();
+            assert_eq!(record.to_ctags_line(), line);
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut record = TagRecord::new("area", "shapes.rs");
+        record.line = Some(12);
+        record.kind = Some("f".to_string());
+        record.scope = Some(Scope::new("trait", "Shape"));
+        record.fields = vec![
+            ("signature".to_string(), "(&self)".to_string()),
+            ("access".to_string(), "public".to_string()),
+        ];
+        record.set_language(
+            "Rust",
+            &[KindInfo {
+                letter: "f".to_string(),
+                name: "function".to_string(),
+                default: true,
+            }],
+        );
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"area","path":"shapes.rs","line":12,"kind":"f","kind_name":"function","language":"Rust","scope":{"kind":"trait","name":"Shape"},"fields":{"signature":"(&self)","access":"public"}}"#
+        );
+        assert_eq!(serde_json::from_str::<TagRecord>(&json).unwrap(), record);
+        assert_eq!(
+            serde_json::from_str::<TagRecord>(r#"{"name":"x","path":"a.rs"}"#).unwrap(),
+            TagRecord::new("x", "a.rs")
+        );
+    }
+}
@@ -5,9 +5,15 @@
 //! This module handles sorting and writing tags to the output file or standard output.
 
 use crate::tag::Tag;
+use crate::tag_record::TagRecord;
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
 
+/// The `-x` columns Universal Ctags prints by default: name, kind, line,
+/// file and the source line.
+pub const DEFAULT_XREF_FORMAT: &str = "%-16N %-10K %4n %-16F %C";
+
 /// A structure for writing tags to a file.
 ///
 /// TagWriter handles sorting tags and writing them to the output file.
@@ -40,21 +46,8 @@ impl TagWriter {
     ///
     /// * `tags` - A mutable reference to a vector of tags to write
     pub fn write_tags(&self, tags: &mut Vec<Tag>, emit_pseudo_tags: bool, sorted: bool) {
-        // Create a buffered writer for either stdout or a file
-        let mut writer: Box<dyn Write> = if self.file_path == "-" {
-            // Write to stdout
-            Box::new(BufWriter::new(io::stdout()))
-        } else {
-            // Open file for writing
-            let file = match File::create(&self.file_path) {
-                Ok(file) => file,
-                Err(e) => {
-                    eprintln!("Failed to create tag file: {}", e);
-                    return;
-                }
-            };
-
-            Box::new(BufWriter::new(file))
+        let Some(mut writer) = self.open() else {
+            return;
         };
 
         if emit_pseudo_tags {
@@ -78,4 +71,318 @@ impl TagWriter {
             }
         }
     }
+
+    /// Writes `tags` as a cross-reference table (`ctags -x`), one line per
+    /// tag laid out by `format`.
+    ///
+    /// `describe` is called on the record of every tag before it is
+    /// formatted, e.g. to fill in the language and kind names with
+    /// [`TagRecord::set_language`].
+    pub fn write_xref(
+        &self,
+        tags: &[Tag],
+        format: &XrefFormat,
+        mut describe: impl FnMut(&mut TagRecord),
+    ) {
+        let Some(mut writer) = self.open() else {
+            return;
+        };
+
+        let mut line = String::new();
+        for tag in tags {
+            let mut record = TagRecord::from(tag);
+            describe(&mut record);
+            line.clear();
+            format.format_into(tag, &record, &mut line);
+            line.push('\n');
+            if let Err(e) = writer.write_all(line.as_bytes()) {
+                eprintln!("Failed to write tag: {}", e);
+            }
+        }
+    }
+
+    /// Creates a buffered writer for either stdout or the output file.
+    fn open(&self) -> Option<Box<dyn Write>> {
+        if self.file_path == "-" {
+            return Some(Box::new(BufWriter::new(io::stdout())));
+        }
+        match File::create(&self.file_path) {
+            Ok(file) => Some(Box::new(BufWriter::new(file))),
+            Err(e) => {
+                eprintln!("Failed to create tag file: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// A column of [`XrefFormat`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum XrefColumn {
+    Name,
+    Input,
+    /// The search pattern as written in a tags file.
+    Pattern,
+    /// The source line with surrounding whitespace removed and inner runs of
+    /// whitespace collapsed.
+    Compact,
+    Line,
+    End,
+    KindLetter,
+    /// The long kind name, or the letter when the language's kinds are unknown.
+    KindName,
+    Language,
+    Scope,
+    ScopeKind,
+    /// Any other extension field, by name.
+    Field(String),
+}
+
+impl XrefColumn {
+    fn from_letter(letter: char) -> Option<Self> {
+        Some(match letter {
+            'N' => Self::Name,
+            'F' => Self::Input,
+            'P' => Self::Pattern,
+            'C' => Self::Compact,
+            'n' => Self::Line,
+            'e' => Self::End,
+            'k' => Self::KindLetter,
+            'K' => Self::KindName,
+            'l' => Self::Language,
+            's' => Self::Scope,
+            'p' => Self::ScopeKind,
+            'S' => Self::Field("signature".to_string()),
+            'a' => Self::Field("access".to_string()),
+            't' => Self::Field("typeref".to_string()),
+            'r' => Self::Field("roles".to_string()),
+            _ => return None,
+        })
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "name" => Self::Name,
+            "input" => Self::Input,
+            "pattern" => Self::Pattern,
+            "compact" => Self::Compact,
+            "line" => Self::Line,
+            "end" => Self::End,
+            "kind" => Self::KindName,
+            "language" => Self::Language,
+            "scope" => Self::Scope,
+            "scopeKind" => Self::ScopeKind,
+            _ => Self::Field(name.to_string()),
+        }
+    }
+
+    fn value<'a>(&self, tag: &'a Tag, record: &'a TagRecord) -> Option<Cow<'a, str>> {
+        let number = |n: Option<u32>| n.map(|n| Cow::Owned(n.to_string()));
+        match self {
+            Self::Name => Some(Cow::Borrowed(&record.name)),
+            Self::Input => Some(Cow::Borrowed(&record.path)),
+            Self::Pattern => {
+                let address = tag.address.trim_end_matches('\t');
+                Some(Cow::Borrowed(
+                    address.strip_suffix(";\"").unwrap_or(address),
+                ))
+            }
+            Self::Compact => record.pattern.as_deref().map(|pattern| {
+                Cow::Owned(pattern.split_whitespace().collect::<Vec<_>>().join(" "))
+            }),
+            Self::Line => number(record.line),
+            Self::End => number(record.end_line),
+            Self::KindLetter => record.kind.as_deref().map(Cow::Borrowed),
+            Self::KindName => record
+                .kind_name
+                .as_deref()
+                .or(record.kind.as_deref())
+                .map(Cow::Borrowed),
+            Self::Language => record.language.as_deref().map(Cow::Borrowed),
+            Self::Scope => record
+                .scope
+                .as_ref()
+                .map(|scope| Cow::Borrowed(scope.name.as_str())),
+            Self::ScopeKind => record
+                .scope
+                .as_ref()
+                .map(|scope| Cow::Borrowed(scope.kind.as_str())),
+            Self::Field(name) => record.field(name).map(Cow::Borrowed),
+        }
+    }
+
+    /// The `--fields` name that makes tags carry this column, if any.
+    fn field_name(&self) -> Option<&str> {
+        match self {
+            Self::Line => Some("line"),
+            Self::End => Some("end"),
+            Self::Scope | Self::ScopeKind => Some("scope"),
+            Self::Field(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum XrefPart {
+    Text(String),
+    Column {
+        column: XrefColumn,
+        width: usize,
+        left: bool,
+    },
+}
+
+/// The line layout of `-x` output, parsed from a `--xformat` string.
+///
+/// The string is copied as is except for `%` conversions: `%N` and the other
+/// field letters, `%{NAME}` for a field by name, and `%%` for a literal `%`.
+/// A width pads the value with spaces, on the left as in `%4n` or on the
+/// right as in `%-16N`. Missing values print as `-`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XrefFormat {
+    parts: Vec<XrefPart>,
+}
+
+impl XrefFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                text.push(ch);
+                continue;
+            }
+            if chars.next_if_eq(&'%').is_some() {
+                text.push('%');
+                continue;
+            }
+            let left = chars.next_if_eq(&'-').is_some();
+            let mut width = 0usize;
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                width = width
+                    .saturating_mul(10)
+                    .saturating_add(digit as usize - '0' as usize);
+            }
+            let column = match chars.next() {
+                Some('{') => {
+                    let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if name.is_empty() {
+                        return Err(format!("empty field name in `{format}`"));
+                    }
+                    XrefColumn::from_name(&name)
+                }
+                Some(letter) => XrefColumn::from_letter(letter)
+                    .ok_or_else(|| format!("unknown field letter `{letter}` in `{format}`"))?,
+                None => return Err(format!("`{format}` ends inside a `%` conversion")),
+            };
+            if !text.is_empty() {
+                parts.push(XrefPart::Text(std::mem::take(&mut text)));
+            }
+            parts.push(XrefPart::Column {
+                column,
+                width,
+                left,
+            });
+        }
+        if !text.is_empty() {
+            parts.push(XrefPart::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The `--fields` names the columns need enabled.
+    pub fn field_names(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            XrefPart::Column { column, .. } => column.field_name(),
+            XrefPart::Text(_) => None,
+        })
+    }
+
+    /// Appends the line for `tag`, whose record is `record`, to `out`.
+    pub fn format_into(&self, tag: &Tag, record: &TagRecord, out: &mut String) {
+        use std::fmt::Write;
+        for part in &self.parts {
+            match part {
+                XrefPart::Text(text) => out.push_str(text),
+                XrefPart::Column {
+                    column,
+                    width,
+                    left,
+                } => {
+                    let value = column.value(tag, record);
+                    let value = value.as_deref().unwrap_or("-");
+                    let _ = if *left {
+                        write!(out, "{value:<width$}")
+                    } else {
+                        write!(out, "{value:>width$}")
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl Default for XrefFormat {
+    fn default() -> Self {
+        Self::parse(DEFAULT_XREF_FORMAT).expect("default xref format parses")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag_record::Scope;
+
+    fn area() -> (Tag, TagRecord) {
+        let mut record = TagRecord::new("area", "src/shapes.rs");
+        record.line = Some(12);
+        record.end_line = Some(14);
+        record.kind = Some("P".to_string());
+        record.pattern = Some("    pub fn   area(&self) -> f64 {".to_string());
+        record.scope = Some(Scope::new("implementation", "Circle"));
+        record.fields = vec![("signature".to_string(), "(&self) -> f64".to_string())];
+        let tag = Tag::from(&record);
+        record.kind_name = Some("method".to_string());
+        (tag, record)
+    }
+
+    fn format(format: &str) -> String {
+        let (tag, record) = area();
+        let mut out = String::new();
+        XrefFormat::parse(format)
+            .unwrap()
+            .format_into(&tag, &record, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_default_xref_format() {
+        assert_eq!(
+            format(DEFAULT_XREF_FORMAT),
+            "area             method       12 src/shapes.rs    pub fn area(&self) -> f64 {"
+        );
+    }
+
+    #[test]
+    fn test_xref_format_fields_and_errors() {
+        assert_eq!(
+            format("%k|%s|%p|%S|%{signature}|%{end}|%a|100%%"),
+            "P|Circle|implementation|(&self) -> f64|(&self) -> f64|14|-|100%"
+        );
+        assert_eq!(format("%P"), "/^    pub fn   area(&self) -> f64 {$/");
+        assert_eq!(format("[%-6e][%6l]"), "[14    ][     -]");
+
+        let fields: Vec<_> = XrefFormat::parse("%N %n %s %{roles}")
+            .unwrap()
+            .field_names()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(fields, ["line", "scope", "roles"]);
+
+        assert!(XrefFormat::parse("%q").unwrap_err().contains("`q`"));
+        assert!(XrefFormat::parse("%{}").is_err());
+        assert!(XrefFormat::parse("trailing %-4").is_err());
+    }
 }
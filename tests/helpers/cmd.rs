@@ -0,0 +1,16 @@
+use assert_cmd::Command;
+use std::path::Path;
+
+/// A `treetags` command run in `working_dir` with an empty plugins directory
+/// and user config, so the user's installed plugins and
+/// ~/.config/treetags/config.toml never affect a test.
+pub fn treetags_cmd(working_dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("treetags").expect("treetags binary should build");
+    cmd.current_dir(working_dir)
+        .args(["--plugins-dir", env!("TREETAGS_TEST_EMPTY_PLUGINS_DIR")])
+        .args([
+            "--user-languages-config",
+            env!("TREETAGS_TEST_EMPTY_USER_CONFIG"),
+        ]);
+    cmd
+}
@@ -0,0 +1,139 @@
+//! Integration tests for the `--interactive` JSON request mode.
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+fn run_session(dir: &std::path::Path, requests: &[&str]) -> Vec<Value> {
+    let output = treetags_cmd(dir)
+        .arg("--interactive")
+        .write_stdin(requests.join("\n"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_interactive_requests_share_one_session() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("shapes.py"), "class Circle:\n    pass\n").unwrap();
+
+    let lines = run_session(
+        dir.path(),
+        &[
+            r#"{"command": "generate", "filename": "shapes.py"}"#,
+            r#"{"command": "generate", "filename": "unsaved.py", "fields": "+n",
+                "content": "class Square:\n    def area(self):\n        pass\n"}"#
+                .replace('\n', " ")
+                .as_str(),
+            r#"{"command": "generate", "filename": "unsaved.py", "kinds": {"python": "c"},
+                "content": "class Square:\n    def area(self):\n        pass\n"}"#
+                .replace('\n', " ")
+                .as_str(),
+        ],
+    );
+
+    let summary: Vec<String> = lines
+        .iter()
+        .map(|line| match line["_type"].as_str().unwrap() {
+            "tag" => format!(
+                "tag {} {} {}",
+                line["name"].as_str().unwrap(),
+                line["path"].as_str().unwrap(),
+                line["line"]
+            ),
+            other => other.to_string(),
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            "program",
+            "tag Circle shapes.py null",
+            "completed",
+            "tag Square unsaved.py 1",
+            "tag area unsaved.py 2",
+            "completed",
+            "tag Square unsaved.py null",
+            "completed",
+        ]
+    );
+    assert_eq!(lines[1]["kind_name"], "classes");
+    assert_eq!(lines[1]["language"], "python");
+}
+
+#[test]
+fn test_interactive_kinds_apply_per_request() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("shapes.py"),
+        "class Circle:\n    def area(self):\n        pass\n\ndef main():\n    pass\n",
+    )
+    .unwrap();
+
+    let lines = run_session(
+        dir.path(),
+        &[
+            r#"{"command": "generate", "filename": "shapes.py", "kinds": {"python": "c"}}"#,
+            r#"{"command": "generate", "filename": "shapes.py", "kinds": {"python": "fm"}}"#,
+            r#"{"command": "generate", "filename": "shapes.py", "kinds": {"python": "c"}}"#,
+        ],
+    );
+
+    let summary: Vec<String> = lines
+        .iter()
+        .map(|line| match line["_type"].as_str().unwrap() {
+            "tag" => format!(
+                "{} {} {}",
+                line["name"].as_str().unwrap(),
+                line["kind_name"].as_str().unwrap(),
+                line["language"].as_str().unwrap()
+            ),
+            other => other.to_string(),
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            "program",
+            "Circle classes python",
+            "completed",
+            "area class members python",
+            "main function python",
+            "completed",
+            "Circle classes python",
+            "completed",
+        ]
+    );
+}
+
+#[test]
+fn test_interactive_errors_do_not_end_the_session() {
+    let dir = TempDir::new().unwrap();
+
+    let lines = run_session(
+        dir.path(),
+        &[
+            r#"{"command": "generate", "filename": "missing.py"}"#,
+            "not json",
+            r#"{"command": "explode"}"#,
+            r#"{"command": "generate", "filename": "a.py", "content": "x = 1\n"}"#,
+        ],
+    );
+
+    let types: Vec<_> = lines.iter().map(|l| l["_type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        ["program", "error", "error", "error", "tag", "completed"]
+    );
+    assert_eq!(lines[3]["message"], "unknown command `explode`");
+}
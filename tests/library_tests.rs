@@ -0,0 +1,69 @@
+//! Integration tests for the library entry points: `Config::builder`,
+//! `treetags::generate` and `treetags::generate_source`.
+
+use std::fs;
+use tempfile::TempDir;
+use treetags::Config;
+
+fn write_sources(dir: &TempDir) {
+    fs::write(
+        dir.path().join("shapes.py"),
+        "class Circle:\n    def area(self):\n        pass\n\ndef unit():\n    pass\n",
+    )
+    .unwrap();
+    fs::create_dir(dir.path().join("vendor")).unwrap();
+    fs::write(
+        dir.path().join("vendor/lib.py"),
+        "def vendored():\n    pass\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_generate_walks_directories_and_sorts() {
+    let dir = TempDir::new().unwrap();
+    write_sources(&dir);
+    let root = dir.path().to_string_lossy().into_owned();
+
+    let config = Config::builder().build();
+    let names: Vec<String> = treetags::generate(&[&root], &config)
+        .map(|tag| tag.name)
+        .collect();
+
+    assert_eq!(names, ["Circle", "area", "unit", "vendored"]);
+}
+
+#[test]
+fn test_generate_applies_builder_settings() {
+    let dir = TempDir::new().unwrap();
+    write_sources(&dir);
+    let root = dir.path().to_string_lossy().into_owned();
+
+    let config = Config::builder()
+        .kinds("python", "c")
+        .fields("+n")
+        .exclude("vendor")
+        .build();
+    let tags: Vec<_> = treetags::generate(&[&root], &config).collect();
+
+    assert_eq!(tags.len(), 1, "unexpected tags: {tags:?}");
+    assert_eq!(tags[0].name, "Circle");
+    assert_eq!(
+        &*tags[0].file_name,
+        dir.path().join("shapes.py").to_string_lossy()
+    );
+    let fields = tags[0].extension_fields.as_ref().unwrap();
+    assert_eq!(fields.get("line"), Some("1"));
+}
+
+#[test]
+fn test_generate_source_uses_virtual_path() {
+    let config = Config::builder().build();
+    let tags: Vec<_> =
+        treetags::generate_source("unsaved/buffer.py", b"def draft():\n    pass\n", &config)
+            .collect();
+
+    assert_eq!(tags.len(), 1, "unexpected tags: {tags:?}");
+    assert_eq!(tags[0].name, "draft");
+    assert_eq!(&*tags[0].file_name, "unsaved/buffer.py");
+}
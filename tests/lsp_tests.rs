@@ -0,0 +1,117 @@
+//! Integration tests for `treetags lsp`, driving a session over stdio.
+
+#![cfg(feature = "lsp")]
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use serde_json::{json, Value};
+use std::fs;
+use tempfile::TempDir;
+
+fn frame(message: Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+/// Splits the server's output into its JSON messages.
+fn messages(stdout: &[u8]) -> Vec<Value> {
+    let stdout = String::from_utf8(stdout.to_vec()).unwrap();
+    stdout
+        .split("Content-Length: ")
+        .filter(|chunk| !chunk.is_empty())
+        .map(|chunk| {
+            let (_, body) = chunk.split_once("\r\n\r\n").unwrap();
+            serde_json::from_str(body).unwrap()
+        })
+        .collect()
+}
+
+fn response(messages: &[Value], id: u64) -> &Value {
+    &messages
+        .iter()
+        .find(|m| m["id"] == id)
+        .unwrap_or_else(|| panic!("no response to request {id}"))["result"]
+}
+
+#[test]
+fn test_lsp_session() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("lib.rs"), "pub fn area() {}\n").unwrap();
+    fs::write(root.join("shapes.rs"), "struct Circle;\n").unwrap();
+    let lib = format!("file://{}", root.join("lib.rs").display());
+    let shapes = format!("file://{}", root.join("shapes.rs").display());
+
+    // The open buffer of shapes.rs differs from the file on disk.
+    let buffer = "struct Circle;\nimpl Circle {\n    fn radius() {\n        area();\n    }\n}\n";
+    let session = [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"capabilities": {}, "rootUri": format!("file://{}", root.display())}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument":
+            {"uri": shapes, "languageId": "rust", "version": 1, "text": buffer}}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/documentSymbol",
+            "params": {"textDocument": {"uri": shapes}}}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/definition",
+            "params": {"textDocument": {"uri": shapes}, "position": {"line": 3, "character": 10}}}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "workspace/symbol",
+            "params": {"query": "rad"}}),
+        json!({"jsonrpc": "2.0", "id": 5, "method": "textDocument/foldingRange",
+            "params": {"textDocument": {"uri": shapes}}}),
+        json!({"jsonrpc": "2.0", "id": 6, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ];
+    let input: String = session.into_iter().map(frame).collect();
+
+    let output = treetags_cmd(&root)
+        .arg("lsp")
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let messages = messages(&output.stdout);
+
+    let capabilities = &response(&messages, 1)["capabilities"];
+    assert_eq!(capabilities["documentSymbolProvider"], true);
+
+    let symbols = response(&messages, 2).as_array().unwrap();
+    let tree: Vec<_> = symbols
+        .iter()
+        .map(|s| {
+            let children: Vec<_> = s["children"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c["name"].as_str().unwrap())
+                .collect();
+            (s["name"].as_str().unwrap(), children)
+        })
+        .collect();
+    assert_eq!(tree, [("Circle", vec![]), ("Circle", vec!["radius"])]);
+
+    assert_eq!(
+        response(&messages, 3),
+        &json!([{"uri": lib, "range": {
+            "start": {"line": 0, "character": 7},
+            "end": {"line": 0, "character": 11}}}])
+    );
+
+    let found = response(&messages, 4).as_array().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["name"], "radius");
+    assert_eq!(found[0]["location"]["uri"], shapes.as_str());
+
+    assert_eq!(
+        response(&messages, 5),
+        &json!([
+            {"startLine": 1, "endLine": 5},
+            {"startLine": 2, "endLine": 4}
+        ])
+    );
+}
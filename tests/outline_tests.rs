@@ -0,0 +1,57 @@
+//! Integration tests for `treetags outline`.
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use std::fs;
+use tempfile::TempDir;
+
+const SHAPES: &str = "pub struct Circle {
+    radius: f64,
+}
+
+impl Circle {
+    pub fn area(&self) -> f64 {
+        3.14 * self.radius * self.radius
+    }
+}
+";
+
+#[test]
+fn test_outline_prints_nested_text() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("shapes.rs"), SHAPES).unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["outline", "shapes.rs"])
+        .assert()
+        .success()
+        .stdout(
+            "struct Circle  1-3\n\
+             \x20 field radius  2\n\
+             impl Circle  5-9\n\
+             \x20 method area(&self) -> f64  6-8\n",
+        );
+    assert!(!dir.path().join("tags").exists());
+}
+
+#[test]
+fn test_outline_json_nests_children() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("shapes.rs"), SHAPES).unwrap();
+
+    let output = treetags_cmd(dir.path())
+        .args(["outline", "--json", "shapes.rs"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let tree: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(tree.as_array().unwrap().len(), 2);
+    let area = &tree[1]["children"][0];
+    assert_eq!(area["name"], "area");
+    assert_eq!(area["kind_name"], "method");
+    assert_eq!(area["line"], 6);
+    assert_eq!(area["end_line"], 8);
+    assert_eq!(area["fields"]["signature"], "(&self) -> f64");
+}
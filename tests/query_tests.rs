@@ -0,0 +1,75 @@
+//! Integration tests for looking up tags with `treetags query`.
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use std::fs;
+use tempfile::TempDir;
+
+/// Tags a small Rust file and returns the directory holding it and `tags`.
+fn tagged_dir() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("shapes.rs"),
+        "struct Circle;\nfn area() {}\nfn area_of() {}\nmod inner {\n    fn area() {}\n}\n",
+    )
+    .unwrap();
+    treetags_cmd(dir.path()).arg("-R").assert().success();
+    dir
+}
+
+#[test]
+fn test_query_prints_matching_lines() {
+    let dir = tagged_dir();
+
+    let output = treetags_cmd(dir.path())
+        .args(["query", "area", "--scope", "module:inner"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "area\tshapes.rs\t/^    fn area() {}$/;\"\tf\tmodule:inner\n"
+    );
+
+    treetags_cmd(dir.path())
+        .args(["query", "missing"])
+        .assert()
+        .code(1)
+        .stdout("");
+}
+
+#[test]
+fn test_query_prefix_json_with_language() {
+    let dir = tagged_dir();
+
+    let output = treetags_cmd(dir.path())
+        .args(["query", "--prefix", "area", "--language", "Rust", "--json"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let names: Vec<_> = records
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                r["name"].as_str().unwrap(),
+                r["kind_name"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("area", "function"),
+            ("area", "function"),
+            ("area_of", "function")
+        ]
+    );
+}
@@ -0,0 +1,51 @@
+//! Integration tests for `--output-format=scip`.
+
+#![cfg(feature = "scip")]
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use std::fs;
+use tempfile::TempDir;
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+#[test]
+fn test_scip_index_is_written_to_index_scip() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("shapes.py"),
+        "class Circle:\n    # Area of the circle.\n    def area(self):\n        pass\n",
+    )
+    .unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["--output-format=scip", "-R"])
+        .assert()
+        .success();
+
+    assert!(!dir.path().join("tags").exists());
+    let index = fs::read(dir.path().join("index.scip")).unwrap();
+    assert!(contains(&index, "shapes.py"));
+    assert!(contains(&index, "treetags . python . Circle#"));
+    assert!(contains(&index, "treetags . python . Circle#area."));
+    assert!(contains(&index, "Area of the circle."));
+}
+
+#[test]
+fn test_scip_rejects_append() {
+    let dir = TempDir::new().unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["--output-format=scip", "--append", "-R"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--append only works with --output-format=ctags",
+        ));
+}
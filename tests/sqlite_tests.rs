@@ -0,0 +1,72 @@
+//! Integration tests for `--output-format=sqlite` and querying the database.
+
+#![cfg(feature = "sqlite")]
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use std::fs;
+use tempfile::TempDir;
+
+fn query(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = treetags_cmd(dir)
+        .args(["query", "-t", "tags.db"])
+        .args(args)
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_sqlite_database_is_updated_per_file() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.rs"), "fn area() {}\nfn shared() {}\n").unwrap();
+    fs::write(dir.path().join("b.rs"), "fn shared() {}\n").unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["--output-format=sqlite", "-R"])
+        .assert()
+        .success();
+    assert!(!dir.path().join("tags").exists());
+
+    let found = query(dir.path(), &["shared"]);
+    assert!(found.starts_with("shared\ta.rs\t/^fn shared() {}$/;\"\tf"));
+    assert_eq!(found.lines().count(), 2);
+    let json = query(dir.path(), &["area", "--json"]);
+    assert!(json.contains("\"language\": \"rust\""));
+    assert!(json.contains("\"kind_name\": \"function\""));
+
+    // Retag after one file changes and the other is removed.
+    fs::write(dir.path().join("a.rs"), "fn perimeter() {}\n").unwrap();
+    fs::remove_file(dir.path().join("b.rs")).unwrap();
+    treetags_cmd(dir.path())
+        .args(["--output-format=sqlite", "-R"])
+        .assert()
+        .success();
+
+    assert_eq!(query(dir.path(), &["shared"]), "");
+    assert_eq!(query(dir.path(), &["area"]), "");
+    assert!(query(dir.path(), &["per", "--prefix"]).starts_with("perimeter\ta.rs\t"));
+}
+
+#[test]
+fn test_sqlite_append_keeps_other_files() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.rs"), "fn area() {}\n").unwrap();
+    fs::write(dir.path().join("b.rs"), "fn shared() {}\n").unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["--output-format=sqlite", "-R"])
+        .assert()
+        .success();
+    fs::write(dir.path().join("b.rs"), "fn other() {}\n").unwrap();
+    treetags_cmd(dir.path())
+        .args(["--output-format=sqlite", "--append", "b.rs"])
+        .assert()
+        .success();
+
+    assert!(query(dir.path(), &["area"]).starts_with("area\ta.rs\t"));
+    assert!(query(dir.path(), &["other"]).starts_with("other\tb.rs\t"));
+    assert_eq!(query(dir.path(), &["shared"]), "");
+}
@@ -0,0 +1,65 @@
+//! Integration tests for tagging stdin with `--stdin-filename`.
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use tempfile::TempDir;
+
+#[test]
+fn test_stdin_is_tagged_as_the_virtual_file() {
+    let dir = TempDir::new().unwrap();
+
+    let output = treetags_cmd(dir.path())
+        .args(["--stdin-filename=src/shapes.py", "-f", "-", "-"])
+        .write_stdin("class Circle:\n    pass\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("Circle\tsrc/shapes.py\t"),
+        "expected a tag attributed to the virtual path:\n{stdout}"
+    );
+}
+
+#[test]
+fn test_stdin_language_from_content() {
+    let dir = TempDir::new().unwrap();
+
+    let output = treetags_cmd(dir.path())
+        .args(["-G", "--stdin-filename", "build-script", "-f", "-", "-"])
+        .write_stdin("#!/usr/bin/env python3\ndef build():\n    pass\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("build\tbuild-script\t"),
+        "expected the shebang to select python:\n{stdout}"
+    );
+}
+
+#[test]
+fn test_stdin_filename_rejects_file_names() {
+    let dir = TempDir::new().unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["--stdin-filename=a.py", "-f", "-", "b.py"])
+        .write_stdin("")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_dash_without_stdin_filename_fails() {
+    let dir = TempDir::new().unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["-f", "-", "-"])
+        .write_stdin("def f():\n    pass\n")
+        .assert()
+        .failure();
+}
@@ -0,0 +1,21 @@
+/// A circle centred on the origin.
+pub struct Circle {
+    pub radius: f64,
+}
+
+impl Circle {
+    /// Area enclosed by the circle.
+    pub fn area(&self) -> f64 {
+        std::f64::consts::PI * self.radius * self.radius
+    }
+
+    pub fn perimeter(&self) -> f64 {
+        2.0 * std::f64::consts::PI * self.radius
+    }
+}
+
+pub fn area_sum(circles: &[Circle]) -> f64 {
+    circles.iter().map(Circle::area).sum()
+}
+
+pub const UNIT: Circle = Circle { radius: 1.0 };
@@ -0,0 +1,60 @@
+//! Integration tests for `-x` / `--output-format=xref`.
+
+#[path = "helpers/cmd.rs"]
+mod cmd;
+
+use cmd::treetags_cmd;
+use std::fs;
+use tempfile::TempDir;
+
+const SHAPES: &str =
+    "struct Circle;\n\nimpl Circle {\n    fn area(&self) -> f64 {\n        0.0\n    }\n}\n";
+
+#[test]
+fn test_xref_prints_table_to_stdout() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("shapes.rs"), SHAPES).unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["-x", "shapes.rs"])
+        .assert()
+        .success()
+        .stdout(
+            "Circle           impl          3 shapes.rs        impl Circle {\n\
+             Circle           struct        1 shapes.rs        struct Circle;\n\
+             area             method        4 shapes.rs        fn area(&self) -> f64 {\n",
+        );
+    assert!(!dir.path().join("tags").exists());
+}
+
+#[test]
+fn test_xref_custom_format_enables_fields() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("shapes.rs"), SHAPES).unwrap();
+
+    treetags_cmd(dir.path())
+        .args([
+            "--output-format=xref",
+            "--xformat",
+            "%N\t%k\t%n-%e\t%{signature}\t%s",
+            "-f",
+            "xref.txt",
+            "shapes.rs",
+        ])
+        .assert()
+        .success()
+        .stdout("");
+    let table = fs::read_to_string(dir.path().join("xref.txt")).unwrap();
+    assert!(table.contains("area\tP\t4-6\t(&self) -> f64\tCircle\n"));
+}
+
+#[test]
+fn test_xref_rejects_unknown_field_letter() {
+    let dir = TempDir::new().unwrap();
+
+    treetags_cmd(dir.path())
+        .args(["-x", "--xformat", "%Q"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown field letter `Q`"));
+}